@@ -0,0 +1,354 @@
+// src/audio_output.rs
+// Senza la feature `audio` non si apre nessun dispositivo: il synth
+// si usa comunque per il rendering su WAV.
+#![cfg_attr(not(feature = "audio"), allow(dead_code))]
+use crate::midi_loader::Song;
+use crate::recording::{DecodedAudio, PlaybackState, RecordingPlayer};
+use crate::sequencer::Sequencer;
+use crate::synth::{Instrument, SynthEvent};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, mpsc};
+
+pub const WAV_SAMPLE_RATE: u32 = 44_100;
+// Secondi di coda dopo l'ultima nota per lasciar spegnere il suono
+pub const TAIL_SECS: f32 = 2.0;
+
+// Messaggi dal thread principale al thread audio
+enum AudioCommand {
+    Event(SynthEvent),
+    SetInstrument(Box<dyn Instrument>),
+    SetRecording(Option<RecordingPlayer>),
+    SeekRecording(f32),
+    SetRecordingOffset(f32),
+    SetPlayback(PlaybackState),
+}
+
+// Uscita audio in tempo reale: lo strumento vive nel thread audio di cpal e
+// riceve gli eventi dal thread principale attraverso un canale.
+pub struct AudioOutput {
+    sender: Option<mpsc::Sender<AudioCommand>>,
+    sample_rate: u32,
+    // Posizione nel brano della registrazione, scritta dal thread audio
+    recording_position: Arc<AtomicU64>,
+    recording_attached: bool,
+    // Salti della registrazione chiesti al thread audio e quelli che ha già
+    // eseguito: finché non coincidono la posizione letta è quella vecchia
+    recording_seeks_sent: u64,
+    recording_seeks_done: Arc<AtomicU64>,
+    #[cfg(feature = "audio")]
+    _stream: Option<cpal::Stream>,
+    pub error: Option<String>,
+}
+
+impl AudioOutput {
+    pub fn new() -> Self {
+        match Self::start() {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("[ATTENZIONE] Audio non disponibile: {}", e);
+                Self {
+                    sender: None,
+                    sample_rate: WAV_SAMPLE_RATE,
+                    recording_position: Arc::new(AtomicU64::new(0)),
+                    recording_attached: false,
+                    recording_seeks_sent: 0,
+                    recording_seeks_done: Arc::new(AtomicU64::new(0)),
+                    #[cfg(feature = "audio")]
+                    _stream: None,
+                    error: Some(e),
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    fn start() -> Result<Self, String> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or("Nessun dispositivo di uscita audio")?;
+        let supported = device.default_output_config().map_err(|e| e.to_string())?;
+        let sample_format = supported.sample_format();
+        let config: cpal::StreamConfig = supported.into();
+
+        let (sender, receiver) = mpsc::channel();
+        let seeks_done = Arc::new(AtomicU64::new(0));
+        let done = seeks_done.clone();
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, receiver, done),
+            cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, receiver, done),
+            cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, receiver, done),
+            other => Err(format!("Formato audio non supportato: {:?}", other)),
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        println!(
+            "[INFO] Audio: {} Hz, {} canali",
+            config.sample_rate.0, config.channels
+        );
+        Ok(Self {
+            sender: Some(sender),
+            sample_rate: config.sample_rate.0,
+            recording_position: Arc::new(AtomicU64::new(0)),
+            recording_attached: false,
+            recording_seeks_sent: 0,
+            recording_seeks_done: seeks_done,
+            _stream: Some(stream),
+            error: None,
+        })
+    }
+
+    #[cfg(not(feature = "audio"))]
+    fn start() -> Result<Self, String> {
+        Err("Supporto audio non compilato (abilitare la feature `audio`)".to_string())
+    }
+
+    // Frequenza di campionamento del dispositivo, per creare strumenti compatibili
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn send(&self, event: SynthEvent) {
+        self.send_command(AudioCommand::Event(event));
+    }
+
+    // Sostituisce lo strumento che suona nel thread audio
+    pub fn set_instrument(&self, instrument: Box<dyn Instrument>) {
+        self.send_command(AudioCommand::SetInstrument(instrument));
+    }
+
+    // Aggancia una registrazione al brano; parte ferma, all'inizio
+    pub fn attach_recording(&mut self, audio: Arc<DecodedAudio>, offset_secs: f32) {
+        if self.sender.is_none() {
+            return;
+        }
+        let player = RecordingPlayer::new(
+            audio,
+            offset_secs,
+            self.sample_rate,
+            self.recording_position.clone(),
+        );
+        self.send_command(AudioCommand::SetRecording(Some(player)));
+        self.recording_seeks_sent += 1;
+        self.recording_attached = true;
+    }
+
+    pub fn detach_recording(&mut self) {
+        self.send_command(AudioCommand::SetRecording(None));
+        self.recording_seeks_sent += 1;
+        self.recording_attached = false;
+    }
+
+    pub fn seek_recording(&mut self, song_position_secs: f32) {
+        if self.sender.is_none() {
+            return;
+        }
+        self.send_command(AudioCommand::SeekRecording(song_position_secs));
+        self.recording_seeks_sent += 1;
+    }
+
+    pub fn set_recording_offset(&self, offset_secs: f32) {
+        self.send_command(AudioCommand::SetRecordingOffset(offset_secs));
+    }
+
+    pub fn set_playback(&self, state: PlaybackState) {
+        self.send_command(AudioCommand::SetPlayback(state));
+    }
+
+    // Posizione nel brano secondo la registrazione, se ce n'è una agganciata.
+    // None anche mentre il thread audio non ha ancora eseguito l'ultimo salto:
+    // la posizione vecchia riporterebbe indietro il trasporto.
+    pub fn recording_position(&self) -> Option<f32> {
+        let settled =
+            self.recording_seeks_done.load(Ordering::Acquire) == self.recording_seeks_sent;
+        (self.recording_attached && settled)
+            .then(|| f64::from_bits(self.recording_position.load(Ordering::Relaxed)) as f32)
+    }
+
+    fn send_command(&self, command: AudioCommand) {
+        if let Some(sender) = &self.sender {
+            // Se il thread audio è morto non c'è nulla da fare
+            let _ = sender.send(command);
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    receiver: mpsc::Receiver<AudioCommand>,
+    seeks_done: Arc<AtomicU64>,
+) -> Result<cpal::Stream, String>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    use cpal::traits::DeviceTrait;
+
+    let channels = config.channels as usize;
+    let mut instrument: Box<dyn Instrument> =
+        Box::new(crate::synth::AdditiveSynth::new(config.sample_rate.0));
+    let mut recording: Option<RecordingPlayer> = None;
+    let mut playback = None;
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                let mut seeks = 0;
+                while let Ok(command) = receiver.try_recv() {
+                    match command {
+                        AudioCommand::Event(event) => instrument.handle_event(event),
+                        AudioCommand::SetInstrument(new_instrument) => instrument = new_instrument,
+                        AudioCommand::SetRecording(mut new_recording) => {
+                            if let (Some(player), Some(state)) = (&mut new_recording, playback) {
+                                player.set_state(state);
+                            }
+                            recording = new_recording;
+                            seeks += 1;
+                        }
+                        AudioCommand::SeekRecording(position) => {
+                            if let Some(player) = &mut recording {
+                                player.seek(position);
+                            }
+                            seeks += 1;
+                        }
+                        AudioCommand::SetRecordingOffset(offset) => {
+                            if let Some(player) = &mut recording {
+                                player.set_offset(offset);
+                            }
+                        }
+                        AudioCommand::SetPlayback(state) => {
+                            playback = Some(state);
+                            if let Some(player) = &mut recording {
+                                player.set_state(state);
+                            }
+                        }
+                    }
+                }
+                let frames = data.len() / channels;
+                left.resize(frames, 0.0);
+                right.resize(frames, 0.0);
+                instrument.render(&mut left, &mut right);
+                if let Some(player) = &mut recording {
+                    player.mix_into(&mut left, &mut right);
+                }
+                // Solo ora la posizione scritta tiene conto dei salti
+                if seeks > 0 {
+                    seeks_done.fetch_add(seeks, Ordering::Release);
+                }
+                for (i, frame) in data.chunks_mut(channels).enumerate() {
+                    for (c, sample) in frame.iter_mut().enumerate() {
+                        // Mono: media dei due canali; più di due: i primi due, poi silenzio
+                        let value = match (channels, c) {
+                            (1, _) => 0.5 * (left[i] + right[i]),
+                            (_, 0) => left[i],
+                            (_, 1) => right[i],
+                            _ => 0.0,
+                        };
+                        *sample = T::from_sample(value);
+                    }
+                }
+            },
+            |e| eprintln!("[ATTENZIONE] Errore stream audio: {}", e),
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
+
+// Suona l'intero brano offline con `instrument` (creato a `WAV_SAMPLE_RATE`)
+// e lo scrive in un WAV stereo a 16 bit. Gli eventi cadono sul campione
+// esatto, indipendentemente dai blocchi.
+pub fn render_song_to_wav(
+    song: &Song,
+    path: &Path,
+    instrument: &mut dyn Instrument,
+) -> Result<(), String> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: WAV_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).map_err(|e| e.to_string())?;
+
+    let mut sequencer = Sequencer::new(song);
+
+    let total_samples = ((song.end_secs() + TAIL_SECS) * WAV_SAMPLE_RATE as f32) as usize;
+
+    const BLOCK: usize = 512;
+    let mut left = [0.0f32; BLOCK];
+    let mut right = [0.0f32; BLOCK];
+    let mut rendered = 0;
+    while rendered < total_samples {
+        let block_len = BLOCK.min(total_samples - rendered);
+        let block_end_secs = (rendered + block_len) as f32 / WAV_SAMPLE_RATE as f32;
+
+        let mut offset = 0;
+        let mut pending = Vec::new();
+        sequencer.advance(block_end_secs, |event| pending.push(event));
+        for event in pending {
+            let event_sample = (event.time_secs * WAV_SAMPLE_RATE as f32) as usize;
+            let split = event_sample
+                .saturating_sub(rendered)
+                .clamp(offset, block_len);
+            instrument.render(&mut left[offset..split], &mut right[offset..split]);
+            offset = split;
+            instrument.handle_event(event.event);
+        }
+        instrument.render(&mut left[offset..block_len], &mut right[offset..block_len]);
+
+        for (&l, &r) in left[..block_len].iter().zip(&right[..block_len]) {
+            for value in [l, r] {
+                let value = value.clamp(-1.0, 1.0);
+                writer
+                    .write_sample((value * i16::MAX as f32) as i16)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        rendered += block_len;
+    }
+
+    writer.finalize().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::AdditiveSynth;
+
+    #[test]
+    fn song_renders_to_wav_with_sound_where_the_notes_are() {
+        let song = Song::demo();
+        let path = std::env::temp_dir().join(format!("pv-render-{}.wav", std::process::id()));
+        let mut instrument = AdditiveSynth::new(WAV_SAMPLE_RATE);
+        render_song_to_wav(&song, &path, &mut instrument).unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        assert_eq!((spec.channels, spec.sample_rate), (2, WAV_SAMPLE_RATE));
+        let expected_frames = ((song.end_secs() + TAIL_SECS) * WAV_SAMPLE_RATE as f32) as u32;
+        assert_eq!(reader.duration(), expected_frames);
+
+        let samples: Vec<i16> = reader.samples::<i16>().map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+        // Picco per ogni mezzo secondo (campioni stereo intrecciati)
+        let peak = |from_secs: f32, to_secs: f32| {
+            let frame = |secs: f32| (secs * WAV_SAMPLE_RATE as f32) as usize * 2;
+            samples[frame(from_secs)..frame(to_secs)]
+                .iter()
+                .map(|s| s.unsigned_abs())
+                .max()
+                .unwrap_or(0)
+        };
+        let first_note = song.notes[0].start_time_secs;
+        // Silenzio prima della prima nota, suono subito dopo
+        assert_eq!(peak(0.0, first_note - 0.1), 0);
+        assert!(peak(first_note, first_note + 0.5) > 1000);
+    }
+}
@@ -0,0 +1,425 @@
+// src/cli.rs
+// Riga di comando: `play` (il default, con la finestra) e i comandi senza
+// finestra `export`, `info` e `thumbnail`. Qui si leggono e si controllano
+// gli argomenti; cosa farne lo decide main.
+use crate::layout::KeyRange;
+use crate::piano_roll::{Orientation, PianoRollSettings};
+use crate::scene;
+use crate::settings::{Settings, ViewSettings};
+use crate::thumbnail;
+use crate::video_export::VideoSettings;
+use egui::Color32;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Uso:
+  piano_visualizer [play] [brano.mid|lezione.pvs|scaletta.m3u] [opzioni]
+  piano_visualizer export <brano.mid> <uscita.mp4|frame.png> [opzioni]
+  piano_visualizer export <brano.mid> <roll.svg|roll.png> --roll [opzioni]
+  piano_visualizer info <brano.mid>
+  piano_visualizer thumbnail <brano.mid|cartella>... [--size 256x64]
+
+Aspetto (play ed export; senza opzioni valgono le impostazioni salvate):
+  --fall <secondi>        tempo di caduta delle note (0.5-10)
+  --left-color <RRGGBB>   colore della mano sinistra
+  --right-color <RRGGBB>  colore della mano destra
+  --keys <basso-alto>     tasti mostrati, es. 21-108 o Do2-Do7 (auto = attorno al Do3)
+
+Play:
+  --start <secondi>       posizione iniziale
+  --speed <fattore>       velocità di riproduzione (0.25-2)
+  --size <LxA>            dimensioni della finestra (default 800x600)
+  --fullscreen            a schermo intero
+
+Export video (.mp4, .mkv... con ffmpeg, oppure sequenza .png con il WAV):
+  --size <LxA>            risoluzione (default 1920x1080)
+  --fps <N>               fotogrammi al secondo (default 60)
+  --soundfont <file.sf2>  audio con una SoundFont invece del synth integrato
+
+Export piano roll (--roll, oppure uscita .svg):
+  --vertical              tastiera in basso invece che a sinistra
+  --bars <a-b>            solo le battute indicate, es. 5-12
+  --names                 nome della nota su ogni nota
+  --scale <px/s>          pixel per secondo (default 100)
+
+Generali:
+  --software              rendering senza GPU
+  -h, --help              mostra questo messaggio";
+
+// Opzioni che vogliono un valore, tutte le altre sono interruttori
+const VALUE_OPTIONS: [&str; 11] = [
+    "--fall",
+    "--left-color",
+    "--right-color",
+    "--keys",
+    "--start",
+    "--speed",
+    "--size",
+    "--fps",
+    "--soundfont",
+    "--bars",
+    "--scale",
+];
+const VIEW_OPTIONS: [&str; 4] = ["--fall", "--left-color", "--right-color", "--keys"];
+const VIDEO_OPTIONS: [&str; 3] = ["--size", "--fps", "--soundfont"];
+const ROLL_OPTIONS: [&str; 5] = ["--roll", "--vertical", "--bars", "--names", "--scale"];
+
+// Aspetto della visualizzazione, comune alla finestra e al video
+pub struct ViewOptions {
+    pub fall_duration_secs: f32,
+    pub color_left: Color32,
+    pub color_right: Color32,
+    pub key_range: Option<KeyRange>,
+}
+
+pub struct PlayOptions {
+    // None = note dimostrative
+    pub midi_path: Option<PathBuf>,
+    pub view: ViewOptions,
+    pub start_secs: f32,
+    pub speed: f32,
+    pub window_size: (u32, u32),
+    pub fullscreen: bool,
+}
+
+pub enum Command {
+    Play(PlayOptions),
+    ExportVideo {
+        midi_path: PathBuf,
+        output: PathBuf,
+        settings: VideoSettings,
+    },
+    ExportRoll {
+        midi_path: PathBuf,
+        output: PathBuf,
+        settings: PianoRollSettings,
+    },
+    Info {
+        midi_path: PathBuf,
+    },
+    Thumbnail {
+        inputs: Vec<PathBuf>,
+        width: u32,
+        height: u32,
+    },
+    Help,
+}
+
+pub struct Cli {
+    pub command: Command,
+    // Valido con tutti i comandi: niente GPU
+    pub software: bool,
+}
+
+// Gli argomenti di un comando, separati in posizionali e opzioni
+struct Arguments {
+    positionals: Vec<String>,
+    values: Vec<(String, String)>,
+    flags: Vec<String>,
+}
+
+impl Arguments {
+    fn split(command: &str, args: &[String], allowed: &[&str]) -> Result<Self, String> {
+        let mut arguments = Self {
+            positionals: Vec::new(),
+            values: Vec::new(),
+            flags: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                arguments.positionals.push(arg.clone());
+            } else if !allowed.contains(&arg.as_str()) {
+                return Err(format!("Opzione '{}' sconosciuta per '{}'", arg, command));
+            } else if VALUE_OPTIONS.contains(&arg.as_str()) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Manca il valore di {}", arg))?;
+                arguments.values.push((arg.clone(), value.clone()));
+            } else {
+                arguments.flags.push(arg.clone());
+            }
+        }
+        Ok(arguments)
+    }
+
+    // Errore se è stata data una delle opzioni, che qui non avrebbe effetto
+    fn reject(&self, options: &[&str], context: &str) -> Result<(), String> {
+        let given = self
+            .flags
+            .iter()
+            .chain(self.values.iter().map(|(option, _)| option))
+            .find(|option| options.contains(&option.as_str()));
+        match given {
+            Some(option) => Err(format!("{} non vale {}", option, context)),
+            None => Ok(()),
+        }
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    // Ultimo valore dato all'opzione
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Valore numerico fra `min` e `max` (compresi). NaN e infiniti, che f32
+    // accetta, non passerebbero il controllo dei limiti: vanno esclusi prima.
+    fn number<T: FromStr + PartialOrd + Copy + Into<f64> + std::fmt::Display>(
+        &self,
+        name: &str,
+        min: T,
+        max: T,
+    ) -> Result<Option<T>, String> {
+        let Some(value) = self.value(name) else {
+            return Ok(None);
+        };
+        let number = value
+            .parse::<T>()
+            .ok()
+            .filter(|number| (*number).into().is_finite())
+            .ok_or_else(|| format!("Valore di {} non valido: '{}'", name, value))?;
+        if number < min || number > max {
+            return Err(format!(
+                "Valore di {} fuori dai limiti ({}-{}): {}",
+                name, min, max, value
+            ));
+        }
+        Ok(Some(number))
+    }
+
+    fn size(&self, default: (u32, u32)) -> Result<(u32, u32), String> {
+        self.value("--size").map_or(Ok(default), parse_size)
+    }
+
+    // Senza opzioni valgono le impostazioni salvate dall'utente
+    fn view(&self, defaults: &ViewSettings) -> Result<ViewOptions, String> {
+        Ok(ViewOptions {
+            fall_duration_secs: self
+                .number("--fall", 0.5, 10.0)?
+                .unwrap_or(defaults.fall_duration_secs),
+            color_left: self
+                .value("--left-color")
+                .map_or(Ok(defaults.color_left_hand), parse_color)?,
+            color_right: self
+                .value("--right-color")
+                .map_or(Ok(defaults.color_right_hand), parse_color)?,
+            key_range: match self.value("--keys") {
+                Some("auto") => None,
+                Some(keys) => Some(KeyRange::parse(keys)?),
+                None => defaults.key_range,
+            },
+        })
+    }
+
+    // Esattamente `count` argomenti posizionali
+    fn expect_positionals(&self, count: usize, what: &str) -> Result<(), String> {
+        match self.positionals.len() {
+            n if n < count => Err(format!("Manca {}", what)),
+            n if n > count => Err(format!("Argomento inatteso '{}'", self.positionals[count])),
+            _ => Ok(()),
+        }
+    }
+}
+
+// "LxA" in pixel, es. 1920x1080
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    value
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h): &(u32, u32)| w > 0 && h > 0)
+        .ok_or_else(|| format!("Dimensione '{}' non valida (es. 1920x1080)", value))
+}
+
+// "RRGGBB", con o senza '#'
+pub fn parse_color(value: &str) -> Result<Color32, String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok(Color32::from_rgb(r, g, b)),
+        _ => Err(format!("Colore '{}' non valido (es. 0064FF)", value)),
+    }
+}
+
+// "3-8" oppure una battuta sola ("5")
+fn parse_bars(value: &str) -> Result<(u32, u32), String> {
+    let (first, last) = value.split_once('-').unwrap_or((value, value));
+    first
+        .parse()
+        .ok()
+        .zip(last.parse().ok())
+        .ok_or_else(|| format!("Battute '{}' non valide (es. 1-8)", value))
+}
+
+fn parse_play(args: &[String], defaults: &Settings) -> Result<Command, String> {
+    let allowed = [
+        VIEW_OPTIONS.as_slice(),
+        &["--start", "--speed", "--size", "--fullscreen"],
+    ]
+    .concat();
+    let arguments = Arguments::split("play", args, &allowed)?;
+    if let Some(extra) = arguments.positionals.get(1) {
+        return Err(format!("Argomento inatteso '{}'", extra));
+    }
+    Ok(Command::Play(PlayOptions {
+        midi_path: arguments.positionals.first().map(PathBuf::from),
+        view: arguments.view(&defaults.view)?,
+        start_secs: arguments.number("--start", 0.0, f32::MAX)?.unwrap_or(0.0),
+        speed: arguments
+            .number("--speed", 0.25, 2.0)?
+            .unwrap_or(defaults.playback.speed),
+        window_size: arguments.size((800, 600))?,
+        fullscreen: arguments.flag("--fullscreen"),
+    }))
+}
+
+fn parse_export(args: &[String], defaults: &Settings, software: bool) -> Result<Command, String> {
+    let allowed = [
+        VIEW_OPTIONS.as_slice(),
+        VIDEO_OPTIONS.as_slice(),
+        ROLL_OPTIONS.as_slice(),
+    ]
+    .concat();
+    let arguments = Arguments::split("export", args, &allowed)?;
+    arguments.expect_positionals(2, "il file MIDI o il file di uscita")?;
+    let midi_path = PathBuf::from(&arguments.positionals[0]);
+    let output = PathBuf::from(&arguments.positionals[1]);
+    let view = arguments.view(&defaults.view)?;
+
+    let is_svg = output
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+    if arguments.flag("--roll") || is_svg {
+        // Il piano roll sceglie da sé i tasti e non ha note che cadono
+        arguments.reject(&VIDEO_OPTIONS, "per il piano roll")?;
+        arguments.reject(&["--fall", "--keys"], "per il piano roll")?;
+        let settings = PianoRollSettings {
+            orientation: if arguments.flag("--vertical") {
+                Orientation::Vertical
+            } else {
+                Orientation::Horizontal
+            },
+            bars: arguments.value("--bars").map(parse_bars).transpose()?,
+            pixels_per_second: arguments
+                .number("--scale", 1.0, 10_000.0)?
+                .unwrap_or(PianoRollSettings::default().pixels_per_second),
+            note_names: arguments.flag("--names"),
+            color_left: scene::color_to_f32(view.color_left),
+            color_right: scene::color_to_f32(view.color_right),
+        };
+        return Ok(Command::ExportRoll {
+            midi_path,
+            output,
+            settings,
+        });
+    }
+
+    arguments.reject(&ROLL_OPTIONS[1..], "per il video (serve --roll)")?;
+    let defaults = VideoSettings::default();
+    let (width, height) = arguments.size((defaults.width, defaults.height))?;
+    let settings = VideoSettings {
+        width,
+        height,
+        fps: arguments.number("--fps", 1, 240)?.unwrap_or(defaults.fps),
+        fall_duration_secs: view.fall_duration_secs,
+        color_left: scene::color_to_f32(view.color_left),
+        color_right: scene::color_to_f32(view.color_right),
+        key_range: view.key_range,
+        soundfont: arguments.value("--soundfont").map(PathBuf::from),
+        software_rendering: software,
+    };
+    Ok(Command::ExportVideo {
+        midi_path,
+        output,
+        settings,
+    })
+}
+
+fn parse_thumbnail(args: &[String]) -> Result<Command, String> {
+    let arguments = Arguments::split("thumbnail", args, &["--size"])?;
+    if arguments.positionals.is_empty() {
+        return Err("Manca il file MIDI o la cartella".to_string());
+    }
+    let (width, height) =
+        arguments.size((thumbnail::THUMBNAIL_WIDTH, thumbnail::THUMBNAIL_HEIGHT))?;
+    Ok(Command::Thumbnail {
+        inputs: arguments.positionals.iter().map(PathBuf::from).collect(),
+        width,
+        height,
+    })
+}
+
+// `defaults` sono le impostazioni salvate: le opzioni le sostituiscono
+// solo per questa esecuzione
+pub fn parse(mut args: Vec<String>, defaults: &Settings) -> Result<Cli, String> {
+    let software = args.iter().any(|arg| arg == "--software");
+    args.retain(|arg| arg != "--software");
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Ok(Cli {
+            command: Command::Help,
+            software,
+        });
+    }
+
+    let command = match args.first().map(String::as_str) {
+        Some("play") => parse_play(&args[1..], defaults)?,
+        Some("export") => parse_export(&args[1..], defaults, software)?,
+        Some("info") => {
+            let arguments = Arguments::split("info", &args[1..], &[])?;
+            arguments.expect_positionals(1, "il file MIDI")?;
+            Command::Info {
+                midi_path: PathBuf::from(&arguments.positionals[0]),
+            }
+        }
+        Some("thumbnail") => parse_thumbnail(&args[1..])?,
+        // Senza comando si apre la finestra: `piano_visualizer brano.mid`
+        _ => parse_play(&args, defaults)?,
+    };
+    Ok(Cli { command, software })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(option: &str, value: &str) -> Arguments {
+        let args = [option.to_string(), value.to_string()];
+        Arguments::split("play", &args, &[option]).unwrap()
+    }
+
+    #[test]
+    fn numbers_are_checked_against_the_limits() {
+        let speed = |value| arguments("--speed", value).number("--speed", 0.25, 2.0);
+        assert_eq!(speed("1.5"), Ok(Some(1.5)));
+        assert!(speed("3").is_err());
+        assert!(speed("veloce").is_err());
+        assert_eq!(
+            arguments("--fps", "30").number("--fps", 1, 240),
+            Ok(Some(30))
+        );
+    }
+
+    #[test]
+    fn nan_and_infinity_are_rejected() {
+        for value in ["NaN", "nan", "inf", "-inf", "infinity"] {
+            for (option, max) in [("--speed", 2.0), ("--fall", 10.0), ("--start", f32::MAX)] {
+                assert!(
+                    arguments(option, value).number(option, 0.0, max).is_err(),
+                    "{} {} accettato",
+                    option,
+                    value
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// src/computer_keyboard.rs
+use crate::midi_input::NoteEvent;
+use egui::Key;
+use std::collections::HashMap;
+
+// Fila bassa (Z...) dal Do dell'ottava base, fila alta (Q...) un'ottava
+// sopra; i tasti neri sono sulla fila superiore a ciascuna
+const LOWER_ROW: [(Key, u8); 12] = [
+    (Key::Z, 0),
+    (Key::S, 1),
+    (Key::X, 2),
+    (Key::D, 3),
+    (Key::C, 4),
+    (Key::V, 5),
+    (Key::G, 6),
+    (Key::B, 7),
+    (Key::H, 8),
+    (Key::N, 9),
+    (Key::J, 10),
+    (Key::M, 11),
+];
+const UPPER_ROW: [(Key, u8); 17] = [
+    (Key::Q, 12),
+    (Key::Num2, 13),
+    (Key::W, 14),
+    (Key::Num3, 15),
+    (Key::E, 16),
+    (Key::R, 17),
+    (Key::Num5, 18),
+    (Key::T, 19),
+    (Key::Num6, 20),
+    (Key::Y, 21),
+    (Key::Num7, 22),
+    (Key::U, 23),
+    (Key::I, 24),
+    (Key::Num9, 25),
+    (Key::O, 26),
+    (Key::Num0, 27),
+    (Key::P, 28),
+];
+
+// Tastiera del computer e mouse come strumento: producono gli stessi
+// `NoteEvent` di una tastiera MIDI collegata.
+pub struct ComputerKeyboard {
+    pub enabled: bool,
+    pub velocity: u8,
+    // Nota suonata dal tasto Z (cambia con - e +)
+    pub base_pitch: u8,
+
+    // Nota suonata da ogni tasto premuto: dopo un cambio d'ottava il
+    // rilascio deve spegnere la nota giusta
+    held: HashMap<Key, u8>,
+    // Tasto del piano cliccato e tenuto con il mouse
+    mouse_pitch: Option<u8>,
+}
+
+impl ComputerKeyboard {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            velocity: 90,
+            base_pitch: 48,
+            held: HashMap::new(),
+            mouse_pitch: None,
+        }
+    }
+
+    fn key_offset(key: Key) -> Option<u8> {
+        LOWER_ROW
+            .iter()
+            .chain(UPPER_ROW.iter())
+            .find(|(k, _)| *k == key)
+            .map(|&(_, offset)| offset)
+    }
+
+    // Etichetta del tasto del computer che suona `pitch`, se c'è
+    pub fn key_label(&self, pitch: u8) -> Option<&'static str> {
+        let offset = pitch.checked_sub(self.base_pitch)?;
+        LOWER_ROW
+            .iter()
+            .chain(UPPER_ROW.iter())
+            .find(|&&(_, o)| o == offset)
+            .map(|(key, _)| key.symbol_or_name())
+    }
+
+    // Note tenute in questo momento (tastiera e mouse)
+    pub fn is_held(&self, pitch: u8) -> bool {
+        self.mouse_pitch == Some(pitch) || self.held.values().any(|&p| p == pitch)
+    }
+
+    pub fn on_key(&mut self, key: Key, pressed: bool, repeat: bool) -> Option<NoteEvent> {
+        if !pressed {
+            return self.held.remove(&key).map(|pitch| NoteEvent::Off { pitch });
+        }
+        if repeat || self.held.contains_key(&key) {
+            return None;
+        }
+        match key {
+            Key::Minus => self.base_pitch = self.base_pitch.saturating_sub(12).max(12),
+            Key::PlusEquals => self.base_pitch = (self.base_pitch + 12).min(84),
+            _ => {
+                let pitch = self.base_pitch + Self::key_offset(key)?;
+                self.held.insert(key, pitch);
+                return Some(NoteEvent::On {
+                    pitch,
+                    velocity: self.velocity,
+                });
+            }
+        }
+        None
+    }
+
+    // Tasto del piano sotto il mouse premuto (None = pulsante rilasciato).
+    // Trascinando si passa da un tasto all'altro come un glissando.
+    pub fn on_mouse(&mut self, pitch: Option<u8>) -> Vec<NoteEvent> {
+        if pitch == self.mouse_pitch {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        if let Some(previous) = self.mouse_pitch {
+            events.push(NoteEvent::Off { pitch: previous });
+        }
+        if let Some(pitch) = pitch {
+            events.push(NoteEvent::On {
+                pitch,
+                velocity: self.velocity,
+            });
+        }
+        self.mouse_pitch = pitch;
+        events
+    }
+
+    // Rilascia le note tenute dai tasti, non quella sotto il mouse
+    pub fn release_keys(&mut self) -> Vec<NoteEvent> {
+        self.held
+            .drain()
+            .map(|(_, pitch)| NoteEvent::Off { pitch })
+            .collect()
+    }
+
+    // Rilascia tutto (modalità disattivata o finestra senza focus)
+    pub fn release_all(&mut self) -> Vec<NoteEvent> {
+        let mut events = self.release_keys();
+        events.extend(
+            self.mouse_pitch
+                .take()
+                .map(|pitch| NoteEvent::Off { pitch }),
+        );
+        events
+    }
+}
@@ -5,5 +5,21 @@ pub const NOTE_WIDTH: f32 = 20.0;
 // (usato come valore di default in state.rs)
 pub const FALL_DURATION_SECS: f32 = 2.0;
 
+// Altezza (pixel) della tastiera disegnata in basso: il suo bordo alto è
+// la linea del presente
+pub const KEYBOARD_HEIGHT: f32 = 100.0;
+
+// Colori di default delle due mani
+pub const COLOR_LEFT_HAND: egui::Color32 = egui::Color32::from_rgb(0, 100, 255); // Un bel blu
+pub const COLOR_RIGHT_HAND: egui::Color32 = egui::Color32::from_rgb(0, 255, 100); // Un bel verde
+
+// Le note sotto questo tasto (Do centrale) sono della mano sinistra
+pub const HAND_SPLIT_PITCH: u8 = 60;
+
+// Colori di feedback della valutazione sulle note che cadono
+pub const FEEDBACK_HIT_COLOR: [f32; 3] = [1.0, 0.85, 0.2]; // oro
+pub const FEEDBACK_EARLY_LATE_COLOR: [f32; 3] = [1.0, 0.45, 0.0]; // arancione
+pub const FEEDBACK_MISSED_COLOR: [f32; 3] = [0.35, 0.35, 0.35]; // grigio
+
 // Colore della nota (rosso per ora)
 // pub const NOTE_COLOR: [f32; 3] = [1.0, 0.0, 0.0]; // <-- RIMOSSO (o commentato)
\ No newline at end of file
@@ -0,0 +1,241 @@
+// src/file_browser.rs
+// Finestra egui per scegliere un file MIDI, una sessione o una scaletta: cartelle e brani
+// della cartella corrente, percorso modificabile a mano e anteprima del
+// brano selezionato. Niente dialoghi nativi, così funziona ovunque giri egui.
+use crate::playlist;
+use crate::session;
+use crate::thumbnail::{self, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+use std::path::{Path, PathBuf};
+
+const MIDI_EXTENSIONS: [&str; 2] = ["mid", "midi"];
+
+struct Entry {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+}
+
+// Cosa ha fatto l'utente nella lista, applicato dopo averla disegnata
+enum Action {
+    Navigate(PathBuf),
+    Select(PathBuf),
+    Choose(PathBuf),
+}
+
+pub struct FileBrowser {
+    pub open: bool,
+    directory: PathBuf,
+    entries: Vec<Entry>,
+    selected: Option<PathBuf>,
+    // Percorso nella casella di testo (cartella o file)
+    path_text: String,
+    error: Option<String>,
+    // Anteprima del brano selezionato (None = non disponibile: non si
+    // riprova finché non cambia la selezione)
+    thumbnail: Option<(PathBuf, Option<egui::TextureHandle>)>,
+}
+
+pub fn is_midi_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        MIDI_EXTENSIONS
+            .iter()
+            .any(|midi| ext.eq_ignore_ascii_case(midi))
+    })
+}
+
+impl FileBrowser {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            directory: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            entries: Vec::new(),
+            selected: None,
+            path_text: String::new(),
+            error: None,
+            thumbnail: None,
+        }
+    }
+
+    // Apre la finestra, nella cartella data se c'è (es. quella del brano)
+    pub fn open_in(&mut self, directory: Option<&Path>) {
+        if let Some(directory) = directory.filter(|d| d.is_dir()) {
+            self.directory = directory.to_path_buf();
+        }
+        self.open = true;
+        self.refresh();
+    }
+
+    fn navigate(&mut self, directory: PathBuf) {
+        self.directory = directory;
+        self.selected = None;
+        self.refresh();
+    }
+
+    // Rilegge la cartella: prima le sottocartelle, poi i brani, in ordine
+    // alfabetico; i file nascosti non si mostrano
+    fn refresh(&mut self) {
+        self.path_text = self.directory.display().to_string();
+        self.entries.clear();
+        self.error = None;
+        let read = match std::fs::read_dir(&self.directory) {
+            Ok(read) => read,
+            Err(e) => {
+                self.error = Some(format!(
+                    "Impossibile leggere '{}': {}",
+                    self.directory.display(),
+                    e
+                ));
+                return;
+            }
+        };
+        for entry in read.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_dir = path.is_dir();
+            let openable = is_midi_file(&path)
+                || session::is_session_file(&path)
+                || playlist::is_playlist_file(&path);
+            if name.starts_with('.') || !(is_dir || openable) {
+                continue;
+            }
+            self.entries.push(Entry { path, name, is_dir });
+        }
+        self.entries
+            .sort_by_cached_key(|e| (!e.is_dir, e.name.to_lowercase()));
+    }
+
+    // Carica l'anteprima del brano selezionato, una volta sola per file
+    fn update_thumbnail(&mut self, ctx: &egui::Context) {
+        let Some(selected) = &self.selected else {
+            self.thumbnail = None;
+            return;
+        };
+        if self
+            .thumbnail
+            .as_ref()
+            .is_some_and(|(path, _)| path == selected)
+        {
+            return;
+        }
+        // Sessioni e scalette non hanno anteprima
+        let texture = if is_midi_file(selected) {
+            match thumbnail::load_thumbnail(selected, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT) {
+                Ok(rgba) => {
+                    let image = egui::ColorImage::from_rgba_unmultiplied(
+                        [THUMBNAIL_WIDTH as usize, THUMBNAIL_HEIGHT as usize],
+                        &rgba,
+                    );
+                    Some(ctx.load_texture("anteprima_brano", image, Default::default()))
+                }
+                Err(e) => {
+                    self.error = Some(e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        self.thumbnail = Some((selected.clone(), texture));
+    }
+
+    // Disegna la finestra se è aperta; restituisce il brano scelto
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<PathBuf> {
+        if !self.open {
+            return None;
+        }
+        self.update_thumbnail(ctx);
+
+        let mut action = None;
+        let mut open = self.open;
+        egui::Window::new("Apri brano")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("⬆").on_hover_text("Cartella superiore").clicked()
+                        && let Some(parent) = self.directory.parent()
+                    {
+                        action = Some(Action::Navigate(parent.to_path_buf()));
+                    }
+                    // Invio sulla casella apre il percorso scritto
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut self.path_text)
+                            .desired_width(f32::INFINITY),
+                    );
+                    if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        let path = PathBuf::from(self.path_text.trim());
+                        action = Some(if path.is_dir() {
+                            Action::Navigate(path)
+                        } else {
+                            Action::Choose(path)
+                        });
+                    }
+                });
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        if self.entries.is_empty() {
+                            ui.label("Nessun brano in questa cartella");
+                        }
+                        for entry in &self.entries {
+                            let label = if entry.is_dir {
+                                format!("📁 {}", entry.name)
+                            } else {
+                                entry.name.clone()
+                            };
+                            let selected = self.selected.as_ref() == Some(&entry.path);
+                            let response = ui.selectable_label(selected, label);
+                            if entry.is_dir && response.clicked() {
+                                action = Some(Action::Navigate(entry.path.clone()));
+                            } else if response.double_clicked() {
+                                action = Some(Action::Choose(entry.path.clone()));
+                            } else if response.clicked() {
+                                action = Some(Action::Select(entry.path.clone()));
+                            }
+                        }
+                    });
+
+                ui.separator();
+                if let Some((_, Some(texture))) = &self.thumbnail {
+                    ui.image(
+                        texture.id(),
+                        egui::vec2(THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    let open_button =
+                        ui.add_enabled(self.selected.is_some(), egui::Button::new("Apri"));
+                    if open_button.clicked()
+                        && let Some(selected) = &self.selected
+                    {
+                        action = Some(Action::Choose(selected.clone()));
+                    }
+                    if ui.button("Annulla").clicked() {
+                        self.open = false;
+                    }
+                });
+            });
+        self.open &= open;
+
+        match action? {
+            Action::Navigate(directory) => {
+                self.navigate(directory);
+                None
+            }
+            Action::Select(path) => {
+                self.error = None;
+                self.selected = Some(path);
+                None
+            }
+            Action::Choose(path) => {
+                self.open = false;
+                Some(path)
+            }
+        }
+    }
+}
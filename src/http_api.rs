@@ -0,0 +1,709 @@
+// src/http_api.rs
+use crate::midi_loader::Hand;
+use crate::remote::RemoteCommand;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, ReadWrite, Request, Response, Server, StatusCode};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+pub const DEFAULT_HTTP_PORT: u16 = 8080;
+// Intervallo fra due messaggi WebSocket (~30 al secondo)
+const STREAM_INTERVAL: Duration = Duration::from_millis(33);
+// Quanto aspetta una richiesta che il thread principale esegua il comando
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+// Ogni quanto si guarda se i comandi in attesa sono stati eseguiti
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(5);
+// WebSocket aperti insieme, ognuno con un thread suo
+const MAX_WEBSOCKETS: usize = 8;
+
+// Stato di un comando in coda: lo decide chi arriva prima fra il thread
+// principale, che lo esegue, e il timeout, che lo annulla
+const COMMAND_PENDING: u8 = 0;
+const COMMAND_TAKEN: u8 = 1;
+const COMMAND_CANCELLED: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActiveNote {
+    pub pitch: u8,
+    pub velocity: u8,
+    pub hand: Hand,
+}
+
+// Impostazioni modificabili via API (GET e PUT /api/settings)
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct ApiSettings {
+    pub speed: f32,
+    pub fall_duration_secs: f32,
+    pub color_left: [u8; 3],
+    pub color_right: [u8; 3],
+    pub synth_volume: f32,
+    pub metronome: bool,
+}
+
+// Fotografia dello stato pubblicata dal thread principale a ogni frame
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct StateSnapshot {
+    pub time_secs: f32,
+    pub playing: bool,
+    pub song_path: Option<PathBuf>,
+    pub song_end_secs: f32,
+    pub notes: Vec<ActiveNote>,
+    pub settings: ApiSettings,
+}
+
+// Aggiornamento parziale: i campi assenti restano invariati
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsPatch {
+    speed: Option<f32>,
+    fall_duration_secs: Option<f32>,
+    color_left: Option<[u8; 3]>,
+    color_right: Option<[u8; 3]>,
+    synth_volume: Option<f32>,
+    metronome: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SeekBody {
+    position_secs: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoadBody {
+    path: PathBuf,
+}
+
+// Comandi di una richiesta, con il canale su cui mandare l'esito
+struct CommandBatch {
+    commands: Vec<RemoteCommand>,
+    state: Arc<AtomicU8>,
+    reply: Sender<Result<(), String>>,
+}
+
+// Richiesta che aspetta l'esito dei suoi comandi
+struct PendingCommand {
+    request: Request,
+    state: Arc<AtomicU8>,
+    reply: Receiver<Result<(), String>>,
+    deadline: Instant,
+}
+
+// Server HTTP locale con API REST e WebSocket sulla stessa porta:
+//
+//   GET  /api/state               stato completo (tempo, note attive, impostazioni)
+//   POST /api/play | /api/pause | /api/toggle
+//   POST /api/seek                {"position_secs": 12.5}
+//   POST /api/load                {"path": "/percorso/brano.mid"}
+//   GET  /api/settings
+//   PUT  /api/settings            {"speed": 0.5, "color_left": [255, 0, 0], ...}
+//   GET  /ws                      WebSocket: lo stato in JSON ~30 volte al secondo
+//
+// POST e PUT vogliono "Content-Type: application/json" (anche senza corpo) e
+// le richieste con Origin o Host non locali vengono rifiutate: una pagina web
+// qualsiasi non può comandare il programma né leggerne lo stato.
+//
+// I comandi vengono eseguiti dal thread principale in `State::update`; la
+// richiesta HTTP aspetta l'esito senza fermare le altre. Se non arriva entro
+// COMMAND_TIMEOUT il comando viene annullato (503) e non sarà più eseguito.
+//
+// Ogni WebSocket ha un thread suo (al massimo MAX_WEBSOCKETS), che legge
+// anche i frame del client: ping e chiusura ricevono risposta.
+pub struct HttpApi {
+    receiver: Receiver<CommandBatch>,
+    snapshot: Arc<Mutex<StateSnapshot>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    pub address: SocketAddr,
+}
+
+impl HttpApi {
+    pub fn start(port: u16) -> Result<Self, String> {
+        let server = Server::http(("127.0.0.1", port))
+            .map_err(|e| format!("Impossibile aprire la porta HTTP {}: {}", port, e))?;
+        let address = server
+            .server_addr()
+            .to_ip()
+            .ok_or("Indirizzo del server HTTP non valido")?;
+
+        let (sender, receiver) = mpsc::channel();
+        let snapshot = Arc::new(Mutex::new(StateSnapshot::default()));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let snapshot = snapshot.clone();
+            let running = running.clone();
+            std::thread::spawn(move || serve(server, sender, snapshot, running))
+        };
+
+        println!("[INFO] API HTTP in ascolto su http://{}", address);
+        Ok(Self {
+            receiver,
+            snapshot,
+            running,
+            thread: Some(thread),
+            address,
+        })
+    }
+
+    // Esegue i comandi arrivati dall'ultimo frame e risponde alle richieste
+    pub fn process(&self, mut apply: impl FnMut(RemoteCommand) -> Result<(), String>) {
+        for batch in self.receiver.try_iter() {
+            // Annullato per timeout: il client ha già avuto la sua risposta
+            let taken = batch.state.compare_exchange(
+                COMMAND_PENDING,
+                COMMAND_TAKEN,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+            if taken.is_err() {
+                continue;
+            }
+            let result = batch.commands.into_iter().try_for_each(&mut apply);
+            // Il client potrebbe aver già chiuso la connessione
+            let _ = batch.reply.send(result);
+        }
+    }
+
+    pub fn publish(&self, snapshot: StateSnapshot) {
+        if let Ok(mut shared) = self.snapshot.lock() {
+            *shared = snapshot;
+        }
+    }
+}
+
+impl Drop for HttpApi {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        println!("[INFO] API HTTP fermata");
+    }
+}
+
+fn serve(
+    server: Server,
+    sender: Sender<CommandBatch>,
+    snapshot: Arc<Mutex<StateSnapshot>>,
+    running: Arc<AtomicBool>,
+) {
+    let mut pending = Vec::new();
+    let websockets = Arc::new(AtomicUsize::new(0));
+    while running.load(Ordering::Relaxed) {
+        // Il timeout permette di accorgersi dello stop e dei comandi eseguiti
+        let wait = if pending.is_empty() {
+            Duration::from_millis(100)
+        } else {
+            COMMAND_POLL_INTERVAL
+        };
+        if let Ok(Some(request)) = server.recv_timeout(wait) {
+            let response = if request.url().split('?').next() == Some("/ws") {
+                upgrade_websocket(request, &snapshot, &running, &websockets)
+            } else {
+                handle_request(request, &sender, &snapshot, &mut pending)
+            };
+            if let Err(e) = response {
+                eprintln!("[ATTENZIONE] Risposta HTTP non inviata: {}", e);
+            }
+        }
+        answer_pending(&mut pending);
+    }
+}
+
+// Risponde alle richieste i cui comandi sono stati eseguiti o sono scaduti
+fn answer_pending(pending: &mut Vec<PendingCommand>) {
+    let mut index = 0;
+    while index < pending.len() {
+        let command = &pending[index];
+        let response = match command.reply.try_recv() {
+            Ok(Ok(())) => json_response(200, &serde_json::json!({ "ok": true })),
+            Ok(Err(message)) => error_response(400, &message),
+            Err(TryRecvError::Disconnected) => error_response(503, "Applicazione in chiusura"),
+            Err(TryRecvError::Empty)
+                if Instant::now() >= command.deadline
+                    && command
+                        .state
+                        .compare_exchange(
+                            COMMAND_PENDING,
+                            COMMAND_CANCELLED,
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                        )
+                        .is_ok() =>
+            {
+                error_response(503, "Nessuna risposta dall'applicazione, comando annullato")
+            }
+            // In esecuzione o ancora in tempo: l'esito arriverà
+            Err(TryRecvError::Empty) => {
+                index += 1;
+                continue;
+            }
+        };
+        let command = pending.swap_remove(index);
+        if let Err(e) = command.request.respond(response) {
+            eprintln!("[ATTENZIONE] Risposta HTTP non inviata: {}", e);
+        }
+    }
+}
+
+// "localhost", "127.0.0.1" o "[::1]", con la porta o senza
+fn is_local_authority(authority: &str) -> bool {
+    let host = match authority.strip_prefix('[') {
+        Some(rest) => rest.split(']').next(),
+        None => authority.split(':').next(),
+    };
+    matches!(
+        host.map(str::to_ascii_lowercase).as_deref(),
+        Some("localhost" | "127.0.0.1" | "::1")
+    )
+}
+
+fn is_local_origin(origin: &str) -> bool {
+    origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+        .is_some_and(is_local_authority)
+}
+
+// Host e Origin (se ci sono) devono essere locali: un Host diverso vuol dire
+// DNS rebinding, un Origin diverso una pagina web di un altro sito
+fn check_origin<'a>(
+    mut header: impl FnMut(&'static str) -> Option<&'a str>,
+) -> Result<(), (u16, String)> {
+    if let Some(host) = header("Host")
+        && !is_local_authority(host)
+    {
+        return Err((403, format!("Host '{}' non ammesso", host)));
+    }
+    if let Some(origin) = header("Origin")
+        && !is_local_origin(origin)
+    {
+        return Err((403, format!("Origin '{}' non ammessa", origin)));
+    }
+    Ok(())
+}
+
+fn header_value<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+// Controlli prima di eseguire qualsiasi cosa
+fn check_request(request: &Request) -> Result<(), (u16, String)> {
+    check_origin(|name| header_value(request, name))?;
+    // Un form o un fetch "semplice" da un'altra pagina non può mandare JSON
+    // senza preflight CORS, che qui non passa mai
+    if matches!(request.method(), Method::Post | Method::Put) {
+        let content_type = header_value(request, "Content-Type").unwrap_or_default();
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        if !media_type.eq_ignore_ascii_case("application/json") {
+            return Err((415, "Serve Content-Type: application/json".to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn json_response(status: u16, body: &impl Serialize) -> Response<std::io::Cursor<Vec<u8>>> {
+    let json = serde_json::to_string(body).unwrap_or_else(|_| "{}".to_string());
+    Response::from_string(json)
+        .with_status_code(StatusCode(status))
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+fn error_response(status: u16, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    json_response(status, &serde_json::json!({ "error": message }))
+}
+
+fn parse_body<T: serde::de::DeserializeOwned>(request: &mut Request) -> Result<T, String> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut body)
+        .map_err(|e| e.to_string())?;
+    serde_json::from_str(&body).map_err(|e| format!("JSON non valido: {}", e))
+}
+
+// Converte la richiesta in comandi; Ok(None) = sola lettura
+fn route(request: &mut Request) -> Result<Option<Vec<RemoteCommand>>, (u16, String)> {
+    let bad_request = |e: String| (400, e);
+    let commands = match (request.method(), request.url()) {
+        (Method::Get, "/api/state" | "/api/settings") => return Ok(None),
+        (Method::Post, "/api/play") => vec![RemoteCommand::Play],
+        (Method::Post, "/api/pause") => vec![RemoteCommand::Pause],
+        (Method::Post, "/api/toggle") => vec![RemoteCommand::TogglePlay],
+        (Method::Post, "/api/seek") => {
+            let body: SeekBody = parse_body(request).map_err(bad_request)?;
+            vec![RemoteCommand::Seek(body.position_secs)]
+        }
+        (Method::Post, "/api/load") => {
+            let body: LoadBody = parse_body(request).map_err(bad_request)?;
+            vec![RemoteCommand::Load(body.path)]
+        }
+        (Method::Put, "/api/settings") => {
+            let patch: SettingsPatch = parse_body(request).map_err(bad_request)?;
+            let mut commands = Vec::new();
+            commands.extend(patch.speed.map(RemoteCommand::Speed));
+            commands.extend(patch.fall_duration_secs.map(RemoteCommand::FallDuration));
+            commands.extend(patch.color_left.map(|rgb| RemoteCommand::Color {
+                hand: Hand::Left,
+                rgb,
+            }));
+            commands.extend(patch.color_right.map(|rgb| RemoteCommand::Color {
+                hand: Hand::Right,
+                rgb,
+            }));
+            commands.extend(patch.synth_volume.map(RemoteCommand::SynthVolume));
+            commands.extend(patch.metronome.map(RemoteCommand::Metronome));
+            commands
+        }
+        (_, url) => return Err((404, format!("Risorsa '{}' non trovata", url))),
+    };
+    Ok(Some(commands))
+}
+
+fn handle_request(
+    mut request: Request,
+    sender: &Sender<CommandBatch>,
+    snapshot: &Mutex<StateSnapshot>,
+    pending: &mut Vec<PendingCommand>,
+) -> std::io::Result<()> {
+    if let Err((status, message)) = check_request(&request) {
+        return request.respond(error_response(status, &message));
+    }
+    let commands = match route(&mut request) {
+        Ok(Some(commands)) => commands,
+        // Letture: si risponde con l'ultima fotografia pubblicata
+        Ok(None) => {
+            let current = snapshot.lock().map(|s| s.clone()).unwrap_or_default();
+            return if request.url() == "/api/settings" {
+                request.respond(json_response(200, &current.settings))
+            } else {
+                request.respond(json_response(200, &current))
+            };
+        }
+        Err((status, message)) => return request.respond(error_response(status, &message)),
+    };
+
+    let (reply_sender, reply) = mpsc::channel();
+    let state = Arc::new(AtomicU8::new(COMMAND_PENDING));
+    let batch = CommandBatch {
+        commands,
+        state: state.clone(),
+        reply: reply_sender,
+    };
+    if sender.send(batch).is_err() {
+        return request.respond(error_response(503, "Applicazione in chiusura"));
+    }
+    pending.push(PendingCommand {
+        request,
+        state,
+        reply,
+        deadline: Instant::now() + COMMAND_TIMEOUT,
+    });
+    Ok(())
+}
+
+// Completa l'handshake WebSocket e trasmette lo stato su un thread dedicato
+fn upgrade_websocket(
+    request: Request,
+    snapshot: &Arc<Mutex<StateSnapshot>>,
+    running: &Arc<AtomicBool>,
+    websockets: &Arc<AtomicUsize>,
+) -> std::io::Result<()> {
+    if let Err((status, message)) = check_request(&request) {
+        return request.respond(error_response(status, &message));
+    }
+    let Some(key) = header_value(&request, "Sec-WebSocket-Key").map(str::to_string) else {
+        return request.respond(error_response(
+            400,
+            "Richiesta WebSocket senza Sec-WebSocket-Key",
+        ));
+    };
+    let open = websockets.fetch_add(1, Ordering::AcqRel);
+    if open >= MAX_WEBSOCKETS {
+        websockets.fetch_sub(1, Ordering::AcqRel);
+        return request.respond(error_response(503, "Troppi WebSocket aperti"));
+    }
+
+    let accept = tungstenite::handshake::derive_accept_key(key.as_bytes());
+    let response = Response::empty(StatusCode(101))
+        .with_header(Header::from_bytes("Sec-WebSocket-Accept", accept).unwrap());
+    let stream = request.upgrade("websocket", response);
+
+    let snapshot = snapshot.clone();
+    let running = running.clone();
+    let websockets = websockets.clone();
+    std::thread::spawn(move || {
+        stream_websocket(stream, &snapshot, &running);
+        websockets.fetch_sub(1, Ordering::AcqRel);
+    });
+    Ok(())
+}
+
+// Trasmette lo stato quando cambia. La connessione di tiny_http non ha
+// timeout, quindi la lettura aspetta il client: a ogni giro si manda un ping,
+// a cui il client risponde da solo (RFC 6455), e intanto si gestiscono i
+// suoi frame (ping, chiusura). Un client che non risponde ferma solo il suo thread.
+fn stream_websocket(
+    stream: Box<dyn ReadWrite + Send>,
+    snapshot: &Mutex<StateSnapshot>,
+    running: &AtomicBool,
+) {
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    let mut last_sent = None;
+    while running.load(Ordering::Relaxed) {
+        let started = Instant::now();
+        let current = snapshot.lock().map(|s| s.clone()).unwrap_or_default();
+        if last_sent.as_ref() != Some(&current) {
+            let json = serde_json::to_string(&current).unwrap_or_default();
+            // Errore = client disconnesso
+            if socket.send(Message::text(json)).is_err() {
+                return;
+            }
+            last_sent = Some(current);
+        }
+        if socket.send(Message::Ping(Vec::new())).is_err() {
+            return;
+        }
+        // I pong e la risposta alla chiusura partono con la lettura successiva;
+        // a chiusura completata la lettura dà errore
+        loop {
+            match socket.read() {
+                Ok(Message::Pong(_)) => break,
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        }
+        if let Some(rest) = STREAM_INTERVAL.checked_sub(started.elapsed()) {
+            std::thread::sleep(rest);
+        }
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Instant;
+
+    const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    // Manda una richiesta grezza e restituisce la risposta intera
+    fn send(address: SocketAddr, request: String) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn post(address: SocketAddr, headers: &str, body: &str) -> String {
+        send(
+            address,
+            format!(
+                "POST /api/seek HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n{}Content-Length: {}\r\n\r\n{}",
+                address,
+                headers,
+                body.len(),
+                body
+            ),
+        )
+    }
+
+    // Esegue i comandi finché il client non ha finito, come fa State::update
+    fn serve_commands(
+        api: &HttpApi,
+        client: std::thread::JoinHandle<String>,
+    ) -> (String, Vec<RemoteCommand>) {
+        let mut received = Vec::new();
+        while !client.is_finished() {
+            api.process(|command| {
+                received.push(command);
+                Ok(())
+            });
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        (client.join().unwrap(), received)
+    }
+
+    #[test]
+    fn json_command_reaches_the_application() {
+        let api = HttpApi::start(0).unwrap();
+        let address = api.address;
+        let client = std::thread::spawn(move || {
+            post(
+                address,
+                "Content-Type: application/json; charset=utf-8\r\nOrigin: http://localhost:3000\r\n",
+                r#"{"position_secs": 12.5}"#,
+            )
+        });
+        let (response, received) = serve_commands(&api, client);
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert_eq!(received, vec![RemoteCommand::Seek(12.5)]);
+    }
+
+    #[test]
+    fn other_content_types_and_foreign_origins_are_rejected() {
+        let api = HttpApi::start(0).unwrap();
+        let address = api.address;
+        let body = r#"{"position_secs": 1}"#;
+        let client = std::thread::spawn(move || {
+            [
+                post(address, "Content-Type: text/plain\r\n", body),
+                post(address, "", body),
+                post(
+                    address,
+                    "Content-Type: application/json\r\nOrigin: http://example.com\r\n",
+                    body,
+                ),
+                send(
+                    address,
+                    "GET /api/state HTTP/1.1\r\nHost: attacker.example:8080\r\nConnection: close\r\n\r\n"
+                        .to_string(),
+                ),
+            ]
+            .join("\n---\n")
+        });
+        let (responses, received) = serve_commands(&api, client);
+        let statuses: Vec<&str> = responses
+            .split("\n---\n")
+            .map(|response| &response[9..12])
+            .collect();
+        assert_eq!(statuses, ["415", "415", "403", "403"]);
+        assert!(received.is_empty());
+    }
+
+    #[test]
+    fn pending_command_does_not_block_other_clients() {
+        let api = HttpApi::start(0).unwrap();
+        let address = api.address;
+        // Nessuno esegue i comandi: il POST resta in attesa
+        let pending = std::thread::spawn(move || {
+            post(
+                address,
+                "Content-Type: application/json\r\n",
+                r#"{"position_secs": 1}"#,
+            )
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        let started = Instant::now();
+        let response = send(
+            address,
+            "GET /api/state HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n".to_string(),
+        );
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        drop(pending);
+    }
+
+    #[test]
+    fn command_not_run_in_time_is_cancelled() {
+        let api = HttpApi::start(0).unwrap();
+        let address = api.address;
+        let response = post(
+            address,
+            "Content-Type: application/json\r\n",
+            r#"{"position_secs": 1}"#,
+        );
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+        // Il client ha saputo che non è stato eseguito: non deve arrivare più
+        let mut received = Vec::new();
+        api.process(|command| {
+            received.push(command);
+            Ok(())
+        });
+        assert!(received.is_empty());
+    }
+
+    #[test]
+    fn websocket_streams_state_and_closes_cleanly() {
+        let api = HttpApi::start(0).unwrap();
+        api.publish(StateSnapshot {
+            time_secs: 3.0,
+            ..StateSnapshot::default()
+        });
+        let url = format!("ws://{}/ws", api.address);
+        let stream = TcpStream::connect(api.address).unwrap();
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        let (mut socket, _) = tungstenite::client(url.as_str(), stream).unwrap();
+
+        let Message::Text(text) = socket.read().unwrap() else {
+            panic!("atteso un messaggio di testo");
+        };
+        let state: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(state["time_secs"], 3.0);
+
+        // Il server risponde al ping e alla chiusura
+        socket.send(Message::Ping(b"ciao".to_vec())).unwrap();
+        let pong = loop {
+            match socket.read().unwrap() {
+                Message::Pong(payload) => break payload,
+                _ => continue,
+            }
+        };
+        assert_eq!(pong, b"ciao");
+        socket.close(None).unwrap();
+        loop {
+            match socket.read() {
+                Ok(_) => continue,
+                Err(tungstenite::Error::ConnectionClosed) => break,
+                Err(e) => panic!("chiusura non pulita: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn websockets_beyond_the_limit_are_refused() {
+        let api = HttpApi::start(0).unwrap();
+        let url = format!("ws://{}/ws", api.address);
+        let sockets: Vec<_> = (0..MAX_WEBSOCKETS)
+            .map(|_| tungstenite::connect(url.as_str()).unwrap())
+            .collect();
+        match tungstenite::connect(url.as_str()) {
+            Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 503),
+            other => panic!(
+                "WebSocket oltre il limite accettato: {:?}",
+                other.map(|_| ())
+            ),
+        }
+        drop(sockets);
+    }
+
+    #[test]
+    fn websocket_from_a_foreign_origin_is_refused() {
+        let api = HttpApi::start(0).unwrap();
+        let request = tungstenite::http::Request::builder()
+            .uri(format!("ws://{}/ws", api.address))
+            .header("Host", api.address.to_string())
+            .header("Origin", "http://example.com")
+            .header("Connection", "Upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "13")
+            .header(
+                "Sec-WebSocket-Key",
+                tungstenite::handshake::client::generate_key(),
+            )
+            .body(())
+            .unwrap();
+        let stream = TcpStream::connect(api.address).unwrap();
+        match tungstenite::client(request, stream) {
+            Err(tungstenite::HandshakeError::Failure(tungstenite::Error::Http(response))) => {
+                assert_eq!(response.status(), 403)
+            }
+            other => panic!("handshake non rifiutato: {:?}", other.map(|_| ())),
+        }
+    }
+}
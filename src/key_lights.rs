@@ -0,0 +1,149 @@
+// src/key_lights.rs
+use crate::midi_loader::{Hand, Song};
+use crate::midi_output::MidiOutput;
+use crate::sequencer::Sequencer;
+use crate::synth::SynthEvent;
+use std::collections::HashMap;
+
+// Come si accendono i LED per una mano: molte tastiere usano un canale
+// dedicato e leggono il colore dalla velocity del NoteOn
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HandLight {
+    pub enabled: bool,
+    pub channel: u8,
+    pub color_velocity: u8,
+}
+
+impl HandLight {
+    pub const DEFAULT_LEFT: HandLight = HandLight {
+        enabled: true,
+        channel: 14,
+        color_velocity: 40,
+    };
+    pub const DEFAULT_RIGHT: HandLight = HandLight {
+        enabled: true,
+        channel: 15,
+        color_velocity: 100,
+    };
+}
+
+pub const DEFAULT_LOOK_AHEAD_SECS: f32 = 0.5;
+
+// Guida luminosa sulla tastiera dello studente: ogni tasto si accende un po'
+// prima che la nota arrivi alla linea del presente e si spegne a fine nota.
+// Usa lo stesso sequencer dell'audio, con i NoteOn anticipati.
+pub struct KeyLights {
+    pub enabled: bool,
+    pub look_ahead_secs: f32,
+    pub left: HandLight,
+    pub right: HandLight,
+
+    sequencer: Sequencer,
+    // Quante note tengono acceso ogni (canale, tasto): due note ribattute
+    // si sovrappongono perché la seconda si accende prima della fine della prima
+    lit: HashMap<(u8, u8), u32>,
+    // Note già dentro la finestra di anticipo al momento di un seek
+    catch_up: Vec<usize>,
+}
+
+impl KeyLights {
+    pub fn new(song: &Song) -> Self {
+        Self {
+            enabled: false,
+            look_ahead_secs: DEFAULT_LOOK_AHEAD_SECS,
+            left: HandLight::DEFAULT_LEFT,
+            right: HandLight::DEFAULT_RIGHT,
+            sequencer: Sequencer::with_note_lead(song, DEFAULT_LOOK_AHEAD_SECS),
+            lit: HashMap::new(),
+            catch_up: Vec::new(),
+        }
+    }
+
+    fn light_for(&self, hand: Hand) -> HandLight {
+        match hand {
+            Hand::Left => self.left,
+            Hand::Right => self.right,
+        }
+    }
+
+    // Da chiamare dopo un cambio di anticipo o di brano
+    pub fn rebuild(&mut self, song: &Song, position_secs: f32) {
+        self.sequencer = Sequencer::with_note_lead(song, self.look_ahead_secs);
+        self.seek(position_secs, song);
+    }
+
+    // Riallinea le luci alla nuova posizione. Chi chiama deve aver già spento
+    // le luci accese (es. con `MidiOutput::all_notes_off`).
+    pub fn seek(&mut self, position_secs: f32, song: &Song) {
+        self.lit.clear();
+        self.sequencer.seek(position_secs);
+        // Il seek salta i NoteOn anticipati prima della posizione: le note
+        // ancora da finire vanno accese a mano
+        self.catch_up = song
+            .notes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| {
+                n.start_time_secs - self.look_ahead_secs < position_secs
+                    && n.start_time_secs + n.duration_secs > position_secs
+            })
+            .map(|(index, _)| index)
+            .collect();
+    }
+
+    pub fn advance(&mut self, position_secs: f32, song: &Song, output: &mut MidiOutput) {
+        for index in std::mem::take(&mut self.catch_up) {
+            self.switch(song, index, true, output);
+        }
+
+        let mut events = Vec::new();
+        self.sequencer.advance(position_secs, |e| events.push(e));
+        for event in events {
+            match (event.event, event.note) {
+                (SynthEvent::NoteOn { .. }, Some(index)) => self.switch(song, index, true, output),
+                (SynthEvent::NoteOff { .. }, Some(index)) => {
+                    self.switch(song, index, false, output)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn switch(&mut self, song: &Song, index: usize, on: bool, output: &mut MidiOutput) {
+        let note = &song.notes[index];
+        let light = self.light_for(note.hand);
+        if !light.enabled {
+            return;
+        }
+        let key = (light.channel, note.pitch);
+        let count = self.lit.entry(key).or_insert(0);
+        if on {
+            *count += 1;
+            if *count == 1 {
+                output.send_event(SynthEvent::NoteOn {
+                    channel: light.channel,
+                    pitch: note.pitch,
+                    velocity: light.color_velocity,
+                });
+            }
+        } else if *count > 0 {
+            *count -= 1;
+            if *count == 0 {
+                output.send_event(SynthEvent::NoteOff {
+                    channel: light.channel,
+                    pitch: note.pitch,
+                });
+            }
+        }
+    }
+
+    // Spegne tutte le luci accese (es. quando la guida viene disattivata)
+    pub fn all_off(&mut self, output: &mut MidiOutput) {
+        for ((channel, pitch), count) in self.lit.drain() {
+            if count > 0 {
+                output.send_event(SynthEvent::NoteOff { channel, pitch });
+            }
+        }
+        self.catch_up.clear();
+    }
+}
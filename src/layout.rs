@@ -0,0 +1,169 @@
+// src/layout.rs
+// Geometria condivisa da note che cadono e tastiera disegnata sotto di esse.
+// Tutte le misure sono in pixel fisici della finestra.
+use crate::config::{KEYBOARD_HEIGHT, NOTE_WIDTH};
+use std::ops::RangeInclusive;
+
+// Il tasto che cade all'inizio del primo quarto dello schermo (Do3)
+const ANCHOR_PITCH: f32 = 48.0;
+
+const NOTE_NAMES: [&str; 12] = [
+    "Do", "Do#", "Re", "Re#", "Mi", "Fa", "Fa#", "Sol", "Sol#", "La", "La#", "Si",
+];
+
+// Tasti da mostrare, estremi compresi
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct KeyRange {
+    pub low: u8,
+    pub high: u8,
+}
+
+impl KeyRange {
+    // "21-108" oppure con i nomi delle note, "Do2-Do7"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "Intervallo di tasti '{}' non valido (es. 21-108 o Do2-Do7)",
+                text
+            )
+        };
+        // Il separatore è il primo '-' dopo una cifra: quello di "Do-1" fa
+        // parte dell'ottava
+        let separator = text
+            .char_indices()
+            .find(|&(i, c)| {
+                c == '-' && text[..i].trim_end().ends_with(|c: char| c.is_ascii_digit())
+            })
+            .map(|(i, _)| i)
+            .ok_or_else(invalid)?;
+        let (low, high) = (&text[..separator], &text[separator + 1..]);
+        let low = parse_pitch(low.trim()).ok_or_else(invalid)?;
+        let high = parse_pitch(high.trim()).ok_or_else(invalid)?;
+        if low >= high {
+            return Err(invalid());
+        }
+        Ok(Self { low, high })
+    }
+}
+
+// Nello stesso formato accettato da parse, con i nomi delle note
+impl std::fmt::Display for KeyRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}-{}", note_name(self.low), note_name(self.high))
+    }
+}
+
+// Disposizione dei tasti su una superficie larga `screen_width`. Senza
+// intervallo le colonne sono larghe NOTE_WIDTH con il Do3 a un quarto dello
+// schermo; con un intervallo i suoi tasti riempiono tutta la larghezza.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    screen_width: f32,
+    range: Option<KeyRange>,
+}
+
+impl Layout {
+    pub fn new(screen_width: f32, range: Option<KeyRange>) -> Self {
+        Self {
+            screen_width,
+            range,
+        }
+    }
+
+    pub fn key_width(&self) -> f32 {
+        match self.range {
+            Some(range) => self.screen_width / (range.high - range.low + 1) as f32,
+            None => NOTE_WIDTH,
+        }
+    }
+
+    // Tasto di riferimento e sua posizione orizzontale
+    fn anchor(&self) -> (f32, f32) {
+        match self.range {
+            Some(range) => (range.low as f32, 0.0),
+            None => (ANCHOR_PITCH, self.screen_width / 4.0),
+        }
+    }
+
+    // Bordo sinistro della colonna di un tasto
+    pub fn note_x(&self, pitch: u8) -> f32 {
+        let (anchor_pitch, anchor_x) = self.anchor();
+        (pitch as f32 - anchor_pitch) * self.key_width() + anchor_x
+    }
+
+    // Il tasto sotto una coordinata orizzontale, se esiste
+    pub fn pitch_at_x(&self, x: f32) -> Option<u8> {
+        let (anchor_pitch, anchor_x) = self.anchor();
+        let pitch = ((x - anchor_x) / self.key_width()).floor() + anchor_pitch;
+        (0.0..=127.0).contains(&pitch).then_some(pitch as u8)
+    }
+
+    // I tasti (anche parzialmente) visibili: quelli dell'intervallo scelto,
+    // altrimenti quelli della tastiera da 88 che entrano nello schermo
+    pub fn visible_pitches(&self) -> RangeInclusive<u8> {
+        if let Some(range) = self.range {
+            return range.low..=range.high;
+        }
+        let first = self.pitch_at_x(0.0).unwrap_or(0).max(21);
+        let last = self
+            .pitch_at_x(self.screen_width - 1.0)
+            .unwrap_or(127)
+            .min(108);
+        first..=last
+    }
+}
+
+// Altezza in pixel della linea del presente (il bordo alto della tastiera)
+pub fn present_line_y() -> f32 {
+    KEYBOARD_HEIGHT
+}
+
+pub fn is_black_key(pitch: u8) -> bool {
+    matches!(pitch % 12, 1 | 3 | 6 | 8 | 10)
+}
+
+// Nome della nota nella notazione italiana, con l'ottava (60 = Do4)
+pub fn note_name(pitch: u8) -> String {
+    format!(
+        "{}{}",
+        NOTE_NAMES[pitch as usize % 12],
+        pitch as i32 / 12 - 1
+    )
+}
+
+// Numero MIDI ("60") o nome della nota ("Do4", "fa#3")
+pub fn parse_pitch(text: &str) -> Option<u8> {
+    if let Ok(pitch) = text.parse::<u8>() {
+        return (pitch <= 127).then_some(pitch);
+    }
+    let octave_start = text.find(|c: char| c.is_ascii_digit() || c == '-')?;
+    let (name, octave) = text.split_at(octave_start);
+    let index = NOTE_NAMES
+        .iter()
+        .position(|candidate| candidate.eq_ignore_ascii_case(name))?;
+    let pitch = (octave.parse::<i32>().ok()? + 1) * 12 + index as i32;
+    u8::try_from(pitch).ok().filter(|&pitch| pitch <= 127)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_range_round_trips_through_its_name() {
+        for (low, high) in [(0, 127), (0, 11), (5, 6), (21, 108), (60, 72)] {
+            let range = KeyRange { low, high };
+            assert_eq!(KeyRange::parse(&range.to_string()), Ok(range));
+        }
+        assert_eq!(
+            KeyRange::parse("Do-1-Sol9"),
+            Ok(KeyRange { low: 0, high: 127 })
+        );
+        assert_eq!(
+            KeyRange::parse("21 - 108"),
+            Ok(KeyRange { low: 21, high: 108 })
+        );
+        assert!(KeyRange::parse("Do-1").is_err());
+        assert!(KeyRange::parse("-1-5").is_err());
+    }
+}
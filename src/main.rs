@@ -1,25 +1,271 @@
+mod audio_output;
+mod cli;
+mod computer_keyboard;
 mod config;
+mod file_browser;
+mod http_api;
+mod key_lights;
+mod layout;
+mod metronome;
+mod midi_input;
 mod midi_loader;
+mod midi_output;
+mod midi_sync;
+mod osc;
+mod paths;
+mod piano_roll;
+mod playlist;
+mod png_writer;
+mod presets;
+mod recent;
+mod recording;
+mod remote;
+mod renderer;
+mod scene;
+mod scoring;
+mod screenshot;
+mod sequencer;
+mod session;
+mod shortcuts;
+mod settings;
+mod soundfont;
 mod state;
+mod synth;
+mod tempo_map;
+mod thumbnail;
+mod transport;
+mod ui;
 mod vertex;
+mod video_export;
+mod wait_mode;
 
+use cli::{Command, PlayOptions};
+use midi_loader::{Hand, Song};
+use session::Session;
+use settings::Settings;
 use pollster::block_on;
 use state::State;
+use std::path::{Path, PathBuf};
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 
+// Riassunto del brano sul terminale
+fn info_command(midi_path: &Path) -> Result<(), String> {
+    let song = midi_loader::load_midi_file(midi_path)?;
+    let duration = song.end_secs();
+    let left = song.notes.iter().filter(|n| n.hand == Hand::Left).count();
+    println!("File:       {}", midi_path.display());
+    println!(
+        "Durata:     {}:{:04.1} ({:.1} s)",
+        (duration / 60.0) as u32,
+        duration % 60.0,
+        duration
+    );
+    println!(
+        "Note:       {} (sinistra {}, destra {})",
+        song.notes.len(),
+        left,
+        song.notes.len() - left
+    );
+    let pitches = song.notes.iter().map(|n| n.pitch);
+    if let (Some(low), Some(high)) = (pitches.clone().min(), pitches.max()) {
+        println!(
+            "Estensione: {} - {} ({}-{})",
+            layout::note_name(low),
+            layout::note_name(high),
+            low,
+            high
+        );
+    }
+    let (slowest, fastest) = song.tempo_map.bpm_range();
+    if (fastest - slowest).abs() < 0.05 {
+        println!("Tempo:      {:.1} BPM", slowest);
+    } else {
+        println!("Tempo:      {:.1}-{:.1} BPM", slowest, fastest);
+    }
+    let mut signatures: Vec<String> = Vec::new();
+    for signature in song.tempo_map.time_signatures() {
+        let text = format!("{}/{}", signature.numerator, signature.denominator);
+        if signatures.last() != Some(&text) {
+            signatures.push(text);
+        }
+    }
+    println!(
+        "Battute:    {} ({})",
+        song.bar_starts().len(),
+        signatures.join(", ")
+    );
+    println!("Tracce:");
+    for (track, name) in song.track_names.iter().enumerate() {
+        let notes = song.notes.iter().filter(|n| n.track == track).count();
+        let name = if name.is_empty() { "(senza nome)" } else { name };
+        println!("  {:>2}  {:<30} {} note", track, name, notes);
+    }
+    Ok(())
+}
+
+// Crea (o trova in cache) le anteprime e ne stampa il percorso. Le cartelle
+// valgono per i file MIDI che contengono (senza sottocartelle).
+fn thumbnail_command(inputs: Vec<PathBuf>, width: u32, height: u32) -> Result<(), String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let entries = std::fs::read_dir(&input)
+                .map_err(|e| format!("Impossibile leggere '{}': {}", input.display(), e))?;
+            let mut midi_files: Vec<_> = entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| file_browser::is_midi_file(path))
+                .collect();
+            midi_files.sort();
+            files.extend(midi_files);
+        } else {
+            files.push(input);
+        }
+    }
+
+    let mut failed = 0;
+    for file in &files {
+        match thumbnail::thumbnail_path(file, width, height) {
+            Ok(thumbnail) => println!("{} -> {}", file.display(), thumbnail.display()),
+            Err(e) => {
+                eprintln!("[ATTENZIONE] {}", e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} anteprime su {} non riuscite", failed, files.len()));
+    }
+    Ok(())
+}
+
 fn main() {
+    let settings = Settings::load();
+    let cli = match cli::parse(std::env::args().skip(1).collect(), &settings) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("Errore: {}", e);
+            eprintln!("Usa --help per l'elenco dei comandi e delle opzioni");
+            std::process::exit(1);
+        }
+    };
+
+    // Comandi senza finestra
+    let result = match cli.command {
+        Command::Play(options) => return play(options, settings, cli.software),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        Command::ExportVideo {
+            midi_path,
+            output,
+            settings,
+        } => midi_loader::load_midi_file(&midi_path)
+            .and_then(|song| video_export::export_video(&song, &output, &settings)),
+        Command::ExportRoll {
+            midi_path,
+            output,
+            settings,
+        } => midi_loader::load_midi_file(&midi_path)
+            .and_then(|song| piano_roll::export_piano_roll(&song, &output, &settings)),
+        Command::Info { midi_path } => info_command(&midi_path),
+        Command::Thumbnail {
+            inputs,
+            width,
+            height,
+        } => thumbnail_command(inputs, width, height),
+    };
+    if let Err(e) = result {
+        eprintln!("Errore: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// Apre la finestra e suona il brano
+fn play(mut options: PlayOptions, settings: Settings, force_software: bool) {
+    // Una scaletta si controlla subito e si apre con lo stato, partendo
+    // dalle note dimostrative come se non ci fosse un brano
+    let playlist_path = options
+        .midi_path
+        .take_if(|path| playlist::is_playlist_file(path));
+    if let Some(path) = &playlist_path
+        && let Err(e) = playlist::read_m3u(path)
+    {
+        eprintln!("Errore: {}", e);
+        std::process::exit(1);
+    }
+
+    // Il brano si carica prima della finestra: un file sbagliato non la apre.
+    // Di una sessione per ora basta il brano, il resto arriva con lo stato.
+    let session_path = options
+        .midi_path
+        .clone()
+        .filter(|path| session::is_session_file(path));
+    let song = match &options.midi_path {
+        Some(path) if session_path.is_some() => {
+            Session::load(path).and_then(|session| session.song(path).map(|s| s.song))
+        }
+        Some(path) => midi_loader::load_midi_file(path),
+        None if playlist_path.is_some() => Ok(Song::demo()),
+        None => {
+            println!("[INFO] Nessun brano indicato, uso le note dimostrative");
+            Ok(Song::demo())
+        }
+    }
+    .and_then(|song| {
+        if options.start_secs > song.end_secs() {
+            return Err(format!(
+                "L'inizio ({:.1} s) è oltre la fine del brano ({:.1} s)",
+                options.start_secs,
+                song.end_secs()
+            ));
+        }
+        Ok(song)
+    });
+    let song = match song {
+        Ok(song) => song,
+        Err(e) => {
+            eprintln!("Errore: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title("Piano Visualizer")
-        .with_inner_size(winit::dpi::PhysicalSize::new(800, 600))
+        .with_inner_size(winit::dpi::PhysicalSize::new(
+            options.window_size.0,
+            options.window_size.1,
+        ))
+        .with_fullscreen(
+            options
+                .fullscreen
+                .then_some(winit::window::Fullscreen::Borderless(None)),
+        )
         .build(&event_loop)
         .unwrap();
 
-    let mut state = block_on(State::new(&window));
+    let mut state = match block_on(State::new(&window, song, &options, settings, force_software)) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Errore: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(path) = &session_path {
+        if let Err(e) = state.load_session(path) {
+            eprintln!("Errore: {}", e);
+            std::process::exit(1);
+        }
+        state.seek(options.start_secs);
+    }
+    if let Some(path) = &playlist_path {
+        state.open_file(path);
+    }
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
@@ -34,11 +280,15 @@ fn main() {
 
                 if !egui_consumed_event {
                     match event {
-                        WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                        WindowEvent::CloseRequested => {
+                            state.save_settings();
+                            *control_flow = ControlFlow::Exit
+                        }
                         WindowEvent::Resized(size) => state.resize(*size),
                         WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                             state.resize(**new_inner_size)
                         }
+                        WindowEvent::DroppedFile(path) => state.open_file(path),
                         _ => {}
                     }
                 }
@@ -52,37 +302,16 @@ fn main() {
                 // Il fix per il DPI
                 raw_input.pixels_per_point = Some(window.scale_factor() as f32);
                 
-                let full_output = state.egui_ctx.run(raw_input, |ctx| {
-                    // Qui costruiamo la nostra UI
-                    egui::Window::new("Impostazioni").show(ctx, |ui| {
-                        ui.label("Velocità Animazione");
-                        ui.add(
-                            egui::Slider::new(&mut state.fall_duration_secs, 0.5..=10.0)
-                                .text("Durata Caduta (sec)"),
-                        );
-                        ui.label("(Valori più bassi = più veloce)");
-                        
-                        ui.separator(); // Un separatore visivo
-
-                        ui.label("Colori Note");
-                        ui.horizontal(|ui| {
-                            ui.label("Mano Sinistra:");
-                            // --- MODIFICA QUI ---
-                            // Usiamo 'srgba' che accetta &mut Color32
-                            egui::color_picker::color_edit_button_srgba(ui, &mut state.color_left_hand, egui::color_picker::Alpha::Opaque);
-                            // --- FINE MODIFICA ---
-                        });
-                        ui.horizontal(|ui| {
-                            ui.label("Mano Destra:");
-                            // --- MODIFICA QUI ---
-                            // Usiamo 'srgba' che accetta &mut Color32
-                            egui::color_picker::color_edit_button_srgba(ui, &mut state.color_right_hand, egui::color_picker::Alpha::Opaque);
-                            // --- FINE MODIFICA ---
-                        });
-                        ui.label("(Split su Do Centrale - Tasto 60)");
-                    });
-                });
-                
+                // Cloniamo il contesto (è un Arc) così la UI può prendere `state` in mutabile
+                let egui_ctx = state.egui_ctx.clone();
+                let full_output = egui_ctx.run(raw_input, |ctx| ui::draw(ctx, &mut state));
+                if state.take_fullscreen_request() {
+                    let fullscreen = window.fullscreen().is_none();
+                    window.set_fullscreen(
+                        fullscreen.then_some(winit::window::Fullscreen::Borderless(None)),
+                    );
+                }
+
                 state
                     .egui_state
                     .handle_platform_output(&window, &state.egui_ctx, full_output.platform_output);
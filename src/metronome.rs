@@ -0,0 +1,72 @@
+// src/metronome.rs
+use crate::synth::SynthEvent;
+use crate::tempo_map::TempoMap;
+
+// Per quanto resta acceso l'indicatore visivo dopo un battito
+const FLASH_SECS: f32 = 0.1;
+
+// Metronomo che segue la mappa dei tempi del brano: i battiti cadono in
+// tempo-brano, quindi rallentano e accelerano insieme alla velocità di
+// riproduzione. Il primo battito di ogni battuta è accentato.
+pub struct Metronome {
+    pub enabled: bool,
+    // Battute di conteggio prima di far partire la riproduzione
+    pub count_in_bars: u32,
+
+    last_position: f32,
+    // Ultimo battito suonato: serve all'indicatore visivo
+    last_beat: Option<(f32, bool)>,
+}
+
+impl Metronome {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            count_in_bars: 1,
+            last_position: 0.0,
+            last_beat: None,
+        }
+    }
+
+    // Dopo un salto non si recuperano i battiti saltati
+    pub fn seek(&mut self, position_secs: f32) {
+        self.last_position = position_secs;
+        self.last_beat = None;
+    }
+
+    // Emette un click per ogni battito superato dall'ultima chiamata
+    pub fn advance(
+        &mut self,
+        position_secs: f32,
+        tempo_map: &TempoMap,
+        mut emit: impl FnMut(SynthEvent),
+    ) {
+        if position_secs <= self.last_position {
+            self.last_position = position_secs;
+            return;
+        }
+        for beat in tempo_map.beats_between(self.last_position, position_secs) {
+            if self.enabled {
+                emit(SynthEvent::Click {
+                    accent: beat.downbeat,
+                });
+            }
+            self.last_beat = Some((beat.time_secs, beat.downbeat));
+        }
+        self.last_position = position_secs;
+    }
+
+    // Se l'indicatore deve essere acceso ora: `Some(true)` sul primo battito
+    pub fn flash(&self, position_secs: f32) -> Option<bool> {
+        let (time, downbeat) = self.last_beat?;
+        (self.enabled && position_secs - time < FLASH_SECS).then_some(downbeat)
+    }
+
+    // Dove far partire la riproduzione per avere il conteggio prima di `position_secs`
+    pub fn count_in_start(&self, position_secs: f32, tempo_map: &TempoMap) -> f32 {
+        if !self.enabled || self.count_in_bars == 0 {
+            return position_secs;
+        }
+        position_secs - self.count_in_bars as f32 * tempo_map.bar_secs_at(position_secs)
+    }
+}
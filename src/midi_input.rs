@@ -0,0 +1,129 @@
+// src/midi_input.rs
+// Senza la feature `midi-io` nessun messaggio viene decodificato: restano
+// solo gli eventi iniettati dalla tastiera del computer.
+#![cfg_attr(not(feature = "midi-io"), allow(dead_code))]
+use std::sync::mpsc::{self, Receiver, Sender};
+
+// Un evento nota ricevuto dallo strumento dello studente
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteEvent {
+    On { pitch: u8, velocity: u8 },
+    Off { pitch: u8 },
+}
+
+// Decodifica un messaggio MIDI grezzo. Ci interessano solo Note On / Note Off
+// (su qualsiasi canale); tutto il resto viene ignorato.
+pub fn parse_note_message(bytes: &[u8]) -> Option<NoteEvent> {
+    match *bytes {
+        [status, pitch, velocity] if status & 0xF0 == 0x90 && velocity > 0 => {
+            Some(NoteEvent::On { pitch, velocity })
+        }
+        // "NoteOn" con velocity 0 è una "NoteOff"
+        [status, pitch, _] if status & 0xF0 == 0x80 || status & 0xF0 == 0x90 => {
+            Some(NoteEvent::Off { pitch })
+        }
+        _ => None,
+    }
+}
+
+// Connessione a una porta di ingresso MIDI.
+// Il callback di midir gira su un thread separato: gli eventi arrivano
+// al thread principale attraverso un canale e vengono letti con `poll`.
+// Sullo stesso canale arrivano le note suonate con tastiera del computer e mouse.
+pub struct MidiInput {
+    sender: Sender<NoteEvent>,
+    receiver: Receiver<NoteEvent>,
+    #[cfg(feature = "midi-io")]
+    connection: Option<midir::MidiInputConnection<()>>,
+    pub connected_port: Option<String>,
+}
+
+impl MidiInput {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            #[cfg(feature = "midi-io")]
+            connection: None,
+            connected_port: None,
+        }
+    }
+
+    // Elenca i nomi delle porte di ingresso disponibili
+    #[cfg(feature = "midi-io")]
+    pub fn port_names() -> Vec<String> {
+        match midir::MidiInput::new("Piano Visualizer") {
+            Ok(midi_in) => midi_in
+                .ports()
+                .iter()
+                .filter_map(|port| midi_in.port_name(port).ok())
+                .collect(),
+            Err(e) => {
+                eprintln!("[ATTENZIONE] Ingresso MIDI non disponibile: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    #[cfg(not(feature = "midi-io"))]
+    pub fn port_names() -> Vec<String> {
+        Vec::new()
+    }
+
+    #[cfg(feature = "midi-io")]
+    pub fn connect(&mut self, port_name: &str) -> Result<(), String> {
+        self.disconnect();
+
+        let midi_in = midir::MidiInput::new("Piano Visualizer").map_err(|e| e.to_string())?;
+        let port = midi_in
+            .ports()
+            .into_iter()
+            .find(|port| midi_in.port_name(port).ok().as_deref() == Some(port_name))
+            .ok_or_else(|| format!("Porta MIDI '{}' non trovata", port_name))?;
+
+        let sender = self.sender.clone();
+        let connection = midi_in
+            .connect(
+                &port,
+                "piano-visualizer-in",
+                move |_timestamp, message, _| {
+                    if let Some(event) = parse_note_message(message) {
+                        // Se il thread principale è già terminato non c'è nulla da fare
+                        let _ = sender.send(event);
+                    }
+                },
+                (),
+            )
+            .map_err(|e| e.to_string())?;
+
+        println!("[INFO] Connesso all'ingresso MIDI '{}'", port_name);
+        self.connection = Some(connection);
+        self.connected_port = Some(port_name.to_string());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "midi-io"))]
+    pub fn connect(&mut self, _port_name: &str) -> Result<(), String> {
+        Err("Supporto MIDI non compilato (abilitare la feature `midi-io`)".to_string())
+    }
+
+    pub fn disconnect(&mut self) {
+        #[cfg(feature = "midi-io")]
+        if let Some(connection) = self.connection.take() {
+            connection.close();
+        }
+        self.connected_port = None;
+    }
+
+    // Accoda un evento come se fosse arrivato dalla porta MIDI
+    pub fn inject(&self, event: NoteEvent) {
+        // Il ricevitore vive nella stessa struct: l'invio non può fallire
+        let _ = self.sender.send(event);
+    }
+
+    // Restituisce gli eventi arrivati dall'ultimo frame
+    pub fn poll(&self) -> impl Iterator<Item = NoteEvent> + '_ {
+        self.receiver.try_iter()
+    }
+}
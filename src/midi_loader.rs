@@ -1,68 +1,224 @@
 // src/midi_loader.rs
+use crate::config::HAND_SPLIT_PITCH;
+use crate::tempo_map::TempoMap;
 use midly::{Smf, TrackEventKind};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 // Una struct per contenere i dati puliti estratti dal MIDI
 #[derive(Debug, Clone)]
 pub struct MidiNote {
+    pub track: usize,
+    pub channel: u8,
     pub pitch: u8,
     pub velocity: u8,
     pub start_time_secs: f32,
     pub duration_secs: f32,
+    // Decisa da Song::assign_hands (all'inizio con lo split sul Do centrale)
+    pub hand: Hand,
 }
 
-// Converte i "tick" del MIDI in secondi
-fn ticks_to_secs(ticks: u32, ticks_per_beat: u16, microsecs_per_beat: u32) -> f32 {
-    ((ticks as f64 * (microsecs_per_beat as f64 / 1_000_000.0)) / ticks_per_beat as f64) as f32
+// Pressione o rilascio del pedale di risonanza (CC 64)
+#[derive(Debug, Clone)]
+pub struct PedalEvent {
+    pub time_secs: f32,
+    pub channel: u8,
+    pub down: bool,
 }
 
-pub fn load_midi_file(path: &std::path::Path) -> Vec<MidiNote> {
-    // Carica i byte del file
-    let data = std::fs::read(path).expect("Impossibile leggere il file MIDI");
-    let smf = Smf::parse(&data).expect("Impossibile parsare il file MIDI");
+// Cambio di strumento su un canale (bank select CC 0 + program change)
+#[derive(Debug, Clone)]
+pub struct ProgramChange {
+    pub time_secs: f32,
+    pub channel: u8,
+    pub bank: u8,
+    pub program: u8,
+}
 
-    let mut notes = Vec::new();
+// Evento di strumento letto da una traccia, prima di risolvere il banco
+enum InstrumentEvent {
+    Bank(u8),
+    Program(u8),
+}
 
-    // 1. Estrai l'impostazione "ticks per beat" (Tpq) dall'header
-    let ticks_per_beat = match smf.header.timing {
-        midly::Timing::Metrical(tpq) => tpq.as_int(),
-        _ => 480, // Fallback comune
-    };
+// Il brano caricato: le note più gli eventi che servono per suonarlo
+#[derive(Debug, Clone, Default)]
+pub struct Song {
+    pub notes: Vec<MidiNote>,
+    pub pedal_events: Vec<PedalEvent>,
+    pub program_changes: Vec<ProgramChange>,
+    // Nome di ogni traccia del file (vuoto se la traccia non ne ha uno)
+    pub track_names: Vec<String>,
+    pub tempo_map: TempoMap,
+    pub hands: HandAssignment,
+}
 
-    // 2. Trova tutti gli eventi di cambio Tempo (BPM)
-    // Il MIDI memorizza il tempo come "microsecondi per beat"
-    let mut tempo_changes = Vec::new();
-    for event in &smf.tracks[0] {
-        if let TrackEventKind::Meta(midly::MetaMessage::Tempo(us_per_beat)) = event.kind {
-            tempo_changes.push((event.delta.as_int(), us_per_beat.as_int()));
+impl Song {
+    // Poche note di prova, per quando non si apre nessun file
+    pub fn demo() -> Self {
+        // Le note devono restare ordinate per tempo di inizio
+        let notes = vec![
+            MidiNote {
+                track: 0,
+                channel: 0,
+                pitch: 60,
+                velocity: 100,
+                start_time_secs: 2.0,
+                duration_secs: 1.0,
+                hand: Hand::for_pitch(60, HAND_SPLIT_PITCH),
+            },
+            // Aggiungiamo una nota per la mano sinistra per test
+            MidiNote {
+                track: 0,
+                channel: 0,
+                pitch: 48, // Sotto il Do centrale
+                velocity: 100,
+                start_time_secs: 2.5,
+                duration_secs: 1.0,
+                hand: Hand::for_pitch(48, HAND_SPLIT_PITCH),
+            },
+            MidiNote {
+                track: 0,
+                channel: 0,
+                pitch: 62,
+                velocity: 100,
+                start_time_secs: 3.0,
+                duration_secs: 0.5,
+                hand: Hand::for_pitch(62, HAND_SPLIT_PITCH),
+            },
+            MidiNote {
+                track: 0,
+                channel: 0,
+                pitch: 64,
+                velocity: 100,
+                start_time_secs: 4.0,
+                duration_secs: 1.5,
+                hand: Hand::for_pitch(64, HAND_SPLIT_PITCH),
+            },
+        ];
+        Song {
+            notes,
+            ..Default::default()
+        }
+    }
+
+    // Fine dell'ultima nota del brano
+    pub fn end_secs(&self) -> f32 {
+        self.notes
+            .iter()
+            .map(|n| n.start_time_secs + n.duration_secs)
+            .fold(0.0, f32::max)
+    }
+
+    // Note che stanno suonando all'istante dato
+    pub fn sounding_at(&self, time_secs: f32) -> impl Iterator<Item = &MidiNote> {
+        // Le note sono ordinate per inizio: quelle successive non contano
+        let end = self.notes.partition_point(|n| n.start_time_secs <= time_secs);
+        self.notes[..end]
+            .iter()
+            .filter(move |n| time_secs < n.start_time_secs + n.duration_secs)
+    }
+
+    // Inizio (in secondi) di ogni battuta fino alla fine del brano
+    pub fn bar_starts(&self) -> Vec<f32> {
+        self.tempo_map
+            .beats_between(0.0, self.end_secs())
+            .into_iter()
+            .filter(|beat| beat.downbeat)
+            .map(|beat| beat.time_secs)
+            .collect()
+    }
+
+    // Riassegna le mani di tutte le note
+    pub fn assign_hands(&mut self, hands: HandAssignment) {
+        for note in &mut self.notes {
+            note.hand = hands.hand_of(note.track, note.pitch);
+        }
+        self.hands = hands;
+    }
+}
+
+// La mano che suona una nota
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+impl Hand {
+    // Le note sotto `split_pitch` sono della mano sinistra
+    pub fn for_pitch(pitch: u8, split_pitch: u8) -> Self {
+        if pitch < split_pitch {
+            Hand::Left
+        } else {
+            Hand::Right
+        }
+    }
+}
+
+// Come si dividono le note fra le mani: le tracce assegnate vanno per
+// intero a una mano, tutte le altre si dividono con lo split
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct HandAssignment {
+    pub split_pitch: u8,
+    pub tracks: BTreeMap<usize, Hand>,
+}
+
+impl Default for HandAssignment {
+    fn default() -> Self {
+        Self {
+            split_pitch: HAND_SPLIT_PITCH,
+            tracks: BTreeMap::new(),
         }
     }
-    // Se non ci sono cambi di tempo, usa il default MIDI (120 BPM)
-    if tempo_changes.is_empty() {
-        tempo_changes.push((0, 500_000)); // 120 BPM = 500,000 µs per beat
+}
+
+impl HandAssignment {
+    pub fn hand_of(&self, track: usize, pitch: u8) -> Hand {
+        self.tracks
+            .get(&track)
+            .copied()
+            .unwrap_or_else(|| Hand::for_pitch(pitch, self.split_pitch))
     }
+}
+
+pub fn load_midi_file(path: &std::path::Path) -> Result<Song, String> {
+    // Carica i byte del file
+    let data = std::fs::read(path)
+        .map_err(|e| format!("Impossibile leggere '{}': {}", path.display(), e))?;
+    parse_midi(&data).map_err(|e| format!("'{}': {}", path.display(), e))
+}
+
+// Decodifica un file MIDI già in memoria
+pub fn parse_midi(data: &[u8]) -> Result<Song, String> {
+    let smf = Smf::parse(data).map_err(|e| format!("File MIDI non valido: {}", e))?;
 
-    // 3. Itera su tutte le tracce per trovare le note
-    let mut current_ticks_total: u32 = 0;
-    let mut current_us_per_beat: u32 = tempo_changes[0].1;
-    let mut tempo_iter = tempo_changes.iter().peekable();
+    let mut notes = Vec::new();
+    let mut pedal_events = Vec::new();
+    // Bank select e program change di tutte le tracce: il banco si risolve
+    // solo alla fine, quando gli eventi sono in ordine di tempo
+    let mut instrument_events: Vec<(u32, u8, InstrumentEvent)> = Vec::new();
+
+    // 1. Tempo (BPM) e indicazioni di tempo da tutte le tracce.
+    // Il MIDI memorizza il tempo come "microsecondi per beat"
+    let tempo_map = TempoMap::from_smf(&smf);
 
+    // 2. Itera su tutte le tracce per trovare le note
     // Mappa per tenere traccia delle note "NoteOn" in attesa del loro "NoteOff"
     // Key = (channel, pitch), Value = (start_tick, velocity)
     let mut pending_notes: HashMap<(u8, u8), (u32, u8)> = HashMap::new();
 
-    for track in &smf.tracks {
-        current_ticks_total = 0;
+    let mut track_names = vec![String::new(); smf.tracks.len()];
+
+    for (track_index, track) in smf.tracks.iter().enumerate() {
+        let mut current_ticks_total: u32 = 0;
 
         for event in track {
             current_ticks_total += event.delta.as_int();
 
-            // Aggiorna il tempo (BPM) se necessario
-            if let Some((delta, us_per_beat)) = tempo_iter.peek() {
-                if current_ticks_total >= *delta {
-                    current_us_per_beat = *us_per_beat;
-                    tempo_iter.next();
-                }
+            if let TrackEventKind::Meta(midly::MetaMessage::TrackName(name)) = event.kind {
+                track_names[track_index] = String::from_utf8_lossy(name).trim().to_string();
             }
 
             if let TrackEventKind::Midi { channel, message } = event.kind {
@@ -76,20 +232,15 @@ pub fn load_midi_file(path: &std::path::Path) -> Vec<MidiNote> {
                             if let Some((start_tick, old_vel)) =
                                 pending_notes.remove(&(channel.as_int(), pitch))
                             {
-                                let duration_ticks = current_ticks_total - start_tick;
                                 notes.push(MidiNote {
+                                    track: track_index,
+                                    channel: channel.as_int(),
                                     pitch,
                                     velocity: old_vel,
-                                    start_time_secs: ticks_to_secs(
-                                        start_tick,
-                                        ticks_per_beat,
-                                        current_us_per_beat,
-                                    ),
-                                    duration_secs: ticks_to_secs(
-                                        duration_ticks,
-                                        ticks_per_beat,
-                                        current_us_per_beat,
-                                    ),
+                                    start_time_secs: tempo_map.ticks_to_secs(start_tick),
+                                    duration_secs: tempo_map.ticks_to_secs(current_ticks_total)
+                                        - tempo_map.ticks_to_secs(start_tick),
+                                    hand: Hand::for_pitch(pitch, HAND_SPLIT_PITCH),
                                 });
                             }
                             pending_notes
@@ -99,20 +250,15 @@ pub fn load_midi_file(path: &std::path::Path) -> Vec<MidiNote> {
                             if let Some((start_tick, velocity)) =
                                 pending_notes.remove(&(channel.as_int(), pitch))
                             {
-                                let duration_ticks = current_ticks_total - start_tick;
                                 notes.push(MidiNote {
+                                    track: track_index,
+                                    channel: channel.as_int(),
                                     pitch,
                                     velocity,
-                                    start_time_secs: ticks_to_secs(
-                                        start_tick,
-                                        ticks_per_beat,
-                                        current_us_per_beat,
-                                    ),
-                                    duration_secs: ticks_to_secs(
-                                        duration_ticks,
-                                        ticks_per_beat,
-                                        current_us_per_beat,
-                                    ),
+                                    start_time_secs: tempo_map.ticks_to_secs(start_tick),
+                                    duration_secs: tempo_map.ticks_to_secs(current_ticks_total)
+                                        - tempo_map.ticks_to_secs(start_tick),
+                                    hand: Hand::for_pitch(pitch, HAND_SPLIT_PITCH),
                                 });
                             }
                         }
@@ -123,30 +269,82 @@ pub fn load_midi_file(path: &std::path::Path) -> Vec<MidiNote> {
                         if let Some((start_tick, velocity)) =
                             pending_notes.remove(&(channel.as_int(), pitch))
                         {
-                            let duration_ticks = current_ticks_total - start_tick;
                             notes.push(MidiNote {
+                                track: track_index,
+                                channel: channel.as_int(),
                                 pitch,
                                 velocity,
-                                start_time_secs: ticks_to_secs(
-                                    start_tick,
-                                    ticks_per_beat,
-                                    current_us_per_beat,
-                                ),
-                                duration_secs: ticks_to_secs(
-                                    duration_ticks,
-                                    ticks_per_beat,
-                                    current_us_per_beat,
-                                ),
+                                start_time_secs: tempo_map.ticks_to_secs(start_tick),
+                                duration_secs: tempo_map.ticks_to_secs(current_ticks_total)
+                                    - tempo_map.ticks_to_secs(start_tick),
+                                hand: Hand::for_pitch(pitch, HAND_SPLIT_PITCH),
                             });
                         }
                     }
+                    midly::MidiMessage::Controller { controller, value }
+                        if controller.as_int() == 64 =>
+                    {
+                        // Pedale di risonanza: da 64 in su è premuto
+                        pedal_events.push(PedalEvent {
+                            time_secs: tempo_map.ticks_to_secs(current_ticks_total),
+                            channel: channel.as_int(),
+                            down: value.as_int() >= 64,
+                        });
+                    }
+                    midly::MidiMessage::Controller { controller, value }
+                        if controller.as_int() == 0 =>
+                    {
+                        instrument_events.push((
+                            current_ticks_total,
+                            channel.as_int(),
+                            InstrumentEvent::Bank(value.as_int()),
+                        ));
+                    }
+                    midly::MidiMessage::ProgramChange { program } => {
+                        instrument_events.push((
+                            current_ticks_total,
+                            channel.as_int(),
+                            InstrumentEvent::Program(program.as_int()),
+                        ));
+                    }
                     _ => {}
                 }
             }
         }
     }
 
+    // 3. Risolve il banco di ogni program change. A parità di tick i bank
+    // select vengono prima, anche se stanno in un'altra traccia
+    // (l'ordinamento è stabile: dentro una traccia l'ordine resta quello del file)
+    instrument_events.sort_by_key(|(tick, _, event)| {
+        (*tick, matches!(event, InstrumentEvent::Program(_)))
+    });
+    // Ultimo bank select visto su ogni canale
+    let mut banks: HashMap<u8, u8> = HashMap::new();
+    let mut program_changes = Vec::new();
+    for (tick, channel, event) in instrument_events {
+        match event {
+            InstrumentEvent::Bank(bank) => {
+                banks.insert(channel, bank);
+            }
+            InstrumentEvent::Program(program) => program_changes.push(ProgramChange {
+                time_secs: tempo_map.ticks_to_secs(tick),
+                channel,
+                bank: banks.get(&channel).copied().unwrap_or(0),
+                program,
+            }),
+        }
+    }
+
     // Ordina le note per tempo di inizio
     notes.sort_by(|a, b| a.start_time_secs.partial_cmp(&b.start_time_secs).unwrap());
-    notes
+    pedal_events.sort_by(|a, b| a.time_secs.partial_cmp(&b.time_secs).unwrap());
+    Ok(Song {
+        notes,
+        pedal_events,
+        program_changes,
+        track_names,
+        tempo_map,
+        hands: HandAssignment::default(),
+    })
 }
@@ -0,0 +1,155 @@
+// src/midi_output.rs
+// Senza la feature `midi-io` non si apre nessuna porta: il resto
+// del modulo resta compilato ma inutilizzato.
+#![cfg_attr(not(feature = "midi-io"), allow(dead_code))]
+use crate::synth::SynthEvent;
+use std::collections::BTreeSet;
+
+// Traduce un evento del sequencer nei messaggi MIDI grezzi corrispondenti
+pub fn encode_event(event: SynthEvent) -> Vec<[u8; 3]> {
+    match event {
+        SynthEvent::NoteOn {
+            channel,
+            pitch,
+            velocity,
+        } => vec![[0x90 | channel, pitch, velocity]],
+        SynthEvent::NoteOff { channel, pitch } => vec![[0x80 | channel, pitch, 0]],
+        SynthEvent::Sustain { channel, down } => {
+            vec![[0xB0 | channel, 64, if down { 127 } else { 0 }]]
+        }
+        // Il program change ha un solo byte di dati: l'ultimo byte va scartato
+        SynthEvent::ProgramChange {
+            channel,
+            bank,
+            program,
+        } => vec![[0xB0 | channel, 0, bank], [0xC0 | channel, program, 0]],
+        // Gestiti a parte (AllNotesOff) o solo per l'audio interno
+        SynthEvent::AllNotesOff | SynthEvent::SetVolume(_) | SynthEvent::Click { .. } => Vec::new(),
+    }
+}
+
+fn message_len(status: u8) -> usize {
+    match status & 0xF0 {
+        0xC0 | 0xD0 => 2,
+        _ => 3,
+    }
+}
+
+// Connessione a una porta di uscita MIDI (synth esterno, pianoforte digitale)
+pub struct MidiOutput {
+    #[cfg(feature = "midi-io")]
+    connection: Option<midir::MidiOutputConnection>,
+    pub connected_port: Option<String>,
+    // Note accese e non ancora spente, per poterle spegnere una per una:
+    // non tutti gli strumenti rispettano il messaggio "All Notes Off"
+    sounding: BTreeSet<(u8, u8)>,
+}
+
+impl MidiOutput {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "midi-io")]
+            connection: None,
+            connected_port: None,
+            sounding: BTreeSet::new(),
+        }
+    }
+
+    // Elenca i nomi delle porte di uscita disponibili
+    #[cfg(feature = "midi-io")]
+    pub fn port_names() -> Vec<String> {
+        match midir::MidiOutput::new("Piano Visualizer") {
+            Ok(midi_out) => midi_out
+                .ports()
+                .iter()
+                .filter_map(|port| midi_out.port_name(port).ok())
+                .collect(),
+            Err(e) => {
+                eprintln!("[ATTENZIONE] Uscita MIDI non disponibile: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    #[cfg(not(feature = "midi-io"))]
+    pub fn port_names() -> Vec<String> {
+        Vec::new()
+    }
+
+    #[cfg(feature = "midi-io")]
+    pub fn connect(&mut self, port_name: &str) -> Result<(), String> {
+        self.disconnect();
+
+        let midi_out = midir::MidiOutput::new("Piano Visualizer").map_err(|e| e.to_string())?;
+        let port = midi_out
+            .ports()
+            .into_iter()
+            .find(|port| midi_out.port_name(port).ok().as_deref() == Some(port_name))
+            .ok_or_else(|| format!("Porta MIDI '{}' non trovata", port_name))?;
+        let connection = midi_out
+            .connect(&port, "piano-visualizer-out")
+            .map_err(|e| e.to_string())?;
+
+        println!("[INFO] Connesso all'uscita MIDI '{}'", port_name);
+        self.connection = Some(connection);
+        self.connected_port = Some(port_name.to_string());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "midi-io"))]
+    pub fn connect(&mut self, _port_name: &str) -> Result<(), String> {
+        Err("Supporto MIDI non compilato (abilitare la feature `midi-io`)".to_string())
+    }
+
+    pub fn disconnect(&mut self) {
+        self.all_notes_off();
+        #[cfg(feature = "midi-io")]
+        if let Some(connection) = self.connection.take() {
+            connection.close();
+        }
+        self.connected_port = None;
+    }
+
+    pub fn send_event(&mut self, event: SynthEvent) {
+        match event {
+            SynthEvent::NoteOn { channel, pitch, .. } => {
+                self.sounding.insert((channel, pitch));
+            }
+            SynthEvent::NoteOff { channel, pitch } => {
+                self.sounding.remove(&(channel, pitch));
+            }
+            SynthEvent::AllNotesOff => {
+                self.all_notes_off();
+                return;
+            }
+            _ => {}
+        }
+        for message in encode_event(event) {
+            self.send_raw(&message[..message_len(message[0])]);
+        }
+    }
+
+    // Spegne tutto: prima le note accese una per una, poi pedale e
+    // "All Notes Off" (CC 123) su tutti i 16 canali
+    pub fn all_notes_off(&mut self) {
+        for (channel, pitch) in std::mem::take(&mut self.sounding) {
+            self.send_raw(&[0x80 | channel, pitch, 0]);
+        }
+        for channel in 0..16u8 {
+            self.send_raw(&[0xB0 | channel, 64, 0]);
+            self.send_raw(&[0xB0 | channel, 123, 0]);
+        }
+    }
+
+    #[cfg(feature = "midi-io")]
+    fn send_raw(&mut self, message: &[u8]) {
+        if let Some(connection) = &mut self.connection
+            && let Err(e) = connection.send(message)
+        {
+            eprintln!("[ATTENZIONE] Errore invio MIDI: {}", e);
+        }
+    }
+
+    #[cfg(not(feature = "midi-io"))]
+    fn send_raw(&mut self, _message: &[u8]) {}
+}
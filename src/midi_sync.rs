@@ -0,0 +1,348 @@
+// src/midi_sync.rs
+// Senza la feature `midi-io` non si apre nessuna porta: il resto
+// del modulo resta compilato ma inutilizzato.
+#![cfg_attr(not(feature = "midi-io"), allow(dead_code))]
+use crate::tempo_map::TempoMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+// Il clock MIDI manda 24 impulsi per semiminima; lo Song Position Pointer
+// conta in "MIDI beat" da 6 impulsi (una semicroma)
+const CLOCKS_PER_QUARTER: f64 = 24.0;
+const CLOCKS_PER_MIDI_BEAT: u64 = 6;
+// Peso di ogni nuovo intervallo nella media del periodo del clock
+const CLOCK_SMOOTHING: f64 = 0.1;
+// Senza quarter frame per questo tempo l'MTC si considera fermo
+const MTC_TIMEOUT: Duration = Duration::from_millis(200);
+
+// Messaggi di sincronizzazione che ci interessano
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMessage {
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    // Posizione in MIDI beat (semicrome) dall'inizio del brano
+    SongPosition(u16),
+    // Un pezzo del timecode MTC (byte dati del messaggio F1)
+    QuarterFrame(u8),
+    // Timecode completo inviato con un locate a trasporto fermo
+    FullFrame(Timecode),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    // Codice del frame rate MTC: 0 = 24, 1 = 25, 2 = 29.97 drop, 3 = 30
+    pub rate: u8,
+}
+
+impl Timecode {
+    pub fn fps(&self) -> f64 {
+        match self.rate {
+            0 => 24.0,
+            1 => 25.0,
+            2 => 29.97,
+            _ => 30.0,
+        }
+    }
+
+    fn secs(&self) -> f64 {
+        self.hours as f64 * 3600.0
+            + self.minutes as f64 * 60.0
+            + self.seconds as f64
+            + self.frames as f64 / self.fps()
+    }
+}
+
+pub fn parse_sync_message(bytes: &[u8]) -> Option<SyncMessage> {
+    match *bytes {
+        [0xF8, ..] => Some(SyncMessage::Clock),
+        [0xFA, ..] => Some(SyncMessage::Start),
+        [0xFB, ..] => Some(SyncMessage::Continue),
+        [0xFC, ..] => Some(SyncMessage::Stop),
+        [0xF2, lsb, msb] => Some(SyncMessage::SongPosition(
+            (msb as u16 & 0x7F) << 7 | (lsb as u16 & 0x7F),
+        )),
+        [0xF1, data] => Some(SyncMessage::QuarterFrame(data)),
+        // SysEx real-time universale "MTC full frame"
+        [
+            0xF0,
+            0x7F,
+            _,
+            0x01,
+            0x01,
+            hours,
+            minutes,
+            seconds,
+            frames,
+            0xF7,
+        ] => Some(SyncMessage::FullFrame(Timecode {
+            hours: hours & 0x1F,
+            minutes,
+            seconds,
+            frames,
+            rate: (hours >> 5) & 0x03,
+        })),
+        _ => None,
+    }
+}
+
+// Dove si trova la sorgente esterna e se sta suonando
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncPosition {
+    pub position_secs: f32,
+    pub running: bool,
+}
+
+// Da quale tipo di sincronizzazione arriva l'ultima informazione
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncSource {
+    Clock,
+    Mtc,
+}
+
+// Ricostruisce la posizione della sorgente esterna dai messaggi ricevuti.
+// Fra un impulso e l'altro la posizione viene interpolata con il periodo
+// medio del clock (o con il tempo trascorso per l'MTC): gli scatti dovuti al
+// jitter vengono poi assorbiti da `Transport::follow`.
+pub struct SyncFollower {
+    pub source: Option<SyncSource>,
+
+    // --- Clock MIDI ---
+    clocks: u64,
+    clock_running: bool,
+    // Dopo Start/Continue il primo impulso segna la posizione corrente
+    waiting_first_clock: bool,
+    last_clock: Option<Instant>,
+    clock_interval_secs: f64,
+
+    // --- MTC ---
+    quarter_frames: [u8; 8],
+    mtc_base_secs: f64,
+    mtc_base_instant: Option<Instant>,
+    last_quarter_frame: Option<Instant>,
+    pub mtc_rate: Option<f64>,
+}
+
+impl SyncFollower {
+    pub fn new() -> Self {
+        Self {
+            source: None,
+            clocks: 0,
+            clock_running: false,
+            waiting_first_clock: false,
+            last_clock: None,
+            // 120 BPM finché non si misura il periodo vero
+            clock_interval_secs: 0.5 / CLOCKS_PER_QUARTER,
+            quarter_frames: [0; 8],
+            mtc_base_secs: 0.0,
+            mtc_base_instant: None,
+            last_quarter_frame: None,
+            mtc_rate: None,
+        }
+    }
+
+    // Tempo della sorgente misurato dal clock
+    pub fn clock_bpm(&self) -> f64 {
+        60.0 / (self.clock_interval_secs * CLOCKS_PER_QUARTER)
+    }
+
+    pub fn handle(&mut self, message: SyncMessage, at: Instant) {
+        match message {
+            SyncMessage::Clock => {
+                if let Some(previous) = self.last_clock {
+                    let interval = at.duration_since(previous).as_secs_f64();
+                    // Una pausa lunga nel flusso non è un cambio di tempo
+                    if interval > 0.0 && interval < 0.25 {
+                        self.clock_interval_secs +=
+                            (interval - self.clock_interval_secs) * CLOCK_SMOOTHING;
+                    }
+                }
+                self.last_clock = Some(at);
+                if self.clock_running {
+                    if self.waiting_first_clock {
+                        self.waiting_first_clock = false;
+                    } else {
+                        self.clocks += 1;
+                    }
+                }
+                self.source = Some(SyncSource::Clock);
+            }
+            SyncMessage::Start | SyncMessage::Continue => {
+                if message == SyncMessage::Start {
+                    self.clocks = 0;
+                }
+                self.clock_running = true;
+                self.waiting_first_clock = true;
+                self.source = Some(SyncSource::Clock);
+            }
+            SyncMessage::Stop => self.clock_running = false,
+            SyncMessage::SongPosition(midi_beats) => {
+                self.clocks = midi_beats as u64 * CLOCKS_PER_MIDI_BEAT;
+                self.source = Some(SyncSource::Clock);
+            }
+            SyncMessage::QuarterFrame(data) => {
+                let piece = (data >> 4) as usize & 0x07;
+                self.quarter_frames[piece] = data & 0x0F;
+                self.last_quarter_frame = Some(at);
+                // Con l'ultimo pezzo il timecode è completo: si riferisce a
+                // quando è arrivato il primo, cioè due frame fa
+                if piece == 7 {
+                    let q = &self.quarter_frames;
+                    let timecode = Timecode {
+                        frames: q[0] | (q[1] & 0x01) << 4,
+                        seconds: q[2] | (q[3] & 0x03) << 4,
+                        minutes: q[4] | (q[5] & 0x03) << 4,
+                        hours: q[6] | (q[7] & 0x01) << 4,
+                        rate: (q[7] >> 1) & 0x03,
+                    };
+                    self.mtc_base_secs = timecode.secs() + 2.0 / timecode.fps();
+                    self.mtc_base_instant = Some(at);
+                    self.mtc_rate = Some(timecode.fps());
+                    self.source = Some(SyncSource::Mtc);
+                }
+            }
+            SyncMessage::FullFrame(timecode) => {
+                self.mtc_base_secs = timecode.secs();
+                self.mtc_base_instant = None;
+                self.last_quarter_frame = None;
+                self.mtc_rate = Some(timecode.fps());
+                self.source = Some(SyncSource::Mtc);
+            }
+        }
+    }
+
+    // Posizione nel brano ricostruita all'istante `now`. Per l'MTC il
+    // timecode diventa tempo del brano togliendo `mtc_offset_secs`.
+    pub fn position(
+        &self,
+        now: Instant,
+        tempo_map: &TempoMap,
+        mtc_offset_secs: f32,
+    ) -> Option<SyncPosition> {
+        match self.source? {
+            SyncSource::Clock => {
+                let running = self.clock_running && !self.waiting_first_clock;
+                let mut clocks = self.clocks as f64;
+                if running && let Some(last_clock) = self.last_clock {
+                    // Al massimo un impulso avanti: se il clock si ferma ci si ferma
+                    let elapsed = now.duration_since(last_clock).as_secs_f64();
+                    clocks += (elapsed / self.clock_interval_secs).min(1.0);
+                }
+                Some(SyncPosition {
+                    position_secs: tempo_map.quarter_notes_to_secs(clocks / CLOCKS_PER_QUARTER),
+                    running,
+                })
+            }
+            SyncSource::Mtc => {
+                let running = self
+                    .last_quarter_frame
+                    .is_some_and(|t| now.duration_since(t) < MTC_TIMEOUT);
+                let mut secs = self.mtc_base_secs;
+                if running && let Some(base) = self.mtc_base_instant {
+                    secs += now.duration_since(base).as_secs_f64();
+                }
+                Some(SyncPosition {
+                    position_secs: secs as f32 - mtc_offset_secs,
+                    running,
+                })
+            }
+        }
+    }
+}
+
+// Ingresso MIDI dedicato alla sincronizzazione (di solito una porta
+// virtuale della DAW, distinta dalla tastiera dello studente)
+pub struct MidiSync {
+    #[cfg(feature = "midi-io")]
+    sender: Sender<(SyncMessage, Instant)>,
+    receiver: Receiver<(SyncMessage, Instant)>,
+    #[cfg(feature = "midi-io")]
+    connection: Option<midir::MidiInputConnection<()>>,
+    pub connected_port: Option<String>,
+    pub follower: SyncFollower,
+    // Timecode che corrisponde all'inizio del brano (spesso 01:00:00:00)
+    pub mtc_offset_secs: f32,
+}
+
+impl MidiSync {
+    pub fn new() -> Self {
+        #[cfg_attr(not(feature = "midi-io"), allow(unused_variables))]
+        let (sender, receiver): (Sender<(SyncMessage, Instant)>, _) = mpsc::channel();
+        Self {
+            #[cfg(feature = "midi-io")]
+            sender,
+            receiver,
+            #[cfg(feature = "midi-io")]
+            connection: None,
+            connected_port: None,
+            follower: SyncFollower::new(),
+            mtc_offset_secs: 0.0,
+        }
+    }
+
+    #[cfg(feature = "midi-io")]
+    pub fn connect(&mut self, port_name: &str) -> Result<(), String> {
+        self.disconnect();
+
+        let mut midi_in = midir::MidiInput::new("Piano Visualizer").map_err(|e| e.to_string())?;
+        // Clock e SysEx servono tutti
+        midi_in.ignore(midir::Ignore::None);
+        let port = midi_in
+            .ports()
+            .into_iter()
+            .find(|port| midi_in.port_name(port).ok().as_deref() == Some(port_name))
+            .ok_or_else(|| format!("Porta MIDI '{}' non trovata", port_name))?;
+
+        let sender = self.sender.clone();
+        let connection = midi_in
+            .connect(
+                &port,
+                "piano-visualizer-sync",
+                move |_timestamp, message, _| {
+                    // L'istante di arrivo va preso subito, non quando il
+                    // thread principale legge il canale
+                    if let Some(sync) = parse_sync_message(message) {
+                        let _ = sender.send((sync, Instant::now()));
+                    }
+                },
+                (),
+            )
+            .map_err(|e| e.to_string())?;
+
+        println!("[INFO] Sincronizzazione dalla porta MIDI '{}'", port_name);
+        self.connection = Some(connection);
+        self.connected_port = Some(port_name.to_string());
+        self.follower = SyncFollower::new();
+        Ok(())
+    }
+
+    #[cfg(not(feature = "midi-io"))]
+    pub fn connect(&mut self, _port_name: &str) -> Result<(), String> {
+        Err("Supporto MIDI non compilato (abilitare la feature `midi-io`)".to_string())
+    }
+
+    pub fn disconnect(&mut self) {
+        #[cfg(feature = "midi-io")]
+        if let Some(connection) = self.connection.take() {
+            connection.close();
+        }
+        self.connected_port = None;
+        self.follower = SyncFollower::new();
+    }
+
+    // Elabora i messaggi arrivati e restituisce la posizione da seguire
+    // (None se non si è collegati o non è ancora arrivato nulla)
+    pub fn poll(&mut self, tempo_map: &TempoMap) -> Option<SyncPosition> {
+        self.connected_port.as_ref()?;
+        for (message, at) in self.receiver.try_iter() {
+            self.follower.handle(message, at);
+        }
+        self.follower
+            .position(Instant::now(), tempo_map, self.mtc_offset_secs)
+    }
+}
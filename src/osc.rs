@@ -0,0 +1,386 @@
+// src/osc.rs
+use crate::midi_loader::Hand;
+use crate::remote::RemoteCommand;
+use rosc::{OscMessage, OscPacket, OscType};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_OSC_PORT: u16 = 9000;
+// Frequenza massima dei messaggi /position
+const POSITION_INTERVAL: Duration = Duration::from_millis(33);
+
+// Cosa arriva dal thread di ricezione
+enum OscInput {
+    Command(RemoteCommand),
+    Subscribe(SocketAddr),
+    Unsubscribe(SocketAddr),
+}
+
+// Server OSC su UDP, solo in locale (127.0.0.1).
+//
+// Riceve:
+//   /play, /pause, /toggle
+//   /seek <secondi>, /speed <fattore>, /load <percorso>
+//   /color/left <r> <g> <b>, /color/right <r> <g> <b>  (0-255 oppure 0.0-1.0)
+//   /subscribe [porta], /unsubscribe  (iscrive il mittente agli aggiornamenti)
+//
+// Invia agli iscritti e all'eventuale destinazione fissa:
+//   /position <secondi>  (al massimo ~30 volte al secondo)
+//   /playing <0|1>       (quando cambia)
+//   /notes <tasto>...    (quando cambiano le note che suonano; vuoto = silenzio)
+pub struct OscServer {
+    socket: UdpSocket,
+    receiver: Receiver<OscInput>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    pub port: u16,
+    subscribers: Vec<SocketAddr>,
+    // Destinazione fissa degli aggiornamenti (es. il banco luci)
+    target: Option<SocketAddr>,
+
+    last_position_sent: Option<Instant>,
+    last_playing: Option<bool>,
+    last_notes: Option<Vec<u8>>,
+}
+
+impl OscServer {
+    pub fn start(port: u16, target: Option<SocketAddr>) -> Result<Self, String> {
+        let socket = UdpSocket::bind(("127.0.0.1", port))
+            .map_err(|e| format!("Impossibile aprire la porta OSC {}: {}", port, e))?;
+        // Il timeout permette al thread di accorgersi dello stop
+        socket
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .map_err(|e| e.to_string())?;
+        let receive_socket = socket.try_clone().map_err(|e| e.to_string())?;
+        // Con la porta 0 la sceglie il sistema
+        let port = socket.local_addr().map_err(|e| e.to_string())?.port();
+
+        let (sender, receiver) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let thread = std::thread::spawn(move || {
+            receive_loop(receive_socket, sender, thread_running);
+        });
+
+        println!("[INFO] Server OSC in ascolto su 127.0.0.1:{}", port);
+        Ok(Self {
+            socket,
+            receiver,
+            running,
+            thread: Some(thread),
+            port,
+            subscribers: Vec::new(),
+            target,
+            last_position_sent: None,
+            last_playing: None,
+            last_notes: None,
+        })
+    }
+
+    // Comandi arrivati dall'ultimo frame; le iscrizioni sono gestite qui
+    pub fn poll(&mut self) -> Vec<RemoteCommand> {
+        let mut commands = Vec::new();
+        for input in self.receiver.try_iter() {
+            match input {
+                OscInput::Command(command) => commands.push(command),
+                OscInput::Subscribe(address) => {
+                    if !self.subscribers.contains(&address) {
+                        println!("[INFO] Nuovo iscritto OSC: {}", address);
+                        self.subscribers.push(address);
+                        // Il nuovo iscritto deve ricevere subito lo stato completo
+                        self.last_playing = None;
+                        self.last_notes = None;
+                    }
+                }
+                OscInput::Unsubscribe(address) => self.subscribers.retain(|a| *a != address),
+            }
+        }
+        commands
+    }
+
+    // Pubblica posizione, stato del trasporto e note che suonano
+    pub fn publish(&mut self, position_secs: f32, playing: bool, sounding: &[u8]) {
+        if self.subscribers.is_empty() && self.target.is_none() {
+            return;
+        }
+        if self
+            .last_position_sent
+            .is_none_or(|t| t.elapsed() >= POSITION_INTERVAL)
+        {
+            self.send("/position", vec![OscType::Float(position_secs)]);
+            self.last_position_sent = Some(Instant::now());
+        }
+        if self.last_playing != Some(playing) {
+            self.send("/playing", vec![OscType::Int(playing as i32)]);
+            self.last_playing = Some(playing);
+        }
+        if self.last_notes.as_deref() != Some(sounding) {
+            let args = sounding.iter().map(|&p| OscType::Int(p as i32)).collect();
+            self.send("/notes", args);
+            self.last_notes = Some(sounding.to_vec());
+        }
+    }
+
+    fn send(&self, address: &str, args: Vec<OscType>) {
+        let packet = OscPacket::Message(OscMessage {
+            addr: address.to_string(),
+            args,
+        });
+        let Ok(bytes) = rosc::encoder::encode(&packet) else {
+            return;
+        };
+        for destination in self.subscribers.iter().chain(self.target.iter()) {
+            // UDP: un destinatario che non ascolta non è un errore
+            let _ = self.socket.send_to(&bytes, destination);
+        }
+    }
+}
+
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        println!("[INFO] Server OSC fermato");
+    }
+}
+
+fn receive_loop(socket: UdpSocket, sender: Sender<OscInput>, running: Arc<AtomicBool>) {
+    let mut buffer = [0u8; rosc::decoder::MTU];
+    while running.load(Ordering::Relaxed) {
+        // Errore = timeout scaduto (o pacchetto troppo grande): si riprova
+        let Ok((len, from)) = socket.recv_from(&mut buffer) else {
+            continue;
+        };
+        match rosc::decoder::decode_udp(&buffer[..len]) {
+            Ok((_, packet)) => forward_packet(packet, from, &sender),
+            Err(e) => eprintln!("[ATTENZIONE] Pacchetto OSC non valido da {}: {:?}", from, e),
+        }
+    }
+}
+
+fn forward_packet(packet: OscPacket, from: SocketAddr, sender: &Sender<OscInput>) {
+    match packet {
+        OscPacket::Message(message) => match parse_message(&message, from) {
+            Some(input) => {
+                let _ = sender.send(input);
+            }
+            None => eprintln!(
+                "[ATTENZIONE] Messaggio OSC non riconosciuto: {} {:?}",
+                message.addr, message.args
+            ),
+        },
+        // I bundle si eseguono subito, ignorando il timetag
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                forward_packet(packet, from, sender);
+            }
+        }
+    }
+}
+
+// Un argomento numerico qualsiasi (int, float, double) come f32; NaN e
+// infiniti non sono numeri validi
+fn number(arg: &OscType) -> Option<f32> {
+    let value = match *arg {
+        OscType::Int(v) => v as f32,
+        OscType::Long(v) => v as f32,
+        OscType::Float(v) => v,
+        OscType::Double(v) => v as f32,
+        _ => return None,
+    };
+    value.is_finite().then_some(value)
+}
+
+// Porta UDP: solo un intero fra 1 e 65535
+fn port(arg: &OscType) -> Option<u16> {
+    let port = match *arg {
+        OscType::Int(v) => u16::try_from(v).ok()?,
+        OscType::Long(v) => u16::try_from(v).ok()?,
+        _ => return None,
+    };
+    (port != 0).then_some(port)
+}
+
+// Componente di colore: gli interi sono 0-255, i float 0.0-1.0
+fn color_component(arg: &OscType) -> Option<u8> {
+    match *arg {
+        OscType::Int(v) => Some(v.clamp(0, 255) as u8),
+        _ => number(arg).map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8),
+    }
+}
+
+fn parse_message(message: &OscMessage, from: SocketAddr) -> Option<OscInput> {
+    let args = message.args.as_slice();
+    let command = match (message.addr.as_str(), args) {
+        ("/play", []) => RemoteCommand::Play,
+        ("/pause", []) => RemoteCommand::Pause,
+        ("/toggle", []) => RemoteCommand::TogglePlay,
+        ("/seek", [position]) => RemoteCommand::Seek(number(position)?),
+        ("/speed", [speed]) => RemoteCommand::Speed(number(speed)?),
+        ("/load", [OscType::String(path)]) => RemoteCommand::Load(PathBuf::from(path)),
+        ("/color/left" | "/color/right", [r, g, b]) => RemoteCommand::Color {
+            hand: if message.addr == "/color/left" {
+                Hand::Left
+            } else {
+                Hand::Right
+            },
+            rgb: [
+                color_component(r)?,
+                color_component(g)?,
+                color_component(b)?,
+            ],
+        },
+        ("/subscribe", []) => return Some(OscInput::Subscribe(from)),
+        // Risposte su un'altra porta dello stesso host
+        ("/subscribe", [port_arg]) => {
+            let port = port(port_arg)?;
+            return Some(OscInput::Subscribe(SocketAddr::new(from.ip(), port)));
+        }
+        ("/unsubscribe", []) => return Some(OscInput::Unsubscribe(from)),
+        ("/unsubscribe", [port_arg]) => {
+            let port = port(port_arg)?;
+            return Some(OscInput::Unsubscribe(SocketAddr::new(from.ip(), port)));
+        }
+        _ => return None,
+    };
+    Some(OscInput::Command(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(addr: &str, args: Vec<OscType>) -> Vec<u8> {
+        rosc::encoder::encode(&OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args,
+        }))
+        .unwrap()
+    }
+
+    // Aspetta (al massimo un secondo) che arrivino `count` comandi
+    fn poll_commands(server: &mut OscServer, count: usize) -> Vec<RemoteCommand> {
+        let started = Instant::now();
+        let mut commands = Vec::new();
+        while commands.len() < count && started.elapsed() < Duration::from_secs(1) {
+            commands.extend(server.poll());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        commands
+    }
+
+    #[test]
+    fn client_commands_and_updates_round_trip() {
+        let mut server = OscServer::start(0, None).unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let address = ("127.0.0.1", server.port);
+
+        client
+            .send_to(&message("/subscribe", vec![]), address)
+            .unwrap();
+        client
+            .send_to(&message("/seek", vec![OscType::Double(12.5)]), address)
+            .unwrap();
+        client
+            .send_to(
+                &message(
+                    "/color/left",
+                    vec![OscType::Int(255), OscType::Float(0.0), OscType::Int(10)],
+                ),
+                address,
+            )
+            .unwrap();
+        assert_eq!(
+            poll_commands(&mut server, 2),
+            vec![
+                RemoteCommand::Seek(12.5),
+                RemoteCommand::Color {
+                    hand: Hand::Left,
+                    rgb: [255, 0, 10]
+                }
+            ]
+        );
+
+        server.publish(3.0, true, &[60]);
+        let mut buffer = [0u8; rosc::decoder::MTU];
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let (len, _) = client.recv_from(&mut buffer).unwrap();
+            let (_, packet) = rosc::decoder::decode_udp(&buffer[..len]).unwrap();
+            let OscPacket::Message(message) = packet else {
+                panic!("atteso un messaggio");
+            };
+            received.push((message.addr, message.args));
+        }
+        assert_eq!(
+            received,
+            vec![
+                ("/position".to_string(), vec![OscType::Float(3.0)]),
+                ("/playing".to_string(), vec![OscType::Int(1)]),
+                ("/notes".to_string(), vec![OscType::Int(60)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn non_finite_numbers_are_rejected() {
+        let mut server = OscServer::start(0, None).unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = ("127.0.0.1", server.port);
+        for arg in [
+            OscType::Float(f32::NAN),
+            OscType::Float(f32::INFINITY),
+            OscType::Double(1e300),
+        ] {
+            client
+                .send_to(&message("/seek", vec![arg.clone()]), address)
+                .unwrap();
+            client
+                .send_to(&message("/speed", vec![arg]), address)
+                .unwrap();
+        }
+        // Un comando valido in coda: quando arriva, gli altri sono già passati
+        client.send_to(&message("/play", vec![]), address).unwrap();
+        assert_eq!(poll_commands(&mut server, 1), vec![RemoteCommand::Play]);
+    }
+
+    #[test]
+    fn subscribe_ports_out_of_range_are_rejected() {
+        let from: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let parse = |addr: &str, arg: OscType| {
+            let message = OscMessage {
+                addr: addr.to_string(),
+                args: vec![arg],
+            };
+            parse_message(&message, from)
+        };
+        for arg in [
+            OscType::Int(0),
+            OscType::Int(-1),
+            OscType::Int(65536),
+            OscType::Long(1 << 40),
+            OscType::Float(9001.0),
+        ] {
+            assert!(parse("/subscribe", arg.clone()).is_none(), "{:?}", arg);
+            assert!(parse("/unsubscribe", arg.clone()).is_none(), "{:?}", arg);
+        }
+        assert!(matches!(
+            parse("/subscribe", OscType::Int(9001)),
+            Some(OscInput::Subscribe(address)) if address.port() == 9001
+        ));
+        assert!(matches!(
+            parse("/unsubscribe", OscType::Long(65535)),
+            Some(OscInput::Unsubscribe(address)) if address.port() == 65535
+        ));
+    }
+}
@@ -0,0 +1,50 @@
+// src/paths.rs
+// Cartelle dell'utente dove il programma tiene i suoi file, secondo le
+// convenzioni XDG (con i ripieghi per macOS/Windows), e percorsi relativi
+// per i file che ne citano altri (sessioni, scalette).
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "piano-visualizer";
+
+// Variabile d'ambiente con un percorso non vuoto
+fn env_dir(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+// Dati rigenerabili (es. anteprime): $XDG_CACHE_HOME, ~/.cache o %LOCALAPPDATA%
+pub fn cache_dir() -> PathBuf {
+    env_dir("XDG_CACHE_HOME")
+        .or_else(|| env_dir("HOME").map(|home| home.join(".cache")))
+        .or_else(|| env_dir("LOCALAPPDATA"))
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_DIR)
+}
+
+// Impostazioni dell'utente: $XDG_CONFIG_HOME, ~/.config o %APPDATA%
+pub fn config_dir() -> PathBuf {
+    env_dir("XDG_CONFIG_HOME")
+        .or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+        .or_else(|| env_dir("APPDATA"))
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_DIR)
+}
+
+// Cartella che contiene il file ("." per un nome senza cartella)
+pub fn directory_of(path: &Path) -> &Path {
+    path.parent()
+        .filter(|directory| !directory.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+// Percorso relativo a `directory` se il file sta lì dentro, altrimenti
+// assoluto: sessioni e scalette restano valide se si sposta tutta la cartella
+pub fn relative_to(path: &Path, directory: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    directory
+        .canonicalize()
+        .ok()
+        .and_then(|directory| path.strip_prefix(directory).ok().map(Path::to_path_buf))
+        .unwrap_or(path)
+}
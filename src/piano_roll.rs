@@ -0,0 +1,379 @@
+// src/piano_roll.rs
+// Piano roll statico per le dispense: tutto il brano (o alcune battute) in
+// un SVG, oppure in un PNG rasterizzato dallo stesso SVG. Non usa la GPU;
+// colonne dei tasti e colori sono quelli della vista dal vivo.
+use crate::config::{COLOR_LEFT_HAND, COLOR_RIGHT_HAND, KEYBOARD_HEIGHT, NOTE_WIDTH};
+use crate::layout::{self, Layout};
+use crate::midi_loader::{Hand, Song};
+use crate::png_writer::write_png;
+use crate::renderer::CLEAR_COLOR;
+use crate::scene::{self, BLACK_KEY_COLOR, KEYBOARD_BACKGROUND, WHITE_KEY_COLOR};
+use resvg::{tiny_skia, usvg};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+const BAR_LINE_COLOR: [f32; 3] = [0.25, 0.25, 0.3];
+const FONT_SIZE: f32 = NOTE_WIDTH * 0.4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    // Il tempo scorre verso destra, tastiera a sinistra con gli acuti in alto
+    Horizontal,
+    // Come la vista dal vivo: tastiera in basso, il tempo sale verso l'alto
+    Vertical,
+}
+
+pub struct PianoRollSettings {
+    pub orientation: Orientation,
+    // Battute da includere, numerate da 1, estremi compresi (None = tutto)
+    pub bars: Option<(u32, u32)>,
+    pub pixels_per_second: f32,
+    // Nome della nota (Do4, Fa#3...) scritto su ogni nota
+    pub note_names: bool,
+    pub color_left: [f32; 3],
+    pub color_right: [f32; 3],
+}
+
+impl Default for PianoRollSettings {
+    fn default() -> Self {
+        Self {
+            orientation: Orientation::Horizontal,
+            bars: None,
+            pixels_per_second: 100.0,
+            note_names: false,
+            color_left: scene::color_to_f32(COLOR_LEFT_HAND),
+            color_right: scene::color_to_f32(COLOR_RIGHT_HAND),
+        }
+    }
+}
+
+// Dimensioni del disegno. Le posizioni si danno lungo l'asse delle altezze
+// (0 = bordo del tasto più grave) e lungo quello del tempo (0 = inizio),
+// l'orientamento decide dove finiscono sull'immagine.
+struct Frame {
+    orientation: Orientation,
+    pitch_len: f32,
+    time_len: f32,
+}
+
+impl Frame {
+    fn size(&self) -> (f32, f32) {
+        match self.orientation {
+            Orientation::Horizontal => (KEYBOARD_HEIGHT + self.time_len, self.pitch_len),
+            Orientation::Vertical => (self.pitch_len, self.time_len + KEYBOARD_HEIGHT),
+        }
+    }
+
+    // Rettangolo (x, y, larghezza, altezza) nell'area delle note
+    fn roll_rect(&self, pitch: (f32, f32), time: (f32, f32)) -> [f32; 4] {
+        match self.orientation {
+            Orientation::Horizontal => [
+                KEYBOARD_HEIGHT + time.0,
+                self.pitch_len - pitch.1,
+                time.1 - time.0,
+                pitch.1 - pitch.0,
+            ],
+            Orientation::Vertical => [
+                pitch.0,
+                self.time_len - time.1,
+                pitch.1 - pitch.0,
+                time.1 - time.0,
+            ],
+        }
+    }
+
+    // Rettangolo nella tastiera; `depth` parte dal bordo opposto alle note
+    fn keyboard_rect(&self, pitch: (f32, f32), depth: (f32, f32)) -> [f32; 4] {
+        match self.orientation {
+            Orientation::Horizontal => [
+                depth.0,
+                self.pitch_len - pitch.1,
+                depth.1 - depth.0,
+                pitch.1 - pitch.0,
+            ],
+            Orientation::Vertical => [
+                pitch.0,
+                self.time_len + KEYBOARD_HEIGHT - depth.1,
+                pitch.1 - pitch.0,
+                depth.1 - depth.0,
+            ],
+        }
+    }
+}
+
+// I colori della scena sono lineari (il target della GPU è sRGB)
+fn srgb_hex(color: [f32; 3]) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        scene::linear_to_srgb8(color[0]),
+        scene::linear_to_srgb8(color[1]),
+        scene::linear_to_srgb8(color[2])
+    )
+}
+
+// Testo scuro sui colori chiari, chiaro su quelli scuri
+fn text_color_on(color: [f32; 3]) -> &'static str {
+    let luminance = 0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2];
+    if luminance > 0.3 {
+        "#000000"
+    } else {
+        "#ffffff"
+    }
+}
+
+fn svg_rect(svg: &mut String, [x, y, w, h]: [f32; 4], fill: &str, extra: &str) {
+    svg.push_str(&format!(
+        "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"{}/>\n",
+        x, y, w, h, fill, extra
+    ));
+}
+
+fn svg_text(svg: &mut String, x: f32, y: f32, anchor: &str, fill: &str, text: &str) {
+    svg.push_str(&format!(
+        "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\" fill=\"{}\">{}</text>\n",
+        x, y, anchor, fill, text
+    ));
+}
+
+// Intervallo di tempo da disegnare
+fn time_range(song: &Song, bars: &[f32], range: Option<(u32, u32)>) -> Result<(f32, f32), String> {
+    let Some((first, last)) = range else {
+        return Ok((0.0, song.end_secs()));
+    };
+    if first == 0 || first > last {
+        return Err(format!(
+            "Intervallo di battute {}-{} non valido",
+            first, last
+        ));
+    }
+    let start = *bars
+        .get(first as usize - 1)
+        .ok_or_else(|| format!("Il brano ha solo {} battute", bars.len()))?;
+    let end = bars.get(last as usize).copied().unwrap_or(song.end_secs());
+    Ok((start, end))
+}
+
+// Ottave intere attorno alle note suonate, dentro la tastiera da 88
+pub fn pitch_range(song: &Song, start: f32, end: f32) -> Option<RangeInclusive<u8>> {
+    let pitches = song
+        .notes
+        .iter()
+        .filter(|n| n.start_time_secs < end && n.start_time_secs + n.duration_secs > start)
+        .map(|n| n.pitch);
+    let low = pitches.clone().min()?;
+    let high = pitches.max()?;
+    Some((low - low % 12).max(21)..=(high + 11 - high % 12).min(108))
+}
+
+pub fn render_svg(song: &Song, settings: &PianoRollSettings) -> Result<String, String> {
+    let bars = song.bar_starts();
+    let (start, end) = time_range(song, &bars, settings.bars)?;
+    let pitches = pitch_range(song, start, end).ok_or("Nessuna nota nell'intervallo scelto")?;
+
+    // Stesse colonne della vista dal vivo, spostate sul tasto più grave
+    let layout = Layout::new(0.0, None);
+    let origin = layout.note_x(*pitches.start());
+    let column = |pitch: u8| {
+        let left = layout.note_x(pitch) - origin;
+        (left, left + layout.key_width())
+    };
+    let frame = Frame {
+        orientation: settings.orientation,
+        pitch_len: column(*pitches.end()).1,
+        time_len: (end - start) * settings.pixels_per_second,
+    };
+    let time_pos = |secs: f32| (secs - start).clamp(0.0, end - start) * settings.pixels_per_second;
+    let (width, height) = frame.size();
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w:.0}\" height=\"{h:.0}\" \
+         viewBox=\"0 0 {w:.0} {h:.0}\" font-family=\"sans-serif\" font-size=\"{f}\">\n",
+        w = width.ceil(),
+        h = height.ceil(),
+        f = FONT_SIZE
+    );
+    let background = [
+        CLEAR_COLOR.r as f32,
+        CLEAR_COLOR.g as f32,
+        CLEAR_COLOR.b as f32,
+    ];
+    svg_rect(
+        &mut svg,
+        [0.0, 0.0, width, height],
+        &srgb_hex(background),
+        "",
+    );
+
+    // Stanghette con il numero della battuta
+    let bar_line = srgb_hex(BAR_LINE_COLOR);
+    for (index, &bar_secs) in bars.iter().enumerate() {
+        if bar_secs < start || bar_secs >= end {
+            continue;
+        }
+        let t = time_pos(bar_secs);
+        let [x, y, w, h] = frame.roll_rect((0.0, frame.pitch_len), (t, t + 1.0));
+        svg_rect(&mut svg, [x, y, w, h], &bar_line, "");
+        let number = (index + 1).to_string();
+        match settings.orientation {
+            Orientation::Horizontal => svg_text(
+                &mut svg,
+                x + 3.0,
+                FONT_SIZE + 2.0,
+                "start",
+                &bar_line,
+                &number,
+            ),
+            Orientation::Vertical => {
+                svg_text(&mut svg, 3.0, y + h - 3.0, "start", &bar_line, &number)
+            }
+        }
+    }
+
+    for note in &song.notes {
+        let note_end = note.start_time_secs + note.duration_secs;
+        if note.start_time_secs >= end || note_end <= start || !pitches.contains(&note.pitch) {
+            continue;
+        }
+        let color = match note.hand {
+            Hand::Left => settings.color_left,
+            Hand::Right => settings.color_right,
+        };
+        let time = (time_pos(note.start_time_secs), time_pos(note_end));
+        let [x, y, w, h] = frame.roll_rect(column(note.pitch), time);
+        svg_rect(
+            &mut svg,
+            [x, y, w, h],
+            &srgb_hex(color),
+            " stroke=\"#000000\" stroke-opacity=\"0.4\" stroke-width=\"0.5\"",
+        );
+        // Il nome va all'attacco della nota, solo se c'è spazio per leggerlo
+        if settings.note_names && time.1 - time.0 >= FONT_SIZE * 1.5 {
+            let name = layout::note_name(note.pitch);
+            let text_color = text_color_on(color);
+            match settings.orientation {
+                Orientation::Horizontal => svg_text(
+                    &mut svg,
+                    x + 2.0,
+                    y + (h + FONT_SIZE * 0.7) / 2.0,
+                    "start",
+                    text_color,
+                    &name,
+                ),
+                Orientation::Vertical => svg_text(
+                    &mut svg,
+                    x + w / 2.0,
+                    y + h - 2.0,
+                    "middle",
+                    text_color,
+                    &name,
+                ),
+            }
+        }
+    }
+
+    // Tastiera a riposo, con i Do indicati per orientarsi
+    svg_rect(
+        &mut svg,
+        frame.keyboard_rect((0.0, frame.pitch_len), (0.0, KEYBOARD_HEIGHT)),
+        &srgb_hex(KEYBOARD_BACKGROUND),
+        "",
+    );
+    for pitch in pitches {
+        let (left, right) = column(pitch);
+        let color = if layout::is_black_key(pitch) {
+            BLACK_KEY_COLOR
+        } else {
+            WHITE_KEY_COLOR
+        };
+        // Mezzo pixel di bordo per separare i tasti, come nella scena
+        let [x, y, w, h] =
+            frame.keyboard_rect((left + 0.5, right - 0.5), (0.5, KEYBOARD_HEIGHT - 0.5));
+        svg_rect(&mut svg, [x, y, w, h], &srgb_hex(color), "");
+        if pitch % 12 == 0 {
+            let name = layout::note_name(pitch);
+            match settings.orientation {
+                Orientation::Horizontal => svg_text(
+                    &mut svg,
+                    4.0,
+                    y + (h + FONT_SIZE * 0.7) / 2.0,
+                    "start",
+                    "#000000",
+                    &name,
+                ),
+                Orientation::Vertical => svg_text(
+                    &mut svg,
+                    x + w / 2.0,
+                    y + h - 4.0,
+                    "middle",
+                    "#000000",
+                    &name,
+                ),
+            }
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+// SVG -> RGBA con il font dell'interfaccia egui, così il risultato non
+// dipende dai font installati nel sistema
+fn rasterize(svg: &str) -> Result<(u32, u32, Vec<u8>), String> {
+    let mut options = usvg::Options::default();
+    if let Some(font) = egui::FontDefinitions::default()
+        .font_data
+        .get("Ubuntu-Light")
+    {
+        options.fontdb_mut().load_font_data(font.font.to_vec());
+    }
+    let family = options
+        .fontdb
+        .faces()
+        .next()
+        .and_then(|face| face.families.first())
+        .map(|(name, _)| name.clone());
+    if let Some(family) = family {
+        options.fontdb_mut().set_sans_serif_family(family);
+    }
+
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|e| format!("SVG non valido: {}", e))?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height()).ok_or_else(|| {
+        format!(
+            "Immagine troppo grande ({}x{})",
+            size.width(),
+            size.height()
+        )
+    })?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    // Lo sfondo è opaco: i pixel premoltiplicati coincidono con quelli normali
+    Ok((size.width(), size.height(), pixmap.take()))
+}
+
+// Scrive il piano roll in `output`: SVG o PNG secondo l'estensione
+pub fn export_piano_roll(
+    song: &Song,
+    output: &Path,
+    settings: &PianoRollSettings,
+) -> Result<(), String> {
+    let svg = render_svg(song, settings)?;
+    let extension = output
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("svg") => std::fs::write(output, svg)
+            .map_err(|e| format!("Impossibile scrivere '{}': {}", output.display(), e))?,
+        Some("png") => {
+            let (width, height, rgba) = rasterize(&svg)?;
+            write_png(output, width, height, &rgba)?;
+        }
+        _ => {
+            return Err(format!(
+                "Formato di '{}' non supportato: usa .svg o .png",
+                output.display()
+            ));
+        }
+    }
+    println!("[INFO] Piano roll salvato in '{}'", output.display());
+    Ok(())
+}
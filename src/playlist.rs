@@ -0,0 +1,332 @@
+// src/playlist.rs
+// Scaletta di brani da suonare uno dopo l'altro, per concerti e sottofondi:
+// ordine modificabile, passaggio automatico al brano successivo dopo una
+// pausa, ordine casuale e ripetizione. Si salva come .m3u, un file per riga.
+use crate::paths;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const PLAYLIST_EXTENSIONS: [&str; 2] = ["m3u", "m3u8"];
+
+// Cosa fare alla fine di un brano, oltre a passare al successivo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Repeat {
+    Off,
+    // Finita la scaletta si ricomincia
+    All,
+    // Lo stesso brano all'infinito
+    One,
+}
+
+pub struct Entry {
+    pub path: PathBuf,
+    // Già suonato in questo giro (serve all'ordine casuale)
+    played: bool,
+}
+
+pub struct Playlist {
+    pub entries: Vec<Entry>,
+    // Brano della scaletta in riproduzione (None = brano aperto a parte)
+    pub current: Option<usize>,
+    pub auto_advance: bool,
+    pub shuffle: bool,
+    pub repeat: Repeat,
+    // Silenzio fra la fine di un brano e l'inizio del successivo
+    pub gap_secs: f32,
+    random_state: u64,
+}
+
+pub fn is_playlist_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        PLAYLIST_EXTENSIONS
+            .iter()
+            .any(|playlist| ext.eq_ignore_ascii_case(playlist))
+    })
+}
+
+// Legge i file di una scaletta .m3u; le righe che iniziano con '#' sono
+// commenti o informazioni estese, i percorsi relativi partono dalla sua cartella
+pub fn read_m3u(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Impossibile leggere '{}': {}", path.display(), e))?;
+    let directory = paths::directory_of(path);
+    let entries: Vec<PathBuf> = text
+        .lines()
+        .map(|line| line.trim().trim_start_matches('\u{feff}'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| directory.join(line))
+        .collect();
+    for entry in entries.iter().filter(|entry| !entry.is_file()) {
+        eprintln!(
+            "[ATTENZIONE] Scaletta '{}': '{}' non trovato",
+            path.display(),
+            entry.display()
+        );
+    }
+    Ok(entries)
+}
+
+impl Playlist {
+    pub fn new() -> Self {
+        // Il seme cambia a ogni avvio, così l'ordine casuale non si ripete
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Self {
+            entries: Vec::new(),
+            current: None,
+            auto_advance: true,
+            shuffle: false,
+            repeat: Repeat::Off,
+            gap_secs: 2.0,
+            random_state: seed | 1,
+        }
+    }
+
+    pub fn set_entries(&mut self, paths: Vec<PathBuf>) {
+        self.entries = paths
+            .into_iter()
+            .map(|path| Entry {
+                path,
+                played: false,
+            })
+            .collect();
+        self.current = None;
+    }
+
+    pub fn add(&mut self, path: PathBuf) {
+        self.entries.push(Entry {
+            path,
+            played: false,
+        });
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.entries.remove(index);
+        self.current = match self.current {
+            Some(current) if current == index => None,
+            Some(current) if current > index => Some(current - 1),
+            current => current,
+        };
+    }
+
+    // Sposta un brano in un'altra posizione; quello in riproduzione resta tale
+    pub fn move_entry(&mut self, from: usize, to: usize) {
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+        self.current = self.current.map(|current| {
+            if current == from {
+                to
+            } else if from < current && current <= to {
+                current - 1
+            } else if to <= current && current < from {
+                current + 1
+            } else {
+                current
+            }
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.current = None;
+    }
+
+    // Ricomincia il giro: nessun brano suonato, nessuno in riproduzione
+    pub fn rewind(&mut self) {
+        for entry in &mut self.entries {
+            entry.played = false;
+        }
+        self.current = None;
+    }
+
+    // Segna il brano come quello in riproduzione
+    pub fn select(&mut self, index: usize) {
+        self.entries[index].played = true;
+        self.current = Some(index);
+    }
+
+    // Il brano da suonare dopo quello attuale (None = scaletta finita).
+    // `automatic` = fine del brano: solo allora conta la ripetizione del brano.
+    pub fn next(&mut self, automatic: bool) -> Option<usize> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        if automatic && self.repeat == Repeat::One && self.current.is_some() {
+            return self.current;
+        }
+        if self.shuffle {
+            let mut unplayed = self.unplayed();
+            if unplayed.is_empty() {
+                if self.repeat != Repeat::All && automatic {
+                    return None;
+                }
+                // Nuovo giro: di nuovo tutti, ma non subito lo stesso brano
+                for entry in &mut self.entries {
+                    entry.played = false;
+                }
+                unplayed = self.unplayed();
+                if unplayed.is_empty() {
+                    return self.current;
+                }
+            }
+            let pick = self.random_below(unplayed.len());
+            return Some(unplayed[pick]);
+        }
+        match self.current.map_or(0, |current| current + 1) {
+            next if next < self.entries.len() => Some(next),
+            _ if self.repeat == Repeat::All || !automatic => Some(0),
+            _ => None,
+        }
+    }
+
+    // Il brano prima di quello attuale, nell'ordine della scaletta
+    pub fn previous(&self) -> Option<usize> {
+        match self.current? {
+            0 => (self.repeat == Repeat::All).then(|| self.entries.len() - 1),
+            current => Some(current - 1),
+        }
+    }
+
+    fn unplayed(&self) -> Vec<usize> {
+        (0..self.entries.len())
+            .filter(|&i| !self.entries[i].played && Some(i) != self.current)
+            .collect()
+    }
+
+    // xorshift: per mescolare dei brani basta e avanza
+    fn random_below(&mut self, n: usize) -> usize {
+        let mut x = self.random_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.random_state = x;
+        (x % n as u64) as usize
+    }
+
+    // Scrive la scaletta come .m3u, con percorsi relativi quando si può
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let directory = paths::directory_of(path);
+        let mut text = String::from("#EXTM3U\n");
+        for entry in &self.entries {
+            text.push_str(
+                &paths::relative_to(&entry.path, directory)
+                    .display()
+                    .to_string(),
+            );
+            text.push('\n');
+        }
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Impossibile creare '{}': {}", parent.display(), e))?;
+        }
+        std::fs::write(path, text)
+            .map_err(|e| format!("Impossibile salvare '{}': {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(count: usize) -> Playlist {
+        let mut playlist = Playlist::new();
+        playlist.set_entries(
+            (0..count)
+                .map(|i| PathBuf::from(format!("{}.mid", i)))
+                .collect(),
+        );
+        playlist
+    }
+
+    fn names(playlist: &Playlist) -> Vec<String> {
+        playlist
+            .entries
+            .iter()
+            .map(|entry| entry.path.display().to_string())
+            .collect()
+    }
+
+    // Suona un giro completo in ordine casuale e restituisce i brani scelti
+    fn shuffle_round(playlist: &mut Playlist, count: usize) -> Vec<usize> {
+        let mut round = Vec::new();
+        for _ in 0..count {
+            let next = playlist.next(true).unwrap();
+            playlist.select(next);
+            round.push(next);
+        }
+        round
+    }
+
+    #[test]
+    fn shuffle_plays_every_entry_once_per_round() {
+        let mut playlist = playlist(4);
+        playlist.shuffle = true;
+        let mut round = shuffle_round(&mut playlist, 4);
+        round.sort();
+        assert_eq!(round, vec![0, 1, 2, 3]);
+        // Giro finito: senza ripetizione ci si ferma...
+        assert_eq!(playlist.next(true), None);
+        // ...ma il pulsante "successivo" ne comincia un altro
+        assert!(playlist.next(false).is_some());
+    }
+
+    #[test]
+    fn shuffle_with_repeat_all_starts_a_new_round() {
+        let mut playlist = playlist(4);
+        playlist.shuffle = true;
+        playlist.repeat = Repeat::All;
+        let first = shuffle_round(&mut playlist, 4);
+        let mut second = shuffle_round(&mut playlist, 4);
+        // Il nuovo giro non ricomincia dal brano appena finito
+        assert_ne!(second[0], first[3]);
+        second.sort();
+        assert_eq!(second, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn single_entry_playlist() {
+        let mut playlist = playlist(1);
+        assert_eq!(playlist.next(true), Some(0));
+        playlist.select(0);
+        assert_eq!(playlist.next(true), None);
+        assert_eq!(playlist.next(false), Some(0));
+        assert_eq!(playlist.previous(), None);
+
+        playlist.repeat = Repeat::All;
+        assert_eq!(playlist.next(true), Some(0));
+        assert_eq!(playlist.previous(), Some(0));
+
+        playlist.shuffle = true;
+        assert_eq!(playlist.next(true), Some(0));
+        playlist.repeat = Repeat::Off;
+        assert_eq!(playlist.next(true), None);
+    }
+
+    #[test]
+    fn moving_entries_keeps_the_current_one() {
+        let mut playlist = playlist(4);
+        playlist.select(1);
+
+        // Da dopo a prima del brano attuale
+        playlist.move_entry(3, 0);
+        assert_eq!(names(&playlist), ["3.mid", "0.mid", "1.mid", "2.mid"]);
+        assert_eq!(playlist.current, Some(2));
+
+        // Da prima a dopo
+        playlist.move_entry(0, 3);
+        assert_eq!(names(&playlist), ["0.mid", "1.mid", "2.mid", "3.mid"]);
+        assert_eq!(playlist.current, Some(1));
+
+        // Tutto dopo il brano attuale: non cambia
+        playlist.move_entry(3, 2);
+        assert_eq!(playlist.current, Some(1));
+
+        // Il brano attuale stesso
+        playlist.move_entry(1, 3);
+        assert_eq!(names(&playlist), ["0.mid", "3.mid", "2.mid", "1.mid"]);
+        assert_eq!(playlist.current, Some(3));
+    }
+}
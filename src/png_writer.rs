@@ -0,0 +1,17 @@
+// src/png_writer.rs
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// Salva un'immagine RGBA a 8 bit per canale, righe senza padding
+pub fn write_png(path: &Path, width: u32, height: u32, rgba: &[u8]) -> Result<(), String> {
+    let file = File::create(path)
+        .map_err(|e| format!("Impossibile creare '{}': {}", path.display(), e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer
+        .write_image_data(rgba)
+        .map_err(|e| format!("Errore scrivendo '{}': {}", path.display(), e))
+}
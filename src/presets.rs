@@ -0,0 +1,224 @@
+// src/presets.rs
+// Preset di aspetto con un nome: velocità di caduta, colori e tasti
+// mostrati. Oltre a quelli predefiniti, l'utente salva i suoi nella cartella
+// di configurazione e li scambia come file .json.
+use crate::layout::KeyRange;
+use crate::paths;
+use crate::settings::{self, ViewSettings};
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_PRESET: &str = "Predefinito";
+
+// Da incrementare quando cambia il formato del file
+const PRESET_VERSION: u64 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    #[serde(default = "preset_version")]
+    pub version: u64,
+    pub name: String,
+    pub view: ViewSettings,
+    // File da cui è stato letto (None = predefinito o non ancora salvato)
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+fn preset_version() -> u64 {
+    PRESET_VERSION
+}
+
+impl Preset {
+    pub fn new(name: &str, view: ViewSettings) -> Self {
+        Self {
+            version: PRESET_VERSION,
+            name: name.to_string(),
+            view,
+            file: None,
+        }
+    }
+
+    // Legge un preset da file, riportando i valori nei limiti
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Impossibile leggere '{}': {}", path.display(), e))?;
+        let mut preset: Preset = serde_json::from_str(&text)
+            .map_err(|e| format!("Preset '{}' non valido: {}", path.display(), e))?;
+        let name = preset.name.trim().to_string();
+        if name.is_empty() {
+            return Err(format!("Il preset '{}' non ha un nome", path.display()));
+        }
+        preset.name = name;
+        let mut warnings = Vec::new();
+        if preset.version > PRESET_VERSION {
+            warnings.push(settings::backup_newer(path, preset.version, PRESET_VERSION));
+        }
+        preset.version = PRESET_VERSION;
+        preset.file = Some(path.to_path_buf());
+        preset.view.validate("view", &mut warnings);
+        for warning in warnings {
+            eprintln!("[ATTENZIONE] Preset '{}': {}", preset.name, warning);
+        }
+        Ok(preset)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        settings::save_json(path, self)
+    }
+}
+
+// I preset che ci sono sempre, non modificabili
+fn builtin_presets() -> Vec<Preset> {
+    vec![
+        Preset::new(DEFAULT_PRESET, ViewSettings::default()),
+        // Note grandi e lente su tre ottave centrali
+        Preset::new(
+            "Principianti",
+            ViewSettings {
+                fall_duration_secs: 4.0,
+                color_left_hand: Color32::from_rgb(255, 140, 0),
+                color_right_hand: Color32::from_rgb(0, 190, 255),
+                key_range: Some(KeyRange { low: 48, high: 84 }),
+            },
+        ),
+        // Tutta la tastiera, note veloci e fitte
+        Preset::new(
+            "Avanzato",
+            ViewSettings {
+                fall_duration_secs: 1.2,
+                color_left_hand: Color32::from_rgb(200, 60, 255),
+                color_right_hand: Color32::from_rgb(255, 220, 40),
+                key_range: Some(KeyRange { low: 21, high: 108 }),
+            },
+        ),
+    ]
+}
+
+fn presets_dir() -> PathBuf {
+    paths::config_dir().join("presets")
+}
+
+// Nome del file per un preset: solo caratteri sicuri su ogni sistema
+fn file_name(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}.json", stem)
+}
+
+// I nomi dei preset non distinguono maiuscole e minuscole
+fn find_preset<'a>(presets: &'a [Preset], name: &str) -> Option<&'a Preset> {
+    presets.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+// Tutti i preset disponibili: prima i predefiniti, poi quelli dell'utente
+pub struct PresetLibrary {
+    builtin_count: usize,
+    presets: Vec<Preset>,
+}
+
+impl PresetLibrary {
+    // Carica i preset dell'utente; quelli illeggibili vengono segnalati e saltati
+    pub fn load() -> Self {
+        let mut presets = builtin_presets();
+        let builtin_count = presets.len();
+        let mut files: Vec<PathBuf> = std::fs::read_dir(presets_dir())
+            .map(|read| {
+                read.flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        for path in files {
+            match Preset::load(&path) {
+                Ok(preset) if find_preset(&presets, &preset.name).is_some() => eprintln!(
+                    "[ATTENZIONE] Preset '{}' in '{}' duplicato, ignorato",
+                    preset.name,
+                    path.display()
+                ),
+                Ok(preset) => presets.push(preset),
+                Err(e) => eprintln!("[ATTENZIONE] {}", e),
+            }
+        }
+        Self {
+            builtin_count,
+            presets,
+        }
+    }
+
+    pub fn presets(&self) -> &[Preset] {
+        &self.presets
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Preset> {
+        find_preset(&self.presets, name)
+    }
+
+    pub fn is_builtin(&self, name: &str) -> bool {
+        find_preset(&self.presets[..self.builtin_count], name).is_some()
+    }
+
+    // Salva (o sovrascrive) un preset dell'utente
+    pub fn save(&mut self, name: &str, view: ViewSettings) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Serve un nome per il preset".to_string());
+        }
+        if self.is_builtin(name) {
+            return Err(format!(
+                "'{}' è un preset predefinito, scegli un altro nome",
+                name
+            ));
+        }
+        let index = self
+            .presets
+            .iter()
+            .position(|p| p.name.eq_ignore_ascii_case(name));
+        // Sovrascrivendo si riusa il file da cui il preset era stato letto
+        let file = index
+            .and_then(|i| self.presets[i].file.clone())
+            .unwrap_or_else(|| presets_dir().join(file_name(name)));
+        let mut preset = Preset::new(name, view);
+        preset.save(&file)?;
+        preset.file = Some(file);
+        match index {
+            Some(i) => self.presets[i] = preset,
+            None => self.presets.push(preset),
+        }
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        if self.is_builtin(name) {
+            return Err(format!("'{}' è un preset predefinito", name));
+        }
+        if let Some(file) = self.find(name).and_then(|p| p.file.as_ref()) {
+            match std::fs::remove_file(file) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(format!("Impossibile eliminare '{}': {}", file.display(), e));
+                }
+            }
+        }
+        self.presets.retain(|p| !p.name.eq_ignore_ascii_case(name));
+        Ok(())
+    }
+
+    // Aggiunge ai preset dell'utente quello contenuto in un file
+    pub fn import(&mut self, path: &Path) -> Result<String, String> {
+        let preset = Preset::load(path)?;
+        self.save(&preset.name, preset.view)?;
+        Ok(preset.name)
+    }
+}
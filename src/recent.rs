@@ -0,0 +1,54 @@
+// src/recent.rs
+// Ultimi file aperti (brani, sessioni e scalette) per il menu File. L'elenco
+// si riscrive a ogni apertura, in recent.json nella cartella di configurazione.
+use crate::paths;
+use crate::settings;
+use std::path::{Path, PathBuf};
+
+const MAX_RECENT_FILES: usize = 10;
+
+pub struct RecentFiles {
+    // Dal più recente
+    paths: Vec<PathBuf>,
+}
+
+fn recent_path() -> PathBuf {
+    paths::config_dir().join("recent.json")
+}
+
+impl RecentFiles {
+    // L'elenco salvato; se manca o è illeggibile si parte da uno vuoto
+    pub fn load() -> Self {
+        let path = recent_path();
+        let paths = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                eprintln!(
+                    "[ATTENZIONE] File recenti non validi in '{}': {}",
+                    path.display(),
+                    e
+                );
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self { paths }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    // Mette il file in cima all'elenco (spostandolo se c'era già)
+    pub fn add(&mut self, path: &Path) -> Result<(), String> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.paths.retain(|p| *p != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(MAX_RECENT_FILES);
+        settings::save_json(&recent_path(), &self.paths)
+    }
+
+    pub fn clear(&mut self) -> Result<(), String> {
+        self.paths.clear();
+        settings::save_json(&recent_path(), &self.paths)
+    }
+}
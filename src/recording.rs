@@ -0,0 +1,201 @@
+// src/recording.rs
+// La registrazione suona solo nel thread audio: senza la feature `audio`
+// il modulo resta compilato ma inutilizzato.
+#![cfg_attr(not(feature = "audio"), allow(dead_code))]
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+// Una registrazione decodificata interamente in memoria (stereo interlacciato)
+pub struct DecodedAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+impl DecodedAudio {
+    fn frames(&self) -> usize {
+        self.samples.len() / 2
+    }
+}
+
+// Decodifica un file MP3/WAV/FLAC/OGG. I file mono vengono duplicati su
+// entrambi i canali, quelli con più di due canali ridotti ai primi due.
+pub fn decode_audio_file(path: &Path) -> Result<DecodedAudio, String> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Impossibile aprire '{}': {}", path.display(), e))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| format!("Formato audio non riconosciuto: {}", e))?;
+    let mut format = probed.format;
+
+    let track = format
+        .default_track()
+        .ok_or("Nessuna traccia audio nel file")?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or("Frequenza di campionamento sconosciuta")?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| format!("Codec non supportato: {}", e))?;
+
+    let mut samples = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // Fine del file
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Un pacchetto corrotto si salta, come fanno i lettori
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count();
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        for frame in buffer.samples().chunks(channels) {
+            samples.push(frame[0]);
+            samples.push(if channels > 1 { frame[1] } else { frame[0] });
+        }
+    }
+
+    Ok(DecodedAudio {
+        samples,
+        sample_rate,
+    })
+}
+
+// Lo stato del trasporto che il thread audio deve rispecchiare
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackState {
+    pub playing: bool,
+    pub speed: f32,
+    pub loop_region: Option<(f32, f32)>,
+}
+
+// Riproduce la registrazione nel thread audio. Tiene una propria posizione nel
+// brano, avanzata campione per campione: seek e loop cadono sul campione
+// esatto, e il thread principale allinea le note a questa posizione.
+pub struct RecordingPlayer {
+    audio: Arc<DecodedAudio>,
+    // Punto della registrazione (secondi) che corrisponde all'inizio del brano
+    offset_secs: f32,
+    output_rate: f64,
+    song_position_secs: f64,
+    state: PlaybackState,
+    // Posizione pubblicata per il thread principale (bit di un f64)
+    position_out: Arc<AtomicU64>,
+}
+
+impl RecordingPlayer {
+    pub fn new(
+        audio: Arc<DecodedAudio>,
+        offset_secs: f32,
+        output_rate: u32,
+        position_out: Arc<AtomicU64>,
+    ) -> Self {
+        let player = Self {
+            audio,
+            offset_secs,
+            output_rate: output_rate as f64,
+            song_position_secs: 0.0,
+            state: PlaybackState {
+                playing: false,
+                speed: 1.0,
+                loop_region: None,
+            },
+            position_out,
+        };
+        // Niente posizione rimasta da una registrazione precedente
+        player.publish();
+        player
+    }
+
+    pub fn seek(&mut self, song_position_secs: f32) {
+        self.song_position_secs = song_position_secs as f64;
+        self.publish();
+    }
+
+    pub fn set_state(&mut self, state: PlaybackState) {
+        self.state = state;
+    }
+
+    pub fn set_offset(&mut self, offset_secs: f32) {
+        self.offset_secs = offset_secs;
+    }
+
+    fn publish(&self) {
+        self.position_out
+            .store(self.song_position_secs.to_bits(), Ordering::Relaxed);
+    }
+
+    // Interpolazione lineare fra i due campioni vicini
+    fn sample_at(&self, recording_secs: f64) -> (f32, f32) {
+        let position = recording_secs * self.audio.sample_rate as f64;
+        if position < 0.0 {
+            return (0.0, 0.0);
+        }
+        let index = position as usize;
+        if index + 1 >= self.audio.frames() {
+            return (0.0, 0.0);
+        }
+        let fraction = (position - index as f64) as f32;
+        let s = &self.audio.samples[index * 2..index * 2 + 4];
+        (
+            s[0] + (s[2] - s[0]) * fraction,
+            s[1] + (s[3] - s[1]) * fraction,
+        )
+    }
+
+    // Somma la registrazione ai buffer di uscita. Con velocità diversa da 1
+    // la registrazione accelera o rallenta insieme alle note (cambia l'intonazione).
+    pub fn mix_into(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if !self.state.playing {
+            return;
+        }
+        let step = self.state.speed as f64 / self.output_rate;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let (rec_l, rec_r) = self.sample_at(self.song_position_secs + self.offset_secs as f64);
+            *l += rec_l;
+            *r += rec_r;
+
+            self.song_position_secs += step;
+            if let Some((start, end)) = self.state.loop_region
+                && end > start
+                && self.song_position_secs >= end as f64
+            {
+                self.song_position_secs = start as f64;
+            }
+        }
+        self.publish();
+    }
+}
@@ -0,0 +1,20 @@
+// src/remote.rs
+use crate::midi_loader::Hand;
+use std::path::PathBuf;
+
+// Un comando arrivato da un controllo remoto (OSC, HTTP). I server di rete
+// girano su thread separati e passano i comandi al thread principale, dove
+// `State::apply_remote` li esegue come se venissero dalla UI.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteCommand {
+    Play,
+    Pause,
+    TogglePlay,
+    Seek(f32),
+    Load(PathBuf),
+    Speed(f32),
+    FallDuration(f32),
+    Color { hand: Hand, rgb: [u8; 3] },
+    SynthVolume(f32),
+    Metronome(bool),
+}
@@ -0,0 +1,477 @@
+// src/renderer.rs
+use crate::vertex::Vertex;
+use wgpu::util::DeviceExt;
+
+// Colore di sfondo della scena
+pub const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.05,
+    g: 0.05,
+    b: 0.1,
+    a: 1.0,
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Uniforms {
+    screen_size: [f32; 2],
+}
+
+// Pipeline e buffer GPU della scena. Non sa nulla di finestre: disegna in
+// qualsiasi render pass il cui target abbia il formato indicato a `new`.
+pub struct SceneRenderer {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+}
+
+impl SceneRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        // --- Creazione Uniforms ---
+        let uniforms = Uniforms {
+            screen_size: [width as f32, height as f32],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Uniform Buffer"),
+            contents: bytemuck::bytes_of(&uniforms),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("Uniform Bind Group Layout"),
+            });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("Uniform Bind Group"),
+        });
+
+        // --- Shader ---
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        });
+
+        // --- Pipeline ---
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        // --- Vertex Buffer ---
+        let initial_size = 6 * 10 * std::mem::size_of::<Vertex>() as u64;
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Vertex Buffer"),
+            size: initial_size,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            render_pipeline,
+            vertex_buffer,
+            num_vertices: 0,
+            uniform_buffer,
+            uniform_bind_group,
+        }
+    }
+
+    // Dimensioni (pixel) del target su cui si disegna
+    pub fn resize(&self, queue: &wgpu::Queue, width: u32, height: u32) {
+        let uniforms = Uniforms {
+            screen_size: [width as f32, height as f32],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    // Carica i vertici del prossimo fotogramma, ingrandendo il buffer se serve
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, vertices: &[Vertex]) {
+        if !vertices.is_empty() {
+            let required_size = std::mem::size_of_val(vertices) as u64;
+            let current_size = self.vertex_buffer.size();
+
+            if required_size > current_size {
+                let new_size = required_size * 2;
+                println!(
+                    "[INFO] Riallocazione buffer GPU: {} -> {} bytes",
+                    current_size, new_size
+                );
+
+                self.vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Vertex Buffer (Dynamic)"),
+                    size: new_size,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+            }
+
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(vertices));
+        }
+        self.num_vertices = vertices.len() as u32;
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+
+    // Pass completo su un target qualsiasi: sfondo e scena
+    pub fn render_to(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = begin_clear_pass(encoder, view);
+        self.draw(&mut render_pass);
+    }
+
+    // Disegna i vertici dati su un target fuori schermo e ne restituisce i pixel
+    pub fn render_rgba(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target: &OffscreenTarget,
+        vertices: &[Vertex],
+    ) -> Result<Vec<u8>, String> {
+        self.resize(queue, target.width, target.height);
+        self.upload(device, queue, vertices);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Offscreen Encoder"),
+        });
+        self.render_to(&mut encoder, target.view());
+        queue.submit(std::iter::once(encoder.finish()));
+        target.read_rgba(device, queue)
+    }
+}
+
+// Render pass che pulisce il target con il colore di sfondo
+pub fn begin_clear_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Render Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(CLEAR_COLOR),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    })
+}
+
+// Formato dei target fuori schermo: RGBA come i PNG e i fotogrammi per ffmpeg
+pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+// Texture su cui disegnare senza finestra, con il buffer per rileggerla.
+// Il formato può essere quello della finestra, così le pipeline già create
+// (scena ed egui) disegnano anche qui.
+pub struct OffscreenTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    readback: wgpu::Buffer,
+    pub width: u32,
+    pub height: u32,
+    padded_bytes_per_row: u32,
+    // I pixel letti vanno riordinati da BGRA a RGBA
+    swap_red_blue: bool,
+}
+
+impl OffscreenTarget {
+    // Errore se la texture o il buffer di lettura superano i limiti del
+    // dispositivo: wgpu andrebbe in panico
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self, String> {
+        let limits = device.limits();
+        let max_side = limits.max_texture_dimension_2d;
+        if width > max_side || height > max_side {
+            return Err(format!(
+                "Dimensione {}x{} troppo grande: il dispositivo arriva a {}x{}",
+                width, height, max_side, max_side
+            ));
+        }
+        // Le righe copiate dalla GPU devono essere allineate a 256 byte
+        let padded_bytes_per_row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let readback_size = padded_bytes_per_row as u64 * height as u64;
+        if readback_size > limits.max_buffer_size {
+            return Err(format!(
+                "Dimensione {}x{} troppo grande: servono {} MB di memoria video, il dispositivo ne concede {}",
+                width,
+                height,
+                readback_size >> 20,
+                limits.max_buffer_size >> 20
+            ));
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Readback"),
+            size: readback_size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Ok(Self {
+            texture,
+            view,
+            readback,
+            width,
+            height,
+            padded_bytes_per_row,
+            swap_red_blue: matches!(
+                format,
+                wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+            ),
+        })
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    // Copia il contenuto attuale della texture in memoria: RGBA a 8 bit,
+    // righe senza padding, dall'alto in basso. Blocca finché la GPU ha finito.
+    pub fn read_rgba(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<u8>, String> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            self.texture.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = self.readback.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|_| "Lettura dalla GPU interrotta".to_string())?
+            .map_err(|e| format!("Impossibile leggere l'immagine dalla GPU: {}", e))?;
+        let row_bytes = (self.width * 4) as usize;
+        let mut rgba = Vec::with_capacity(row_bytes * self.height as usize);
+        {
+            let padded = slice.get_mapped_range();
+            for row in padded.chunks_exact(self.padded_bytes_per_row as usize) {
+                rgba.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+        self.readback.unmap();
+        if self.swap_red_blue {
+            for pixel in rgba.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(rgba)
+    }
+}
+
+// Variabile d'ambiente che forza il rendering software (come `--software`)
+pub const SOFTWARE_RENDERING_ENV: &str = "PIANO_VISUALIZER_SOFTWARE";
+
+pub fn create_instance() -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        dx12_shader_compiler: Default::default(),
+    })
+}
+
+// Sceglie l'adattatore: prima una GPU vera, poi quello software del sistema
+// (WARP su Windows, llvmpipe/lavapipe di Mesa su Linux). Con
+// `force_software` si salta direttamente al secondo.
+pub async fn request_adapter(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface>,
+    force_software: bool,
+) -> Result<wgpu::Adapter, String> {
+    let force_software = force_software || std::env::var_os(SOFTWARE_RENDERING_ENV).is_some();
+    let options = |force_fallback_adapter| wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        force_fallback_adapter,
+        compatible_surface,
+    };
+
+    let mut adapter = None;
+    if !force_software {
+        adapter = instance.request_adapter(&options(false)).await;
+        if adapter.is_none() {
+            eprintln!("[ATTENZIONE] Nessuna GPU disponibile, provo il rendering software");
+        }
+    }
+    if adapter.is_none() {
+        adapter = instance.request_adapter(&options(true)).await;
+    }
+    let adapter = adapter.ok_or_else(|| {
+        "Nessun adattatore grafico disponibile, né GPU né software. \
+         Su Linux installare i driver Mesa (llvmpipe/lavapipe), su Windows \
+         verificare i driver video."
+            .to_string()
+    })?;
+
+    let info = adapter.get_info();
+    println!(
+        "[INFO] Adattatore grafico: {} ({:?}, {:?})",
+        info.name, info.backend, info.device_type
+    );
+    Ok(adapter)
+}
+
+// Apre il dispositivo chiedendo solo i limiti che servono davvero, così
+// funziona anche sugli adattatori software e su OpenGL
+pub async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), String> {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::downlevel_webgl2_defaults()
+                    .using_resolution(adapter.limits()),
+                label: None,
+            },
+            None,
+        )
+        .await
+        .map_err(|e| format!("Impossibile aprire il dispositivo grafico: {}", e))
+}
+
+// Dispositivo GPU senza finestra (esportazioni da riga di comando)
+pub async fn headless_device(force_software: bool) -> Result<(wgpu::Device, wgpu::Queue), String> {
+    let instance = create_instance();
+    let adapter = request_adapter(&instance, None, force_software).await?;
+    request_device(&adapter).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::linear_to_srgb8;
+
+    #[test]
+    fn headless_render_draws_clear_color_and_note() {
+        // Adattatore software, come con --software: niente GPU nei test
+        let (device, queue) = match pollster::block_on(headless_device(true)) {
+            Ok(device) => device,
+            Err(e) => {
+                eprintln!("[ATTENZIONE] Test saltato: {}", e);
+                return;
+            }
+        };
+        let (width, height) = (64, 32);
+        let target = OffscreenTarget::new(&device, width, height, OFFSCREEN_FORMAT).unwrap();
+        let mut renderer = SceneRenderer::new(&device, OFFSCREEN_FORMAT, width, height);
+
+        // Una nota rossa da (16, 8) a (32, 24)
+        let red = [1.0, 0.0, 0.0];
+        let corner = |x: f32, y: f32| Vertex {
+            position: [x, y],
+            color: red,
+        };
+        let note = [
+            corner(16.0, 24.0),
+            corner(32.0, 24.0),
+            corner(16.0, 8.0),
+            corner(32.0, 24.0),
+            corner(32.0, 8.0),
+            corner(16.0, 8.0),
+        ];
+        let rgba = renderer
+            .render_rgba(&device, &queue, &target, &note)
+            .unwrap();
+        assert_eq!(rgba.len(), (width * height * 4) as usize);
+
+        let pixel = |x: u32, y: u32| {
+            let i = ((y * width + x) * 4) as usize;
+            [rgba[i], rgba[i + 1], rgba[i + 2], rgba[i + 3]]
+        };
+        let clear = [
+            linear_to_srgb8(CLEAR_COLOR.r as f32),
+            linear_to_srgb8(CLEAR_COLOR.g as f32),
+            linear_to_srgb8(CLEAR_COLOR.b as f32),
+            255,
+        ];
+        for (x, y) in [(0, 0), (63, 31), (8, 16), (40, 16)] {
+            assert_eq!(pixel(x, y), clear, "sfondo in ({}, {})", x, y);
+        }
+        for (x, y) in [(17, 9), (24, 16), (31, 23)] {
+            assert_eq!(pixel(x, y), [255, 0, 0, 255], "nota in ({}, {})", x, y);
+        }
+        // La lettura separata restituisce gli stessi pixel
+        assert_eq!(target.read_rgba(&device, &queue).unwrap(), rgba);
+    }
+}
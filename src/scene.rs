@@ -0,0 +1,159 @@
+// src/scene.rs
+// Geometria della scena (note che cadono + tastiera) come lista di vertici.
+// Non tocca la GPU: la usano la finestra e l'esportazione video, così
+// tutte le uscite disegnano esattamente la stessa cosa.
+use crate::config::KEYBOARD_HEIGHT;
+use crate::layout::{self, KeyRange, Layout};
+use crate::midi_loader::{Hand, MidiNote, Song};
+use crate::vertex::Vertex;
+
+// Colori della tastiera a riposo (lineari: il target è sRGB)
+pub const KEYBOARD_BACKGROUND: [f32; 3] = [0.005, 0.005, 0.005];
+pub const WHITE_KEY_COLOR: [f32; 3] = [0.92, 0.92, 0.92];
+pub const BLACK_KEY_COLOR: [f32; 3] = [0.015, 0.015, 0.015];
+// Tasto premuto con tastiera del computer o mouse
+pub const HELD_KEY_COLOR: [f32; 3] = [1.0, 0.82, 0.24];
+
+// Cosa serve per disegnare un fotogramma
+pub struct Scene<'a> {
+    pub song: &'a Song,
+    pub time_secs: f32,
+    pub width: f32,
+    pub height: f32,
+    pub fall_duration_secs: f32,
+    pub color_left: [f32; 3],
+    pub color_right: [f32; 3],
+    // Tasti che riempiono la larghezza (None = colonne a larghezza fissa)
+    pub key_range: Option<KeyRange>,
+}
+
+pub fn color_to_f32(color: egui::Color32) -> [f32; 3] {
+    [
+        color.r() as f32 / 255.0,
+        color.g() as f32 / 255.0,
+        color.b() as f32 / 255.0,
+    ]
+}
+
+// Codifica sRGB di un canale lineare, come fa la GPU scrivendo sul target:
+// chi disegna senza GPU la usa per avere le stesse tinte della finestra
+pub fn linear_to_srgb8(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let srgb = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round() as u8
+}
+
+// Rettangolo con l'angolo in alto a sinistra in (x, y), y verso il basso
+fn push_rect(vertices: &mut Vec<Vertex>, x: f32, y: f32, w: f32, h: f32, color: [f32; 3]) {
+    let corner = |dx: f32, dy: f32| Vertex {
+        position: [x + dx, y + dy],
+        color,
+    };
+    vertices.extend_from_slice(&[
+        corner(0.0, h),
+        corner(w, h),
+        corner(0.0, 0.0),
+        corner(w, h),
+        corner(w, 0.0),
+        corner(0.0, 0.0),
+    ]);
+}
+
+impl Scene<'_> {
+    fn layout(&self) -> Layout {
+        Layout::new(self.width, self.key_range)
+    }
+
+    pub fn hand_color(&self, hand: Hand) -> [f32; 3] {
+        match hand {
+            Hand::Left => self.color_left,
+            Hand::Right => self.color_right,
+        }
+    }
+
+    // Le note visibili. `note_color` decide il colore di ogni nota (indice
+    // nel brano), per esempio per il feedback della valutazione.
+    pub fn push_notes(
+        &self,
+        vertices: &mut Vec<Vertex>,
+        note_color: impl Fn(usize, &MidiNote) -> [f32; 3],
+    ) {
+        let layout = self.layout();
+        let present_line_y = layout::present_line_y();
+        let pixels_per_second = (self.height - present_line_y) / self.fall_duration_secs;
+
+        for (note_index, note) in self.song.notes.iter().enumerate() {
+            let y_hit_position =
+                present_line_y + (note.start_time_secs - self.time_secs) * pixels_per_second;
+            let note_height_pixels = note.duration_secs * pixels_per_second;
+            let y_top_position = y_hit_position + note_height_pixels;
+
+            if y_top_position < 0.0 || y_hit_position > self.height {
+                continue;
+            }
+
+            let x_pos = layout.note_x(note.pitch);
+            push_rect(
+                vertices,
+                x_pos,
+                self.height - y_top_position,
+                layout.key_width(),
+                note_height_pixels,
+                note_color(note_index, note),
+            );
+        }
+    }
+
+    // La tastiera sotto la linea del presente: i tasti delle note che
+    // suonano prendono il colore della mano
+    pub fn push_keyboard(&self, vertices: &mut Vec<Vertex>, held: impl Fn(u8) -> bool) {
+        let layout = self.layout();
+        let top = self.height - KEYBOARD_HEIGHT;
+        push_rect(
+            vertices,
+            0.0,
+            top,
+            self.width,
+            KEYBOARD_HEIGHT,
+            KEYBOARD_BACKGROUND,
+        );
+        // Mano della prima nota che suona su ogni tasto, calcolata una volta sola
+        let mut sounding_hands = [None; 128];
+        for note in self.song.sounding_at(self.time_secs) {
+            sounding_hands[note.pitch as usize % 128].get_or_insert(note.hand);
+        }
+        for pitch in layout.visible_pitches() {
+            let sounding_hand = sounding_hands[pitch as usize];
+            let color = if held(pitch) {
+                HELD_KEY_COLOR
+            } else {
+                match sounding_hand {
+                    Some(hand) => self.hand_color(hand),
+                    None if layout::is_black_key(pitch) => BLACK_KEY_COLOR,
+                    None => WHITE_KEY_COLOR,
+                }
+            };
+            // Mezzo pixel di bordo per separare i tasti
+            push_rect(
+                vertices,
+                layout.note_x(pitch) + 0.5,
+                top + 0.5,
+                layout.key_width() - 1.0,
+                KEYBOARD_HEIGHT - 1.0,
+                color,
+            );
+        }
+    }
+
+    // Scena completa senza decorazioni interattive (video, anteprime)
+    pub fn vertices(&self) -> Vec<Vertex> {
+        let mut vertices = Vec::new();
+        self.push_notes(&mut vertices, |_, note| self.hand_color(note.hand));
+        self.push_keyboard(&mut vertices, |_| false);
+        vertices
+    }
+}
@@ -0,0 +1,354 @@
+// src/scoring.rs
+use crate::config::HAND_SPLIT_PITCH;
+use crate::midi_loader::{Hand, MidiNote};
+
+// Il giudizio su una singola nota
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grade {
+    Hit,
+    Early,
+    Late,
+    Wrong,
+    Missed,
+}
+
+// Finestre temporali (in secondi) attorno all'inizio della nota attesa
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TimingWindows {
+    // Entro questa distanza la nota è "giusta"
+    pub hit_secs: f32,
+    // Oltre la finestra "giusta" e fino a questi limiti è "in anticipo"/"in ritardo";
+    // più in là il tasto non viene più associato alla nota
+    pub early_secs: f32,
+    pub late_secs: f32,
+}
+
+impl Default for TimingWindows {
+    fn default() -> Self {
+        Self {
+            hit_secs: 0.05,
+            early_secs: 0.2,
+            late_secs: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScoreSummary {
+    pub hit: usize,
+    pub early: usize,
+    pub late: usize,
+    pub wrong: usize,
+    pub missed: usize,
+}
+
+impl ScoreSummary {
+    // Percentuale di precisione: una nota giusta vale 1, anticipi e ritardi
+    // mezzo punto; le note sbagliate contano come note in più da suonare.
+    pub fn accuracy_percent(&self) -> f32 {
+        let total = self.hit + self.early + self.late + self.missed + self.wrong;
+        if total == 0 {
+            return 100.0;
+        }
+        let points = self.hit as f32 + 0.5 * (self.early + self.late) as f32;
+        100.0 * points / total as f32
+    }
+}
+
+// Confronta le note suonate con quelle attese, nota per nota.
+// Funziona sia dal vivo (eventi dall'ingresso MIDI) sia a posteriori
+// tramite `score_performance`.
+pub struct Scorer {
+    pub enabled: bool,
+    pub windows: TimingWindows,
+
+    // Un giudizio per ogni nota attesa (stesso indice di `song.notes`)
+    grades: Vec<Option<Grade>>,
+    // Prima nota attesa non ancora passata oltre la finestra di ritardo
+    next_unresolved: usize,
+    // Prima nota da valutare: quelle prima del punto di partenza (seek,
+    // inizio del loop) non si sono potute suonare
+    first_note: usize,
+    wrong: usize,
+    // Le note suonate dallo studente, per il riepilogo di fine brano
+    take: Vec<MidiNote>,
+    // Riepilogo calcolato una volta sola a fine brano
+    final_summary: Option<ScoreSummary>,
+}
+
+impl Scorer {
+    pub fn new(note_count: usize) -> Self {
+        Self {
+            enabled: false,
+            windows: TimingWindows::default(),
+            grades: vec![None; note_count],
+            next_unresolved: 0,
+            first_note: 0,
+            wrong: 0,
+            take: Vec::new(),
+            final_summary: None,
+        }
+    }
+
+    // Ricomincia dalla posizione data (dopo un seek, un giro del loop o un
+    // cambio di brano): le note già passate non contano, come in WaitMode::resync
+    pub fn reset(&mut self, position_secs: f32, notes: &[MidiNote]) {
+        let windows = self.windows;
+        let enabled = self.enabled;
+        *self = Self::new(notes.len());
+        self.windows = windows;
+        self.enabled = enabled;
+        self.first_note =
+            notes.partition_point(|n| n.start_time_secs + windows.late_secs < position_secs);
+        self.next_unresolved = self.first_note;
+    }
+
+    pub fn grade_of(&self, note_index: usize) -> Option<Grade> {
+        self.grades.get(note_index).copied().flatten()
+    }
+
+    // Valuta a posteriori le note suonate dall'ultimo punto di partenza;
+    // da chiamare a fine brano, il calcolo si fa una volta sola.
+    // Anche senza nessun tasto premuto: tutte le note risultano mancate
+    pub fn finish(&mut self, notes: &[MidiNote]) {
+        if self.final_summary.is_none() {
+            let expected = &notes[self.first_note.min(notes.len())..];
+            self.final_summary =
+                Some(score_performance(expected, &self.take, self.windows).summary());
+        }
+    }
+
+    pub fn final_summary(&self) -> Option<ScoreSummary> {
+        self.final_summary
+    }
+
+    // Registra un tasto premuto e restituisce il giudizio che ha ricevuto
+    pub fn note_on(
+        &mut self,
+        pitch: u8,
+        velocity: u8,
+        time_secs: f32,
+        notes: &[MidiNote],
+    ) -> Grade {
+        self.take.push(MidiNote {
+            track: 0,
+            channel: 0,
+            pitch,
+            velocity,
+            start_time_secs: time_secs,
+            duration_secs: 0.0,
+            hand: Hand::for_pitch(pitch, HAND_SPLIT_PITCH),
+        });
+
+        // Cerca la nota attesa con la stessa altezza più vicina nel tempo
+        let first =
+            notes.partition_point(|n| n.start_time_secs < time_secs - self.windows.late_secs);
+        let best = notes[first..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, n)| n.start_time_secs <= time_secs + self.windows.early_secs)
+            .filter(|(i, n)| n.pitch == pitch && self.grades[first + i].is_none())
+            .min_by(|(_, a), (_, b)| {
+                let da = (a.start_time_secs - time_secs).abs();
+                let db = (b.start_time_secs - time_secs).abs();
+                da.partial_cmp(&db).unwrap()
+            })
+            .map(|(i, n)| (first + i, n.start_time_secs));
+
+        let Some((index, expected_secs)) = best else {
+            self.wrong += 1;
+            return Grade::Wrong;
+        };
+        let offset = time_secs - expected_secs;
+        let grade = if offset.abs() <= self.windows.hit_secs {
+            Grade::Hit
+        } else if offset < 0.0 {
+            Grade::Early
+        } else {
+            Grade::Late
+        };
+        self.grades[index] = Some(grade);
+        grade
+    }
+
+    pub fn note_off(&mut self, pitch: u8, time_secs: f32) {
+        if let Some(note) = self
+            .take
+            .iter_mut()
+            .rev()
+            .find(|n| n.pitch == pitch && n.duration_secs == 0.0)
+        {
+            note.duration_secs = time_secs - note.start_time_secs;
+        }
+    }
+
+    // Le note attese ormai oltre la finestra di ritardo senza tasto sono "mancate"
+    pub fn update(&mut self, position_secs: f32, notes: &[MidiNote]) {
+        while let Some(note) = notes.get(self.next_unresolved) {
+            if note.start_time_secs + self.windows.late_secs >= position_secs {
+                break;
+            }
+            let grade = &mut self.grades[self.next_unresolved];
+            if grade.is_none() {
+                *grade = Some(Grade::Missed);
+            }
+            self.next_unresolved += 1;
+        }
+    }
+
+    pub fn summary(&self) -> ScoreSummary {
+        let mut summary = ScoreSummary {
+            wrong: self.wrong,
+            ..Default::default()
+        };
+        for grade in self.grades.iter().flatten() {
+            match grade {
+                Grade::Hit => summary.hit += 1,
+                Grade::Early => summary.early += 1,
+                Grade::Late => summary.late += 1,
+                Grade::Wrong => summary.wrong += 1,
+                Grade::Missed => summary.missed += 1,
+            }
+        }
+        summary
+    }
+}
+
+// Valuta a posteriori una esecuzione completa: `played` sono le note suonate,
+// con lo stesso riferimento temporale delle note attese.
+pub fn score_performance(
+    expected: &[MidiNote],
+    played: &[MidiNote],
+    windows: TimingWindows,
+) -> Scorer {
+    let mut scorer = Scorer::new(expected.len());
+    scorer.windows = windows;
+
+    let mut played: Vec<&MidiNote> = played.iter().collect();
+    played.sort_by(|a, b| a.start_time_secs.partial_cmp(&b.start_time_secs).unwrap());
+    for note in played {
+        scorer.update(note.start_time_secs, expected);
+        scorer.note_on(note.pitch, note.velocity, note.start_time_secs, expected);
+        scorer.note_off(note.pitch, note.start_time_secs + note.duration_secs);
+    }
+    scorer.update(f32::INFINITY, expected);
+    scorer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(pitch: u8, start_time_secs: f32) -> MidiNote {
+        MidiNote {
+            track: 0,
+            channel: 0,
+            pitch,
+            velocity: 100,
+            start_time_secs,
+            duration_secs: 0.25,
+            hand: Hand::for_pitch(pitch, HAND_SPLIT_PITCH),
+        }
+    }
+
+    // Do-Re-Mi-Fa, una nota al secondo
+    fn scale() -> Vec<MidiNote> {
+        [60, 62, 64, 65]
+            .iter()
+            .enumerate()
+            .map(|(i, &pitch)| note(pitch, i as f32))
+            .collect()
+    }
+
+    #[test]
+    fn perfect_take_is_all_hits() {
+        let expected = scale();
+        let summary = score_performance(&expected, &expected, TimingWindows::default()).summary();
+        assert_eq!(summary.hit, 4);
+        assert_eq!(summary.accuracy_percent(), 100.0);
+    }
+
+    #[test]
+    fn grades_early_late_wrong_and_missed() {
+        let expected = scale();
+        let played = [
+            note(60, -0.1), // in anticipo
+            note(62, 1.15), // in ritardo
+            note(61, 2.0),  // tasto sbagliato
+            note(64, 2.02), // giusta
+        ];
+        let scorer = score_performance(&expected, &played, TimingWindows::default());
+        assert_eq!(scorer.grade_of(0), Some(Grade::Early));
+        assert_eq!(scorer.grade_of(1), Some(Grade::Late));
+        assert_eq!(scorer.grade_of(2), Some(Grade::Hit));
+        assert_eq!(scorer.grade_of(3), Some(Grade::Missed));
+        let summary = scorer.summary();
+        assert_eq!(
+            summary,
+            ScoreSummary {
+                hit: 1,
+                early: 1,
+                late: 1,
+                wrong: 1,
+                missed: 1,
+            }
+        );
+        assert_eq!(summary.accuracy_percent(), 40.0);
+    }
+
+    #[test]
+    fn notes_outside_the_windows_are_not_matched() {
+        let expected = scale();
+        let played = [note(60, 0.5)];
+        let summary = score_performance(&expected, &played, TimingWindows::default()).summary();
+        assert_eq!(summary.wrong, 1);
+        assert_eq!(summary.missed, 4);
+    }
+
+    #[test]
+    fn reset_skips_notes_before_the_position() {
+        let expected = scale();
+        let mut scorer = Scorer::new(expected.len());
+        scorer.reset(1.5, &expected);
+        scorer.update(1.6, &expected);
+        assert_eq!(scorer.grade_of(0), None);
+        assert_eq!(scorer.grade_of(1), None);
+        assert_eq!(scorer.summary().missed, 0);
+
+        scorer.note_on(65, 100, 3.0, &expected);
+        scorer.update(10.0, &expected);
+        assert_eq!(scorer.grade_of(2), Some(Grade::Missed));
+        assert_eq!(scorer.grade_of(3), Some(Grade::Hit));
+    }
+
+    // Ogni giro del loop riparte da A: conta solo la parte suonata
+    #[test]
+    fn loop_repetition_is_scored_from_loop_start() {
+        let expected = scale();
+        let mut scorer = Scorer::new(expected.len());
+        scorer.reset(1.9, &expected);
+        scorer.note_on(64, 100, 2.0, &expected);
+        scorer.note_on(65, 100, 3.0, &expected);
+        scorer.update(3.5, &expected);
+        let summary = scorer.summary();
+        assert_eq!(summary.hit, 2);
+        assert_eq!(summary.missed, 0);
+        assert_eq!(summary.accuracy_percent(), 100.0);
+
+        scorer.finish(&expected);
+        let summary = scorer.final_summary().unwrap();
+        assert_eq!(summary.hit, 2);
+        assert_eq!(summary.missed, 0);
+    }
+
+    #[test]
+    fn untouched_song_is_all_missed() {
+        let expected = scale();
+        let mut scorer = Scorer::new(expected.len());
+        scorer.finish(&expected);
+        let summary = scorer.final_summary().unwrap();
+        assert_eq!(summary.hit, 0);
+        assert_eq!(summary.missed, expected.len());
+        assert_eq!(summary.accuracy_percent(), 0.0);
+    }
+}
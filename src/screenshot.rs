@@ -0,0 +1,63 @@
+// src/screenshot.rs
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Fattori di ingrandimento offerti rispetto alla finestra
+pub const SCREENSHOT_SCALES: [u32; 3] = [1, 2, 4];
+
+// Percorso libero per una nuova istantanea: accanto al brano, con data e ora
+// nel nome (es. `brano_2024-03-01_18-30-05.png`). Senza brano si usa la
+// cartella corrente.
+pub fn screenshot_path(song_path: Option<&Path>) -> PathBuf {
+    let (directory, stem) = match song_path {
+        Some(path) => (
+            path.parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+            path.file_stem()
+                .map_or("screenshot".into(), |s| s.to_string_lossy().into_owned()),
+        ),
+        None => (PathBuf::from("."), "screenshot".to_string()),
+    };
+    let base = format!("{}_{}", stem, timestamp(SystemTime::now()));
+    let mut path = directory.join(format!("{}.png", base));
+    // Più scatti nello stesso secondo non si sovrascrivono
+    let mut counter = 2;
+    while path.exists() {
+        path = directory.join(format!("{}_{}.png", base, counter));
+        counter += 1;
+    }
+    path
+}
+
+// Data e ora UTC nel formato `AAAA-MM-GG_hh-mm-ss`, valido nei nomi di file
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (days, seconds_of_day) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+// Giorni dal 1970-01-01 -> (anno, mese, giorno) del calendario gregoriano
+// (algoritmo di H. Hinnant, valido per qualsiasi data)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,152 @@
+// src/sequencer.rs
+use crate::midi_loader::{Hand, MidiNote, Song};
+use crate::synth::SynthEvent;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy)]
+pub struct TimedEvent {
+    pub time_secs: f32,
+    pub event: SynthEvent,
+    // Indice in `song.notes` della nota che ha generato l'evento, se c'è
+    pub note: Option<usize>,
+}
+
+// Quali note del brano vengono effettivamente suonate (synth e uscita MIDI):
+// si può escludere una mano o singole tracce, es. per lasciarle allo studente
+#[derive(Debug, Clone, Default)]
+pub struct PlaybackFilter {
+    pub mute_left_hand: bool,
+    pub mute_right_hand: bool,
+    pub muted_tracks: BTreeSet<usize>,
+}
+
+impl PlaybackFilter {
+    pub fn allows(&self, note: &MidiNote) -> bool {
+        let hand_muted = match note.hand {
+            Hand::Left => self.mute_left_hand,
+            Hand::Right => self.mute_right_hand,
+        };
+        !hand_muted && !self.muted_tracks.contains(&note.track)
+    }
+}
+
+// Trasforma il brano in una lista ordinata di eventi da suonare e la
+// scorre seguendo la posizione del trasporto.
+pub struct Sequencer {
+    events: Vec<TimedEvent>,
+    // Primo evento non ancora emesso
+    cursor: usize,
+    // Stato dei canali (strumento, pedale) da ripristinare dopo un seek
+    restore: Vec<TimedEvent>,
+}
+
+impl Sequencer {
+    pub fn new(song: &Song) -> Self {
+        Self::with_note_lead(song, 0.0)
+    }
+
+    // Come `new`, ma ogni NoteOn arriva `lead_secs` prima della nota
+    // (i NoteOff restano alla fine della nota)
+    pub fn with_note_lead(song: &Song, lead_secs: f32) -> Self {
+        let mut events = Vec::with_capacity(
+            song.notes.len() * 2 + song.pedal_events.len() + song.program_changes.len(),
+        );
+        for change in &song.program_changes {
+            events.push(TimedEvent {
+                time_secs: change.time_secs,
+                event: SynthEvent::ProgramChange {
+                    channel: change.channel,
+                    bank: change.bank,
+                    program: change.program,
+                },
+                note: None,
+            });
+        }
+        for (index, note) in song.notes.iter().enumerate() {
+            events.push(TimedEvent {
+                time_secs: note.start_time_secs - lead_secs,
+                event: SynthEvent::NoteOn {
+                    channel: note.channel,
+                    pitch: note.pitch,
+                    velocity: note.velocity,
+                },
+                note: Some(index),
+            });
+            events.push(TimedEvent {
+                time_secs: note.start_time_secs + note.duration_secs,
+                event: SynthEvent::NoteOff {
+                    channel: note.channel,
+                    pitch: note.pitch,
+                },
+                note: Some(index),
+            });
+        }
+        for pedal in &song.pedal_events {
+            events.push(TimedEvent {
+                time_secs: pedal.time_secs,
+                event: SynthEvent::Sustain {
+                    channel: pedal.channel,
+                    down: pedal.down,
+                },
+                note: None,
+            });
+        }
+        // A parità di tempo: prima i cambi di strumento, poi i NoteOff (altrimenti
+        // una nota ribattuta verrebbe spenta subito dopo essere ripartita)
+        events.sort_by(|a, b| {
+            a.time_secs
+                .partial_cmp(&b.time_secs)
+                .unwrap()
+                .then_with(|| Self::order(&a.event).cmp(&Self::order(&b.event)))
+        });
+
+        Self {
+            events,
+            cursor: 0,
+            restore: Vec::new(),
+        }
+    }
+
+    fn order(event: &SynthEvent) -> u8 {
+        match event {
+            SynthEvent::ProgramChange { .. } => 0,
+            SynthEvent::NoteOff { .. } => 1,
+            _ => 2,
+        }
+    }
+
+    // Riposiziona il cursore: gli eventi prima di `position_secs` non verranno
+    // emessi, ma strumento e pedale di ogni canale vengono ripristinati
+    pub fn seek(&mut self, position_secs: f32) {
+        self.cursor = self.events.partition_point(|e| e.time_secs < position_secs);
+
+        let mut programs = BTreeMap::new();
+        let mut pedals = BTreeMap::new();
+        for timed in &self.events[..self.cursor] {
+            match timed.event {
+                SynthEvent::ProgramChange { channel, .. } => {
+                    programs.insert(channel, *timed);
+                }
+                SynthEvent::Sustain { channel, .. } => {
+                    pedals.insert(channel, *timed);
+                }
+                _ => {}
+            }
+        }
+        self.restore = programs.into_values().chain(pedals.into_values()).collect();
+    }
+
+    // Emette in ordine tutti gli eventi con tempo < `position_secs`
+    pub fn advance(&mut self, position_secs: f32, mut emit: impl FnMut(TimedEvent)) {
+        for event in self.restore.drain(..) {
+            emit(event);
+        }
+        while let Some(event) = self.events.get(self.cursor) {
+            if event.time_secs >= position_secs {
+                break;
+            }
+            emit(*event);
+            self.cursor += 1;
+        }
+    }
+}
@@ -0,0 +1,246 @@
+// src/session.rs
+// File di sessione (.pvs): un brano con tutto ciò che serve a una lezione,
+// cioè aspetto, loop, mani, segnaposti e note per lo studente. Il MIDI può
+// essere indicato con il percorso o messo dentro il file, così l'insegnante
+// prepara la lezione una volta e lo studente apre un file solo.
+use crate::midi_loader::{self, HandAssignment, Song};
+use crate::paths;
+use crate::settings::{self, ViewSettings};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const SESSION_EXTENSION: &str = "pvs";
+
+// Da incrementare quando cambia il formato del file
+const SESSION_VERSION: u64 = 1;
+
+// Punto del brano a cui saltare
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub time_secs: f32,
+    pub name: String,
+}
+
+// Testo mostrato sopra le note mentre il brano passa da lì
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub start_secs: f32,
+    pub end_secs: f32,
+    pub text: String,
+}
+
+// Un loop A-B da richiamare con un nome
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedLoop {
+    pub name: String,
+    pub start_secs: f32,
+    pub end_secs: f32,
+}
+
+// Materiale di studio legato al brano aperto
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Lesson {
+    pub markers: Vec<Marker>,
+    pub annotations: Vec<Annotation>,
+    pub loops: Vec<SavedLoop>,
+}
+
+impl Lesson {
+    // Segnaposti e note restano in ordine di tempo
+    pub fn add_marker(&mut self, time_secs: f32, name: &str) {
+        let index = self.markers.partition_point(|m| m.time_secs <= time_secs);
+        let name = match name.trim() {
+            "" => format!("Segnaposto {}", self.markers.len() + 1),
+            name => name.to_string(),
+        };
+        self.markers.insert(index, Marker { time_secs, name });
+    }
+
+    pub fn add_annotation(&mut self, start_secs: f32, duration_secs: f32, text: &str) {
+        let index = self
+            .annotations
+            .partition_point(|a| a.start_secs <= start_secs);
+        self.annotations.insert(
+            index,
+            Annotation {
+                start_secs,
+                end_secs: start_secs + duration_secs,
+                text: text.trim().to_string(),
+            },
+        );
+    }
+
+    pub fn add_loop(&mut self, (start_secs, end_secs): (f32, f32), name: &str) {
+        let name = match name.trim() {
+            "" => format!("Loop {}", self.loops.len() + 1),
+            name => name.to_string(),
+        };
+        self.loops.push(SavedLoop {
+            name,
+            start_secs,
+            end_secs,
+        });
+    }
+
+    // Le note da mostrare all'istante dato
+    pub fn annotations_at(&self, time_secs: f32) -> impl Iterator<Item = &Annotation> {
+        self.annotations
+            .iter()
+            .filter(move |a| a.start_secs <= time_secs && time_secs < a.end_secs)
+    }
+
+    // Tempi non validi (es. file scritti a mano) riportati a qualcosa di usabile
+    fn validate(&mut self) {
+        for marker in &mut self.markers {
+            marker.time_secs = marker.time_secs.max(0.0);
+        }
+        self.markers
+            .sort_by(|a, b| a.time_secs.total_cmp(&b.time_secs));
+        for annotation in &mut self.annotations {
+            annotation.start_secs = annotation.start_secs.max(0.0);
+            annotation.end_secs = annotation.end_secs.max(annotation.start_secs);
+        }
+        self.annotations
+            .sort_by(|a, b| a.start_secs.total_cmp(&b.start_secs));
+        self.loops.retain(|l| l.start_secs < l.end_secs);
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub version: u64,
+    // Relativo alla cartella della sessione se il brano sta lì vicino
+    // (None con midi_data vuoto = note dimostrative)
+    pub midi_path: Option<PathBuf>,
+    // Il file MIDI stesso in base64; se c'è, il percorso serve solo per il nome
+    #[serde(with = "base64_bytes")]
+    pub midi_data: Option<Vec<u8>>,
+    pub preset: Option<String>,
+    pub view: Option<ViewSettings>,
+    pub loop_region: Option<(f32, f32)>,
+    pub hands: HandAssignment,
+    pub lesson: Lesson,
+}
+
+// Il brano di una sessione aperta: note, percorso e byte del file MIDI
+pub struct SessionSong {
+    pub song: Song,
+    pub path: Option<PathBuf>,
+    pub bytes: Option<Vec<u8>>,
+}
+
+mod base64_bytes {
+    use super::BASE64;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        bytes: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_some(&BASE64.encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| BASE64.decode(text.trim()).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+pub fn is_session_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(SESSION_EXTENSION))
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            version: SESSION_VERSION,
+            ..Default::default()
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Impossibile leggere '{}': {}", path.display(), e))?;
+        let mut session: Session = serde_json::from_str(&text)
+            .map_err(|e| format!("Sessione '{}' non valida: {}", path.display(), e))?;
+        let mut warnings = Vec::new();
+        if session.version > SESSION_VERSION {
+            warnings.push(settings::backup_newer(
+                path,
+                session.version,
+                SESSION_VERSION,
+            ));
+        }
+        session.version = SESSION_VERSION;
+
+        if let Some(view) = &mut session.view {
+            view.validate("view", &mut warnings);
+        }
+        if session.hands.split_pitch > 127 {
+            warnings.push(format!(
+                "hands.split_pitch = {} non valido, uso il Do centrale",
+                session.hands.split_pitch
+            ));
+            session.hands.split_pitch = HandAssignment::default().split_pitch;
+        }
+        session.loop_region = session
+            .loop_region
+            .filter(|(start, end)| 0.0 <= *start && start <= end);
+        session.lesson.validate();
+        for warning in warnings {
+            eprintln!("[ATTENZIONE] Sessione: {}", warning);
+        }
+        Ok(session)
+    }
+
+    // Il MIDI della sessione: quello incorporato se c'è, altrimenti il file
+    // indicato (cercato accanto alla sessione se il percorso è relativo)
+    pub fn song(&self, session_path: &Path) -> Result<SessionSong, String> {
+        let path = self
+            .midi_path
+            .as_ref()
+            .map(|p| paths::directory_of(session_path).join(p));
+        let bytes = match (&self.midi_data, &path) {
+            (Some(bytes), _) => bytes.clone(),
+            (None, Some(path)) => std::fs::read(path).map_err(|e| {
+                format!(
+                    "Impossibile leggere il brano della sessione '{}': {}",
+                    path.display(),
+                    e
+                )
+            })?,
+            (None, None) => {
+                return Ok(SessionSong {
+                    song: Song::demo(),
+                    path: None,
+                    bytes: None,
+                });
+            }
+        };
+        let song = midi_loader::parse_midi(&bytes)
+            .map_err(|e| format!("'{}': {}", session_path.display(), e))?;
+        Ok(SessionSong {
+            song,
+            path,
+            bytes: Some(bytes),
+        })
+    }
+
+    pub fn save(mut self, path: &Path) -> Result<(), String> {
+        if let Some(midi_path) = &self.midi_path {
+            self.midi_path = Some(paths::relative_to(midi_path, paths::directory_of(path)));
+        }
+        settings::save_json(path, &self)
+    }
+}
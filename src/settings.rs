@@ -0,0 +1,641 @@
+// src/settings.rs
+// Impostazioni dell'utente salvate fra un avvio e l'altro, in JSON nella
+// cartella di configurazione. Il file porta un numero di versione: quelli
+// vecchi vengono migrati, i valori fuori intervallo riportati nei limiti.
+use crate::cli;
+use crate::computer_keyboard::ComputerKeyboard;
+use crate::config::{COLOR_LEFT_HAND, COLOR_RIGHT_HAND, FALL_DURATION_SECS};
+use crate::key_lights::{self, HandLight};
+use crate::layout::KeyRange;
+use crate::metronome::Metronome;
+use crate::paths;
+use crate::playlist::{Playlist, Repeat};
+use crate::presets;
+use crate::scoring::TimingWindows;
+use crate::screenshot::SCREENSHOT_SCALES;
+use crate::shortcuts::{Action, KeyBinding, Shortcuts};
+use crate::wait_mode::{WaitHands, WaitMode};
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Da incrementare (aggiungendo una voce a MIGRATIONS) quando un campo
+// cambia nome o significato; i campi nuovi bastano i valori di default
+pub const SETTINGS_VERSION: u64 = 1;
+
+// MIGRATIONS[i] porta un file dalla versione i + 1 alla i + 2
+type Migration = fn(&mut serde_json::Map<String, serde_json::Value>);
+const MIGRATIONS: &[Migration] = &[];
+
+// Pausa dopo l'ultimo cambiamento prima di scrivere il file
+pub const SAVE_DELAY: Duration = Duration::from_secs(1);
+// Ogni quanto si controlla se le impostazioni sono cambiate
+pub const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u64,
+    // Preset da cui viene l'aspetto (None = personalizzato)
+    pub preset: Option<String>,
+    pub view: ViewSettings,
+    pub playback: PlaybackSettings,
+    pub audio: AudioSettings,
+    pub input: InputSettings,
+    pub output: OutputSettings,
+    pub practice: PracticeSettings,
+    pub playlist: PlaylistSettings,
+    pub remote: RemoteSettings,
+    pub screenshot: ScreenshotSettings,
+    // Azione -> tasto, es. "play_pause": "Space" ("" = nessun tasto)
+    pub shortcuts: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewSettings {
+    pub fall_duration_secs: f32,
+    #[serde(with = "hex_color")]
+    pub color_left_hand: Color32,
+    #[serde(with = "hex_color")]
+    pub color_right_hand: Color32,
+    // None = colonne a larghezza fissa attorno al Do3
+    pub key_range: Option<KeyRange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaybackSettings {
+    pub speed: f32,
+    pub metronome: bool,
+    pub count_in_bars: u32,
+    pub mute_left_hand: bool,
+    pub mute_right_hand: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub synth_enabled: bool,
+    pub synth_volume: f32,
+    // Vuoto = synth additivo integrato
+    pub soundfont_path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputSettings {
+    // Porte a cui ricollegarsi all'avvio, se ancora presenti
+    pub midi_port: Option<String>,
+    pub sync_port: Option<String>,
+    pub mtc_offset_secs: f32,
+    pub computer_keyboard: bool,
+    pub computer_keyboard_velocity: u8,
+    pub computer_keyboard_base_pitch: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputSettings {
+    pub midi_port: Option<String>,
+    pub key_lights: bool,
+    pub key_lights_look_ahead_secs: f32,
+    // Canali 0-15 come nei messaggi MIDI
+    pub key_lights_left: HandLight,
+    pub key_lights_right: HandLight,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PracticeSettings {
+    pub wait_mode: bool,
+    pub wait_hands: WaitHands,
+    pub chord_tolerance_secs: f32,
+    pub scoring: bool,
+    pub timing_windows: TimingWindows,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaylistSettings {
+    pub auto_advance: bool,
+    pub shuffle: bool,
+    pub repeat: Repeat,
+    pub gap_secs: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteSettings {
+    pub osc_port: u16,
+    pub osc_target: String,
+    pub http_port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenshotSettings {
+    pub scale: u32,
+    pub with_ui: bool,
+}
+
+// --- VALORI DI DEFAULT ---
+// Gli stessi con cui partono i vari moduli
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            preset: Some(presets::DEFAULT_PRESET.to_string()),
+            view: ViewSettings::default(),
+            playback: PlaybackSettings::default(),
+            audio: AudioSettings::default(),
+            input: InputSettings::default(),
+            output: OutputSettings::default(),
+            practice: PracticeSettings::default(),
+            playlist: PlaylistSettings::default(),
+            remote: RemoteSettings::default(),
+            screenshot: ScreenshotSettings::default(),
+            shortcuts: Shortcuts::new().to_map(),
+        }
+    }
+}
+
+impl Default for ViewSettings {
+    fn default() -> Self {
+        Self {
+            fall_duration_secs: FALL_DURATION_SECS,
+            color_left_hand: COLOR_LEFT_HAND,
+            color_right_hand: COLOR_RIGHT_HAND,
+            key_range: None,
+        }
+    }
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        let metronome = Metronome::new();
+        Self {
+            speed: 1.0,
+            metronome: metronome.enabled,
+            count_in_bars: metronome.count_in_bars,
+            mute_left_hand: false,
+            mute_right_hand: false,
+        }
+    }
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            synth_enabled: true,
+            synth_volume: 0.8,
+            soundfont_path: String::new(),
+        }
+    }
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        let keyboard = ComputerKeyboard::new();
+        Self {
+            midi_port: None,
+            sync_port: None,
+            mtc_offset_secs: 0.0,
+            computer_keyboard: keyboard.enabled,
+            computer_keyboard_velocity: keyboard.velocity,
+            computer_keyboard_base_pitch: keyboard.base_pitch,
+        }
+    }
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            midi_port: None,
+            key_lights: false,
+            key_lights_look_ahead_secs: key_lights::DEFAULT_LOOK_AHEAD_SECS,
+            key_lights_left: HandLight::DEFAULT_LEFT,
+            key_lights_right: HandLight::DEFAULT_RIGHT,
+        }
+    }
+}
+
+impl Default for PracticeSettings {
+    fn default() -> Self {
+        let wait_mode = WaitMode::new();
+        Self {
+            wait_mode: wait_mode.enabled,
+            wait_hands: wait_mode.hands,
+            chord_tolerance_secs: wait_mode.chord_tolerance_secs,
+            scoring: false,
+            timing_windows: TimingWindows::default(),
+        }
+    }
+}
+
+impl Default for PlaylistSettings {
+    fn default() -> Self {
+        let playlist = Playlist::new();
+        Self {
+            auto_advance: playlist.auto_advance,
+            shuffle: playlist.shuffle,
+            repeat: playlist.repeat,
+            gap_secs: playlist.gap_secs,
+        }
+    }
+}
+
+impl Default for RemoteSettings {
+    fn default() -> Self {
+        Self {
+            osc_port: crate::osc::DEFAULT_OSC_PORT,
+            osc_target: String::new(),
+            http_port: crate::http_api::DEFAULT_HTTP_PORT,
+        }
+    }
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self {
+            scale: 1,
+            with_ui: true,
+        }
+    }
+}
+
+// I colori nel file come "RRGGBB", lo stesso formato della riga di comando
+mod hex_color {
+    use egui::Color32;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!(
+            "{:02X}{:02X}{:02X}",
+            color.r(),
+            color.g(),
+            color.b()
+        ))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color32, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        super::cli::parse_color(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+// Riporta `value` dentro `range`, annotando la correzione
+fn clamp<T: PartialOrd + Copy + std::fmt::Display>(
+    value: &mut T,
+    min: T,
+    max: T,
+    name: &str,
+    warnings: &mut Vec<String>,
+) {
+    let clamped = if *value < min {
+        min
+    } else if *value > max {
+        max
+    } else {
+        return;
+    };
+    warnings.push(format!(
+        "{} = {} fuori dai limiti ({}-{}), uso {}",
+        name, value, min, max, clamped
+    ));
+    *value = clamped;
+}
+
+impl ViewSettings {
+    // Come Settings::validate, per l'aspetto da solo (anche nei preset);
+    // `section` dà il nome ai campi nei messaggi
+    pub fn validate(&mut self, section: &str, warnings: &mut Vec<String>) {
+        clamp(
+            &mut self.fall_duration_secs,
+            0.5,
+            10.0,
+            &format!("{}.fall_duration_secs", section),
+            warnings,
+        );
+        if let Some(range) = self.key_range
+            && (range.low >= range.high || range.high > 127)
+        {
+            warnings.push(format!(
+                "{}.key_range = {}-{} non valido, uso i tasti attorno al Do3",
+                section, range.low, range.high
+            ));
+            self.key_range = None;
+        }
+    }
+}
+
+pub fn settings_path() -> PathBuf {
+    paths::config_dir().join("settings.json")
+}
+
+impl Settings {
+    // Le impostazioni salvate; se il file manca o è illeggibile, i default.
+    // Un file rovinato viene messo da parte (.bak) invece di essere sovrascritto.
+    pub fn load() -> Self {
+        let path = settings_path();
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                eprintln!(
+                    "[ATTENZIONE] Impossibile leggere '{}': {}",
+                    path.display(),
+                    e
+                );
+                return Self::default();
+            }
+        };
+        match Self::from_json(&text) {
+            Ok((settings, version, mut warnings)) => {
+                if version > SETTINGS_VERSION {
+                    warnings.insert(0, backup_newer(&path, version, SETTINGS_VERSION));
+                }
+                for warning in warnings {
+                    eprintln!("[ATTENZIONE] Impostazioni: {}", warning);
+                }
+                settings
+            }
+            Err(e) => {
+                let backup = path.with_extension("json.bak");
+                eprintln!(
+                    "[ATTENZIONE] Impostazioni non valide in '{}': {}. Uso i valori di default (il file è copiato in '{}')",
+                    path.display(),
+                    e,
+                    backup.display()
+                );
+                if let Err(e) = std::fs::rename(&path, &backup) {
+                    eprintln!("[ATTENZIONE] Impossibile salvare la copia: {}", e);
+                }
+                Self::default()
+            }
+        }
+    }
+
+    // Legge, migra e valida il contenuto del file; restituisce anche la
+    // versione del file e le correzioni fatte, da mostrare all'utente
+    fn from_json(text: &str) -> Result<(Self, u64, Vec<String>), String> {
+        let mut value: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let object = value
+            .as_object_mut()
+            .ok_or("il file non contiene un oggetto JSON")?;
+        // Senza numero di versione si assume quella attuale
+        let version = match object.get("version") {
+            None => SETTINGS_VERSION,
+            Some(version) => version
+                .as_u64()
+                .filter(|&v| v >= 1)
+                .ok_or("campo 'version' non valido")?,
+        };
+
+        for migration in MIGRATIONS.iter().skip(version as usize - 1) {
+            migration(object);
+        }
+        object.insert("version".to_string(), SETTINGS_VERSION.into());
+
+        let mut settings: Settings = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        let mut warnings = Vec::new();
+        settings.validate(&mut warnings);
+        Ok((settings, version, warnings))
+    }
+
+    // Riporta ogni valore nei limiti accettati dall'interfaccia
+    fn validate(&mut self, warnings: &mut Vec<String>) {
+        self.view.validate("view", warnings);
+
+        let playback = &mut self.playback;
+        clamp(&mut playback.speed, 0.25, 2.0, "playback.speed", warnings);
+        clamp(
+            &mut playback.count_in_bars,
+            0,
+            4,
+            "playback.count_in_bars",
+            warnings,
+        );
+
+        clamp(
+            &mut self.audio.synth_volume,
+            0.0,
+            1.0,
+            "audio.synth_volume",
+            warnings,
+        );
+
+        let input = &mut self.input;
+        clamp(
+            &mut input.computer_keyboard_velocity,
+            1,
+            127,
+            "input.computer_keyboard_velocity",
+            warnings,
+        );
+        // La tastiera del computer parte sempre da un Do
+        let base_pitch = &mut input.computer_keyboard_base_pitch;
+        clamp(
+            base_pitch,
+            12,
+            84,
+            "input.computer_keyboard_base_pitch",
+            warnings,
+        );
+        *base_pitch -= *base_pitch % 12;
+
+        let output = &mut self.output;
+        clamp(
+            &mut output.key_lights_look_ahead_secs,
+            0.0,
+            2.0,
+            "output.key_lights_look_ahead_secs",
+            warnings,
+        );
+        for (name, light) in [
+            ("output.key_lights_left", &mut output.key_lights_left),
+            ("output.key_lights_right", &mut output.key_lights_right),
+        ] {
+            clamp(
+                &mut light.channel,
+                0,
+                15,
+                &format!("{}.channel", name),
+                warnings,
+            );
+            clamp(
+                &mut light.color_velocity,
+                1,
+                127,
+                &format!("{}.color_velocity", name),
+                warnings,
+            );
+        }
+
+        let practice = &mut self.practice;
+        clamp(
+            &mut practice.chord_tolerance_secs,
+            0.0,
+            0.5,
+            "practice.chord_tolerance_secs",
+            warnings,
+        );
+        let windows = &mut practice.timing_windows;
+        clamp(
+            &mut windows.hit_secs,
+            0.01,
+            0.2,
+            "practice.timing_windows.hit_secs",
+            warnings,
+        );
+        clamp(
+            &mut windows.early_secs,
+            0.05,
+            0.5,
+            "practice.timing_windows.early_secs",
+            warnings,
+        );
+        clamp(
+            &mut windows.late_secs,
+            0.05,
+            0.5,
+            "practice.timing_windows.late_secs",
+            warnings,
+        );
+
+        clamp(
+            &mut self.playlist.gap_secs,
+            0.0,
+            30.0,
+            "playlist.gap_secs",
+            warnings,
+        );
+
+        let remote = &mut self.remote;
+        clamp(
+            &mut remote.osc_port,
+            1024,
+            65535,
+            "remote.osc_port",
+            warnings,
+        );
+        clamp(
+            &mut remote.http_port,
+            1024,
+            65535,
+            "remote.http_port",
+            warnings,
+        );
+
+        if !SCREENSHOT_SCALES.contains(&self.screenshot.scale) {
+            warnings.push(format!(
+                "screenshot.scale = {} non valido, uso 1",
+                self.screenshot.scale
+            ));
+            self.screenshot.scale = 1;
+        }
+
+        // Le voci sbagliate tornano al tasto di partenza
+        self.shortcuts.retain(|id, text| {
+            let problem = match (Action::from_id(id), text.trim()) {
+                (None, _) => "azione sconosciuta".to_string(),
+                (Some(_), "") => return true,
+                (Some(_), text) => match text.parse::<KeyBinding>() {
+                    Ok(_) => return true,
+                    Err(e) => e,
+                },
+            };
+            warnings.push(format!(
+                "shortcuts.{} = \"{}\" non valido ({}), uso il tasto predefinito",
+                id, text, problem
+            ));
+            false
+        });
+    }
+
+    // Scrive il file passando da uno temporaneo, così un'interruzione non
+    // lascia impostazioni a metà
+    pub fn save(&self) -> Result<(), String> {
+        save_json(&settings_path(), self)
+    }
+}
+
+// Un file scritto da una versione più recente del programma può avere campi
+// che questa non conosce e che andrebbero persi riscrivendolo: prima se ne fa
+// una copia accanto (es. settings.json.v2.bak). Restituisce l'avviso per l'utente.
+pub fn backup_newer(path: &Path, version: u64, current: u64) -> String {
+    let extension = path
+        .extension()
+        .map(|ext| format!("{}.", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let backup = path.with_extension(format!("{}v{}.bak", extension, version));
+    let newer = format!(
+        "file creato da una versione più recente ({}, questo programma usa la {})",
+        version, current
+    );
+    match std::fs::copy(path, &backup) {
+        Ok(_) => format!(
+            "{}: i campi sconosciuti non verranno salvati, l'originale è copiato in '{}'",
+            newer,
+            backup.display()
+        ),
+        Err(e) => format!(
+            "{}: i campi sconosciuti non verranno salvati e non è stato possibile copiare l'originale in '{}': {}",
+            newer,
+            backup.display(),
+            e
+        ),
+    }
+}
+
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| format!("Impossibile creare '{}': {}", directory.display(), e))?;
+    }
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    let partial = path.with_extension("part");
+    std::fs::write(&partial, json + "\n")
+        .and_then(|()| std::fs::rename(&partial, path))
+        .map_err(|e| format!("Impossibile salvare '{}': {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_out_of_range_are_clamped() {
+        let (settings, version, warnings) =
+            Settings::from_json(r#"{"version": 1, "playback": {"speed": 9.0}}"#).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(settings.playback.speed, 2.0);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn newer_file_is_copied_before_being_rewritten() {
+        let directory = std::env::temp_dir().join(format!("pv-settings-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("settings.json");
+        let text = r#"{"version": 2, "from_the_future": true}"#;
+        std::fs::write(&path, text).unwrap();
+
+        let (settings, version, _) = Settings::from_json(text).unwrap();
+        assert_eq!((version, settings.version), (2, SETTINGS_VERSION));
+        let warning = backup_newer(&path, version, SETTINGS_VERSION);
+        let backup = directory.join("settings.json.v2.bak");
+        assert!(
+            warning.contains(&backup.display().to_string()),
+            "{}",
+            warning
+        );
+
+        save_json(&path, &settings).unwrap();
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), text);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
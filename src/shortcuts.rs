@@ -0,0 +1,478 @@
+// src/shortcuts.rs
+// Scorciatoie da tastiera: ogni azione ha al più un tasto (con i suoi
+// modificatori), che l'utente cambia dalle impostazioni. Nel file delle
+// impostazioni i tasti sono scritti come "Ctrl+Shift+F11".
+use egui::{Key, Modifiers};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+// Passo di avanti/indietro e di velocità
+pub const SEEK_STEP_SECS: f32 = 5.0;
+pub const SPEED_STEP: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    PlayPause,
+    Restart,
+    SeekBackward,
+    SeekForward,
+    SpeedDown,
+    SpeedUp,
+    LoopStart,
+    LoopEnd,
+    LoopClear,
+    OpenFile,
+    PreviousSong,
+    NextSong,
+    ToggleUi,
+    Fullscreen,
+    Screenshot,
+}
+
+impl Action {
+    pub const ALL: [Action; 15] = [
+        Action::PlayPause,
+        Action::Restart,
+        Action::SeekBackward,
+        Action::SeekForward,
+        Action::SpeedDown,
+        Action::SpeedUp,
+        Action::LoopStart,
+        Action::LoopEnd,
+        Action::LoopClear,
+        Action::OpenFile,
+        Action::PreviousSong,
+        Action::NextSong,
+        Action::ToggleUi,
+        Action::Fullscreen,
+        Action::Screenshot,
+    ];
+
+    // Nome nel file delle impostazioni: non va mai cambiato
+    pub fn id(self) -> &'static str {
+        match self {
+            Action::PlayPause => "play_pause",
+            Action::Restart => "restart",
+            Action::SeekBackward => "seek_backward",
+            Action::SeekForward => "seek_forward",
+            Action::SpeedDown => "speed_down",
+            Action::SpeedUp => "speed_up",
+            Action::LoopStart => "loop_start",
+            Action::LoopEnd => "loop_end",
+            Action::LoopClear => "loop_clear",
+            Action::OpenFile => "open_file",
+            Action::PreviousSong => "previous_song",
+            Action::NextSong => "next_song",
+            Action::ToggleUi => "toggle_ui",
+            Action::Fullscreen => "fullscreen",
+            Action::Screenshot => "screenshot",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.id() == id)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::PlayPause => "Play / Pausa",
+            Action::Restart => "Ricomincia",
+            Action::SeekBackward => "Indietro di 5 s",
+            Action::SeekForward => "Avanti di 5 s",
+            Action::SpeedDown => "Più lento",
+            Action::SpeedUp => "Più veloce",
+            Action::LoopStart => "Imposta loop A",
+            Action::LoopEnd => "Imposta loop B",
+            Action::LoopClear => "Disattiva loop",
+            Action::OpenFile => "Apri file",
+            Action::PreviousSong => "Brano precedente",
+            Action::NextSong => "Brano successivo",
+            Action::ToggleUi => "Mostra/nascondi interfaccia",
+            Action::Fullscreen => "Schermo intero",
+            Action::Screenshot => "Screenshot",
+        }
+    }
+
+    // I tasti di partenza evitano quelli della tastiera del computer come
+    // strumento (lettere, numeri, - e +), salvo con Ctrl
+    fn default_binding(self) -> KeyBinding {
+        let (modifiers, key) = match self {
+            Action::PlayPause => (Modifiers::NONE, Key::Space),
+            Action::Restart => (Modifiers::NONE, Key::Home),
+            Action::SeekBackward => (Modifiers::NONE, Key::ArrowLeft),
+            Action::SeekForward => (Modifiers::NONE, Key::ArrowRight),
+            Action::SpeedDown => (Modifiers::NONE, Key::ArrowDown),
+            Action::SpeedUp => (Modifiers::NONE, Key::ArrowUp),
+            Action::LoopStart => (Modifiers::COMMAND, Key::A),
+            Action::LoopEnd => (Modifiers::COMMAND, Key::B),
+            Action::LoopClear => (Modifiers::COMMAND, Key::L),
+            Action::OpenFile => (Modifiers::COMMAND, Key::O),
+            Action::PreviousSong => (Modifiers::NONE, Key::PageUp),
+            Action::NextSong => (Modifiers::NONE, Key::PageDown),
+            Action::ToggleUi => (Modifiers::NONE, Key::F1),
+            Action::Fullscreen => (Modifiers::NONE, Key::F11),
+            Action::Screenshot => (Modifiers::NONE, Key::F12),
+        };
+        KeyBinding { modifiers, key }
+    }
+}
+
+// Tutti i tasti di egui, per rileggere i nomi dal file
+const KEYS: [Key; 73] = [
+    Key::ArrowDown,
+    Key::ArrowLeft,
+    Key::ArrowRight,
+    Key::ArrowUp,
+    Key::Escape,
+    Key::Tab,
+    Key::Backspace,
+    Key::Enter,
+    Key::Space,
+    Key::Insert,
+    Key::Delete,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+    Key::Minus,
+    Key::PlusEquals,
+    Key::Num0,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+    Key::F13,
+    Key::F14,
+    Key::F15,
+    Key::F16,
+    Key::F17,
+    Key::F18,
+    Key::F19,
+    Key::F20,
+];
+
+// Un tasto con i modificatori. "Ctrl" è Cmd su macOS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub modifiers: Modifiers,
+    pub key: Key,
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.command || self.modifiers.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut modifiers = Modifiers::NONE;
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key_name = parts.pop().unwrap_or_default();
+        for part in parts {
+            match part.to_lowercase().as_str() {
+                "ctrl" | "cmd" => modifiers.command = true,
+                "alt" => modifiers.alt = true,
+                "shift" => modifiers.shift = true,
+                _ => return Err(format!("modificatore '{}' sconosciuto", part)),
+            }
+        }
+        let key = KEYS
+            .into_iter()
+            .find(|key| key.name().eq_ignore_ascii_case(key_name))
+            .ok_or_else(|| format!("tasto '{}' sconosciuto", key_name))?;
+        Ok(KeyBinding { modifiers, key })
+    }
+}
+
+// Tasti di tutte le azioni; un tasto appartiene a una sola azione
+pub struct Shortcuts {
+    bindings: BTreeMap<Action, KeyBinding>,
+    // Azione che aspetta il nuovo tasto dall'interfaccia
+    pub capturing: Option<Action>,
+}
+
+impl Shortcuts {
+    pub fn new() -> Self {
+        Self {
+            bindings: Action::ALL
+                .into_iter()
+                .map(|action| (action, action.default_binding()))
+                .collect(),
+            capturing: None,
+        }
+    }
+
+    pub fn binding(&self, action: Action) -> Option<KeyBinding> {
+        self.bindings.get(&action).copied()
+    }
+
+    // Assegna il tasto all'azione, togliendolo a quella che l'aveva;
+    // restituisce quest'ultima per avvisare l'utente
+    pub fn bind(&mut self, action: Action, binding: KeyBinding) -> Option<Action> {
+        let previous = self
+            .bindings
+            .iter()
+            .find(|(other, b)| **other != action && **b == binding)
+            .map(|(other, _)| *other);
+        if let Some(other) = previous {
+            self.bindings.remove(&other);
+        }
+        self.bindings.insert(action, binding);
+        previous
+    }
+
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings.remove(&action);
+    }
+
+    // Come nelle impostazioni: azione -> tasto ("" = nessun tasto)
+    pub fn to_map(&self) -> BTreeMap<String, String> {
+        Action::ALL
+            .into_iter()
+            .map(|action| {
+                let binding = self
+                    .binding(action)
+                    .map(|b| b.to_string())
+                    .unwrap_or_default();
+                (action.id().to_string(), binding)
+            })
+            .collect()
+    }
+
+    // Le azioni che mancano nella mappa tengono il tasto di partenza
+    pub fn from_map(map: &BTreeMap<String, String>) -> Self {
+        let mut shortcuts = Self::new();
+        for (id, text) in map {
+            let Some(action) = Action::from_id(id) else {
+                continue;
+            };
+            match text.trim() {
+                "" => shortcuts.unbind(action),
+                text => {
+                    if let Ok(binding) = text.parse() {
+                        shortcuts.bind(action, binding);
+                    }
+                }
+            }
+        }
+        shortcuts
+    }
+
+    // Le azioni premute in questo fotogramma; i tasti usati vengono tolti
+    // dagli eventi, così non arrivano anche alla tastiera come strumento.
+    // La ripetizione automatica di un tasto tenuto giù non conta: si toglie
+    // ma non ripete l'azione. Con un campo di testo attivo la tastiera è
+    // tutta di egui.
+    pub fn pressed(&self, ctx: &egui::Context) -> Vec<Action> {
+        if self.capturing.is_some() || ctx.wants_keyboard_input() {
+            return Vec::new();
+        }
+        let mut actions = Vec::new();
+        ctx.input_mut(|input| {
+            input.events.retain(|event| {
+                let egui::Event::Key {
+                    key,
+                    pressed: true,
+                    repeat,
+                    modifiers,
+                } = event
+                else {
+                    return true;
+                };
+                let Some(action) = self.action_for(*key, *modifiers) else {
+                    return true;
+                };
+                if !repeat && !actions.contains(&action) {
+                    actions.push(action);
+                }
+                false
+            })
+        });
+        actions
+    }
+
+    // L'azione del tasto premuto con questi modificatori
+    fn action_for(&self, key: Key, modifiers: Modifiers) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, binding)| binding.key == key && modifiers.matches(binding.modifiers))
+            .map(|(action, _)| *action)
+    }
+
+    // Durante la riassegnazione, il primo tasto premuto (Esc annulla)
+    pub fn capture(&mut self, ctx: &egui::Context) -> Option<(Action, KeyBinding)> {
+        let action = self.capturing?;
+        let pressed = ctx.input_mut(|input| {
+            let index = input.events.iter().position(|event| {
+                matches!(
+                    event,
+                    egui::Event::Key {
+                        pressed: true,
+                        repeat: false,
+                        ..
+                    }
+                )
+            })?;
+            match input.events.remove(index) {
+                egui::Event::Key { key, modifiers, .. } => Some((key, modifiers)),
+                _ => None,
+            }
+        })?;
+        self.capturing = None;
+        match pressed {
+            (Key::Escape, modifiers) if modifiers.is_none() => None,
+            (key, modifiers) => {
+                // Ctrl e Cmd contano come lo stesso modificatore
+                let modifiers = Modifiers {
+                    command: modifiers.command || modifiers.ctrl || modifiers.mac_cmd,
+                    ctrl: false,
+                    mac_cmd: false,
+                    ..modifiers
+                };
+                Some((action, KeyBinding { modifiers, key }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_event(key: Key, modifiers: Modifiers) -> egui::Event {
+        // egui decide da solo se è una ripetizione: il tasto era già giù
+        egui::Event::Key {
+            key,
+            pressed: true,
+            repeat: false,
+            modifiers,
+        }
+    }
+
+    // Le azioni di un fotogramma con questi eventi, e gli eventi rimasti
+    fn run_frame(
+        ctx: &egui::Context,
+        shortcuts: &Shortcuts,
+        events: Vec<egui::Event>,
+    ) -> (Vec<Action>, usize) {
+        let mut actions = Vec::new();
+        let mut left = 0;
+        let input = egui::RawInput {
+            events,
+            ..Default::default()
+        };
+        let _ = ctx.run(input, |ctx| {
+            actions = shortcuts.pressed(ctx);
+            left = ctx.input(|input| input.events.len());
+        });
+        (actions, left)
+    }
+
+    #[test]
+    fn held_key_triggers_its_action_once() {
+        let ctx = egui::Context::default();
+        let shortcuts = Shortcuts::new();
+        let space = || key_event(Key::Space, Modifiers::NONE);
+        // Il primo fotogramma cambia il fuoco e azzera i tasti premuti
+        run_frame(&ctx, &shortcuts, Vec::new());
+        let (actions, left) = run_frame(&ctx, &shortcuts, vec![space(), space()]);
+        assert_eq!(actions, vec![Action::PlayPause]);
+        // Anche le ripetizioni restano alle scorciatoie
+        assert_eq!(left, 0);
+
+        // Tasto ancora giù nel fotogramma dopo: ripetizione automatica
+        let (actions, left) = run_frame(&ctx, &shortcuts, vec![space()]);
+        assert!(actions.is_empty());
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn modifiers_must_match() {
+        let ctx = egui::Context::default();
+        let shortcuts = Shortcuts::new();
+        let (actions, left) = run_frame(
+            &ctx,
+            &shortcuts,
+            vec![
+                key_event(Key::O, Modifiers::NONE),
+                key_event(Key::ArrowLeft, Modifiers::SHIFT),
+            ],
+        );
+        assert!(actions.is_empty());
+        assert_eq!(left, 2);
+        let (actions, _) = run_frame(
+            &ctx,
+            &shortcuts,
+            vec![key_event(Key::O, Modifiers::COMMAND)],
+        );
+        assert_eq!(actions, vec![Action::OpenFile]);
+    }
+
+    #[test]
+    fn bindings_round_trip_through_the_settings_map() {
+        let mut shortcuts = Shortcuts::new();
+        let binding: KeyBinding = "Ctrl+Shift+F5".parse().unwrap();
+        assert_eq!(shortcuts.bind(Action::Screenshot, binding), None);
+        shortcuts.unbind(Action::ToggleUi);
+        let restored = Shortcuts::from_map(&shortcuts.to_map());
+        assert_eq!(restored.binding(Action::Screenshot), Some(binding));
+        assert_eq!(restored.binding(Action::ToggleUi), None);
+        assert_eq!(binding.to_string(), "Ctrl+Shift+F5");
+    }
+}
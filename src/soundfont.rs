@@ -0,0 +1,66 @@
+// src/soundfont.rs
+use crate::synth::{Instrument, SynthEvent};
+use rustysynth::{SoundFont, Synthesizer, SynthesizerSettings};
+use std::path::Path;
+use std::sync::Arc;
+
+// Carica una SoundFont (.sf2) dal disco. Il risultato si condivide fra il
+// synth in tempo reale e quello del rendering offline.
+pub fn load_soundfont(path: &Path) -> Result<Arc<SoundFont>, String> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| format!("Impossibile aprire '{}': {}", path.display(), e))?;
+    let soundfont = SoundFont::new(&mut file)
+        .map_err(|e| format!("SoundFont '{}' non valida: {:?}", path.display(), e))?;
+    Ok(Arc::new(soundfont))
+}
+
+// Suona le note con i campioni di una SoundFont, rispettando bank e
+// program di ogni canale (il canale 10 usa il banco delle percussioni).
+pub struct SoundFontSynth {
+    synthesizer: Synthesizer,
+}
+
+impl SoundFontSynth {
+    pub fn new(soundfont: &Arc<SoundFont>, sample_rate: u32) -> Result<Self, String> {
+        let settings = SynthesizerSettings::new(sample_rate as i32);
+        let synthesizer = Synthesizer::new(soundfont, &settings).map_err(|e| format!("{:?}", e))?;
+        Ok(Self { synthesizer })
+    }
+}
+
+impl Instrument for SoundFontSynth {
+    fn handle_event(&mut self, event: SynthEvent) {
+        let synth = &mut self.synthesizer;
+        match event {
+            SynthEvent::NoteOn {
+                channel,
+                pitch,
+                velocity,
+            } => synth.note_on(channel as i32, pitch as i32, velocity as i32),
+            SynthEvent::NoteOff { channel, pitch } => synth.note_off(channel as i32, pitch as i32),
+            SynthEvent::Sustain { channel, down } => {
+                synth.process_midi_message(channel as i32, 0xB0, 0x40, if down { 127 } else { 0 })
+            }
+            SynthEvent::ProgramChange {
+                channel,
+                bank,
+                program,
+            } => {
+                synth.process_midi_message(channel as i32, 0xB0, 0x00, bank as i32);
+                synth.process_midi_message(channel as i32, 0xC0, program as i32, 0);
+            }
+            SynthEvent::AllNotesOff => synth.note_off_all(false),
+            SynthEvent::SetVolume(volume) => synth.set_master_volume(volume),
+            // Woodblock alto/basso del kit General MIDI sul canale 10
+            SynthEvent::Click { accent } => synth.note_on(
+                9,
+                if accent { 76 } else { 77 },
+                if accent { 127 } else { 100 },
+            ),
+        }
+    }
+
+    fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.synthesizer.render(left, right);
+    }
+}
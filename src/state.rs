@@ -1,21 +1,45 @@
 // state.rs
+use crate::audio_output::{self, AudioOutput};
+use crate::cli::PlayOptions;
+use crate::computer_keyboard::ComputerKeyboard;
 use crate::config::*;
-use crate::midi_loader::{self, MidiNote};
+use crate::file_browser::{self, FileBrowser};
+use crate::http_api::{self, ActiveNote, ApiSettings, HttpApi, StateSnapshot};
+use crate::key_lights::KeyLights;
+use crate::layout::KeyRange;
+use crate::metronome::Metronome;
+use crate::midi_input::{MidiInput, NoteEvent};
+use crate::midi_loader::{self, Hand, HandAssignment, Song};
+use crate::midi_output::MidiOutput;
+use crate::midi_sync::MidiSync;
+use crate::osc::{self, OscServer};
+use crate::playlist::{self, Playlist};
+use crate::recording::{self, PlaybackState};
+use crate::recent::RecentFiles;
+use crate::remote::RemoteCommand;
+use crate::png_writer;
+use crate::presets::{Preset, PresetLibrary};
+use crate::renderer::{self, OffscreenTarget, SceneRenderer};
+use crate::scene::{self, Scene};
+use crate::screenshot;
+use crate::session::{self, Lesson, Session};
+use crate::shortcuts::{self, Action, KeyBinding, Shortcuts};
+use crate::scoring::{Grade, Scorer};
+use crate::sequencer::{PlaybackFilter, Sequencer};
+use crate::settings::{self, Settings};
+use crate::soundfont::{self, SoundFontSynth};
+use crate::synth::{AdditiveSynth, Instrument, SynthEvent};
+use crate::transport::Transport;
 use crate::vertex::Vertex;
-use wgpu::util::DeviceExt;
+use crate::wait_mode::WaitMode;
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
-use bytemuck::{Pod, Zeroable};
 use egui::Color32; // <--- AGGIUNTO
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
-struct StateUniforms {
-    screen_size: [f32; 2],
-}
-
 pub struct State {
     pub surface: wgpu::Surface,
     pub device: wgpu::Device,
@@ -23,59 +47,176 @@ pub struct State {
     pub config: wgpu::SurfaceConfiguration,
     pub size: PhysicalSize<u32>,
 
-    pub midi_notes: Vec<MidiNote>,
-    pub start_time: Instant,
-
-    pub render_pipeline: wgpu::RenderPipeline,
-    pub vertex_buffer: wgpu::Buffer,
-    pub num_vertices: u32,
+    pub song: Song,
+    pub song_path: Option<PathBuf>,
+    // Il file MIDI così come è stato letto, da incorporare nelle sessioni
+    song_bytes: Option<Vec<u8>>,
+    // Errore dell'ultimo brano aperto dal menu o trascinato nella finestra
+    pub song_error: Option<String>,
+    pub file_browser: FileBrowser,
+    pub transport: Transport,
 
-    pub uniform_buffer: wgpu::Buffer,
-    pub uniform_bind_group: wgpu::BindGroup,
-    pub uniform_bind_group_layout: wgpu::BindGroupLayout,
+    pub scene_renderer: SceneRenderer,
 
     pub egui_ctx: egui::Context,
     pub egui_state: egui_winit::State,
     pub egui_renderer: egui_wgpu::Renderer,
     pub fall_duration_secs: f32,
+    // Tasti mostrati (None = colonne a larghezza fissa attorno al Do3)
+    pub key_range: Option<KeyRange>,
+    // Testo della casella dei tasti, applicato con set_key_range_text
+    pub key_range_text: String,
+    pub key_range_error: Option<String>,
+
+    // --- LEZIONE E SESSIONI ---
+    // Segnaposti, note per lo studente e loop salvati del brano aperto
+    pub lesson: Lesson,
+    pub show_lesson: bool,
+    // Testi dei campi della finestra "Lezione"
+    pub lesson_marker_name: String,
+    pub lesson_loop_name: String,
+    pub lesson_note_text: String,
+    pub lesson_note_secs: f32,
+    // File .pvs da salvare e se metterci dentro il MIDI
+    pub session_path: String,
+    pub session_embed_midi: bool,
+    pub session_status: Option<String>,
+
+    // --- SCALETTA E FILE RECENTI ---
+    pub playlist: Playlist,
+    pub show_playlist: bool,
+    // File o cartella da aggiungere, scaletta .m3u da caricare o salvare
+    pub playlist_file: String,
+    pub playlist_status: Option<String>,
+    pub recent: RecentFiles,
+    pub recent_error: Option<String>,
+
+    // --- PRESET DI ASPETTO ---
+    pub presets: PresetLibrary,
+    // Ultimo preset scelto (None = aspetto personalizzato)
+    pub preset_name: Option<String>,
+    // Nome per "Salva preset" e file per importare/esportare
+    pub preset_new_name: String,
+    pub preset_file: String,
+    pub preset_status: Option<String>,
 
     // --- CAMPI AGGIUNTI PER I COLORI ---
     pub color_left_hand: Color32,
     pub color_right_hand: Color32,
+
+    // --- INGRESSO MIDI E MODALITÀ ATTESA ---
+    pub midi_input: MidiInput,
+    pub midi_input_ports: Vec<String>,
+    pub midi_input_error: Option<String>,
+    pub wait_mode: WaitMode,
+    // Tastiera del computer e mouse come ingresso alternativo
+    pub computer_keyboard: ComputerKeyboard,
+
+    // --- VALUTAZIONE ---
+    pub scorer: Scorer,
+    pub last_grade: Option<Grade>,
+
+    // --- AUDIO ---
+    pub audio: AudioOutput,
+    pub sequencer: Sequencer,
+    pub synth_enabled: bool,
+    pub synth_volume: f32,
+    pub export_status: Option<String>,
+    // Percorso del file .sf2 (vuoto = synth additivo integrato)
+    pub soundfont_path: String,
+    pub soundfont: Option<Arc<rustysynth::SoundFont>>,
+    pub soundfont_error: Option<String>,
+
+    // --- REGISTRAZIONE AUDIO AGGANCIATA AL BRANO ---
+    pub recording_path: String,
+    // Punto della registrazione (secondi) che corrisponde all'inizio del brano
+    pub recording_offset_secs: f32,
+    pub recording_error: Option<String>,
+    // Ultimo stato del trasporto inviato al thread audio
+    sent_playback: Option<PlaybackState>,
+
+    // --- USCITA MIDI ---
+    pub midi_output: MidiOutput,
+    pub midi_output_ports: Vec<String>,
+    pub midi_output_error: Option<String>,
+    // Mani/tracce escluse dalla riproduzione (synth e uscita MIDI)
+    pub playback_filter: PlaybackFilter,
+    // Guida luminosa sui tasti, inviata sulla stessa uscita MIDI
+    pub key_lights: KeyLights,
+
+    // --- METRONOMO ---
+    pub metronome: Metronome,
+
+    // --- SINCRONIZZAZIONE ESTERNA (CLOCK MIDI / MTC) ---
+    pub midi_sync: MidiSync,
+    pub midi_sync_error: Option<String>,
+
+    // --- CONTROLLO REMOTO OSC ---
+    pub osc: Option<OscServer>,
+    pub osc_port: u16,
+    // Indirizzo "host:porta" a cui inviare sempre gli aggiornamenti (vuoto = solo iscritti)
+    pub osc_target: String,
+    pub osc_error: Option<String>,
+
+    // --- API HTTP / WEBSOCKET ---
+    pub http: Option<HttpApi>,
+    pub http_port: u16,
+    pub http_error: Option<String>,
+
+    // --- SCREENSHOT ---
+    // Multiplo della risoluzione della finestra
+    pub screenshot_scale: u32,
+    pub screenshot_with_ui: bool,
+    pub screenshot_status: Option<String>,
+    // Lo scatto avviene in render(), quando l'interfaccia è già pronta
+    screenshot_requested: bool,
+    // Istante mostrato nell'ultimo fotogramma
+    frame_time_secs: f32,
+
+    // --- SCORCIATOIE DA TASTIERA ---
+    pub shortcuts: Shortcuts,
+    pub shortcuts_status: Option<String>,
+    // Menu e finestre visibili (la scena resta sempre)
+    pub show_ui: bool,
+    // Il cambio di schermo intero lo fa main, che ha la finestra
+    fullscreen_requested: bool,
+
+    // --- IMPOSTAZIONI SALVATE ---
+    // Ultime impostazioni scritte su disco, per accorgersi dei cambiamenti
+    saved_settings: Settings,
+    // Primo cambiamento non ancora salvato: si scrive dopo una breve pausa,
+    // non a ogni fotogramma mentre si trascina uno slider
+    settings_changed_at: Option<Instant>,
+    // Ultimo confronto con quelle salvate
+    settings_checked_at: Instant,
+    pub settings_error: Option<String>,
 }
 
 impl State {
-    pub async fn new(window: &winit::window::Window) -> Self {
+    // Si parte dalle impostazioni salvate, con le opzioni della riga di
+    // comando che decidono l'aspetto iniziale; `force_software` salta la GPU
+    // e usa l'adattatore software del sistema
+    pub async fn new(
+        window: &winit::window::Window,
+        song: Song,
+        options: &PlayOptions,
+        settings: Settings,
+        force_software: bool,
+    ) -> Result<Self, String> {
         let size = window.inner_size();
 
         // --- WGPU inizializzazione ---
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(),
-        });
-        let surface = unsafe { instance.create_surface(window) }.unwrap();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    label: None,
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let instance = renderer::create_instance();
+        let surface = unsafe { instance.create_surface(window) }
+            .map_err(|e| format!("Impossibile creare la superficie della finestra: {}", e))?;
+        let adapter = renderer::request_adapter(&instance, Some(&surface), force_software).await?;
+        let (device, queue) = renderer::request_device(&adapter).await?;
 
         let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps.formats[0];
+        let surface_format = *surface_caps
+            .formats
+            .first()
+            .ok_or("L'adattatore grafico non supporta la finestra")?;
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -88,125 +229,14 @@ impl State {
         };
         surface.configure(&device, &config);
 
-        // --- Caricamento Dati ---
-        let midi_path = std::path::Path::new("test.mid");
-        let midi_notes = if midi_path.exists() {
-            midi_loader::load_midi_file(midi_path)
-        } else {
-            println!(
-                "[ATTENZIONE] File MIDI di test '{}' non trovato, uso dati di fallback.",
-                midi_path.display()
-            );
-            vec![
-                MidiNote {
-                    pitch: 60,
-                    velocity: 100,
-                    start_time_secs: 2.0,
-                    duration_secs: 1.0,
-                },
-                MidiNote {
-                    pitch: 62,
-                    velocity: 100,
-                    start_time_secs: 3.0,
-                    duration_secs: 0.5,
-                },
-                MidiNote {
-                    pitch: 64,
-                    velocity: 100,
-                    start_time_secs: 4.0,
-                    duration_secs: 1.5,
-                },
-                // Aggiungiamo una nota per la mano sinistra per test
-                MidiNote {
-                    pitch: 48, // Sotto il Do centrale
-                    velocity: 100,
-                    start_time_secs: 2.5,
-                    duration_secs: 1.0,
-                },
-            ]
-        };
-        println!("Caricate {} note.", midi_notes.len());
-        let start_time = Instant::now();
-
-        // --- Creazione Uniforms ---
-        let uniforms = StateUniforms {
-            screen_size: [size.width as f32, size.height as f32],
-        };
-        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform Buffer"),
-            contents: bytemuck::bytes_of(&uniforms),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-        let uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("Uniform Bind Group Layout"),
-            });
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-            label: Some("Uniform Bind Group"),
-        });
-
-        // --- Shader ---
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
+        println!("Caricate {} note.", song.notes.len());
+        let mut transport = Transport::new();
+        transport.speed = options.speed;
+        let scorer = Scorer::new(song.notes.len());
+        let sequencer = Sequencer::new(&song);
+        let key_lights = KeyLights::new(&song);
 
-        // --- Pipeline ---
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Pipeline Layout"),
-                bind_group_layouts: &[&uniform_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                ..Default::default()
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
-
-        // --- Vertex Buffer ---
-        let initial_size = 6 * 10 * std::mem::size_of::<Vertex>() as u64;
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Vertex Buffer"),
-            size: initial_size,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let scene_renderer = SceneRenderer::new(&device, config.format, size.width, size.height);
 
         // --- INIZIALIZZAZIONE EGUI ---
         let egui_ctx = egui::Context::default();
@@ -219,28 +249,724 @@ impl State {
         );
 
         // 7. Aggiungiamo i nuovi campi a Self
-        Self {
+        let mut state = Self {
             surface,
             device,
             queue,
             config,
             size,
-            midi_notes,
-            start_time,
-            render_pipeline,
-            vertex_buffer,
-            num_vertices: 0,
-            uniform_buffer,
-            uniform_bind_group,
-            uniform_bind_group_layout,
+            song,
+            song_path: options.midi_path.clone(),
+            song_bytes: None,
+            song_error: None,
+            file_browser: FileBrowser::new(),
+            transport,
+            scene_renderer,
             egui_ctx,
             egui_state,
             egui_renderer,
-            fall_duration_secs: FALL_DURATION_SECS,
+            fall_duration_secs: options.view.fall_duration_secs,
+            key_range: options.view.key_range,
+            key_range_text: String::new(),
+            key_range_error: None,
+
+            lesson: Lesson::default(),
+            show_lesson: false,
+            lesson_marker_name: String::new(),
+            lesson_loop_name: String::new(),
+            lesson_note_text: String::new(),
+            lesson_note_secs: 4.0,
+            session_path: options
+                .midi_path
+                .as_ref()
+                .map(|p| p.with_extension(session::SESSION_EXTENSION).display().to_string())
+                .unwrap_or_default(),
+            session_embed_midi: true,
+            session_status: None,
+
+            playlist: Playlist::new(),
+            show_playlist: false,
+            playlist_file: String::new(),
+            playlist_status: None,
+            recent: RecentFiles::load(),
+            recent_error: None,
+
+            presets: PresetLibrary::load(),
+            preset_name: None,
+            preset_new_name: String::new(),
+            preset_file: String::new(),
+            preset_status: None,
 
             // --- INIZIALIZZAZIONE COLORI ---
-            color_left_hand: Color32::from_rgb(0, 100, 255), // Un bel blu
-            color_right_hand: Color32::from_rgb(0, 255, 100), // Un bel verde
+            color_left_hand: options.view.color_left,
+            color_right_hand: options.view.color_right,
+
+            midi_input: MidiInput::new(),
+            midi_input_ports: MidiInput::port_names(),
+            midi_input_error: None,
+            wait_mode: WaitMode::new(),
+            computer_keyboard: ComputerKeyboard::new(),
+            scorer,
+            last_grade: None,
+
+            audio: AudioOutput::new(),
+            sequencer,
+            synth_enabled: true,
+            synth_volume: 0.8,
+            export_status: None,
+            soundfont_path: String::new(),
+            soundfont: None,
+            soundfont_error: None,
+
+            recording_path: String::new(),
+            recording_offset_secs: 0.0,
+            recording_error: None,
+            sent_playback: None,
+
+            midi_output: MidiOutput::new(),
+            midi_output_ports: MidiOutput::port_names(),
+            midi_output_error: None,
+            playback_filter: PlaybackFilter::default(),
+            key_lights,
+
+            metronome: Metronome::new(),
+
+            midi_sync: MidiSync::new(),
+            midi_sync_error: None,
+
+            osc: None,
+            osc_port: osc::DEFAULT_OSC_PORT,
+            osc_target: String::new(),
+            osc_error: None,
+
+            http: None,
+            http_port: http_api::DEFAULT_HTTP_PORT,
+            http_error: None,
+
+            screenshot_scale: 1,
+            screenshot_with_ui: true,
+            screenshot_status: None,
+            screenshot_requested: false,
+            frame_time_secs: 0.0,
+
+            shortcuts: Shortcuts::new(),
+            shortcuts_status: None,
+            show_ui: true,
+            fullscreen_requested: false,
+
+            saved_settings: Settings::default(),
+            settings_changed_at: None,
+            settings_checked_at: Instant::now(),
+            settings_error: None,
+        };
+        // Le opzioni valgono per questa esecuzione: non si salvano finché
+        // l'utente non cambia qualcos'altro
+        let mut startup = settings;
+        startup.view = settings::ViewSettings {
+            fall_duration_secs: options.view.fall_duration_secs,
+            color_left_hand: options.view.color_left,
+            color_right_hand: options.view.color_right,
+            key_range: options.view.key_range,
+        };
+        startup.playback.speed = options.speed;
+        state.apply_settings(&startup);
+        state.saved_settings = state.settings();
+        if let Some(path) = &options.midi_path {
+            state.add_recent(path);
+        }
+        if options.start_secs > 0.0 {
+            state.seek(options.start_secs);
+        }
+        Ok(state)
+    }
+
+    pub fn connect_midi_output(&mut self, port_name: &str) {
+        self.midi_output_error = self.midi_output.connect(port_name).err();
+        // Lo strumento esterno deve ricevere gli strumenti dei canali
+        self.sequencer.seek(self.transport.position_secs());
+    }
+
+    // Zittisce synth e uscita MIDI (pausa, salti, cambi di filtro).
+    // Le luci dei tasti si spengono con il resto e vengono riaccese al
+    // prossimo frame per le note ancora dentro la finestra di anticipo.
+    pub fn all_notes_off(&mut self) {
+        self.audio.send(SynthEvent::AllNotesOff);
+        self.midi_output.all_notes_off();
+        self.key_lights.seek(self.transport.position_secs(), &self.song);
+    }
+
+    pub fn set_key_lights_enabled(&mut self, enabled: bool) {
+        self.key_lights.enabled = enabled;
+        if enabled {
+            self.key_lights.seek(self.transport.position_secs(), &self.song);
+        } else {
+            self.key_lights.all_off(&mut self.midi_output);
+        }
+    }
+
+    // Dopo un cambio di anticipo, canali o colori delle luci
+    pub fn rebuild_key_lights(&mut self) {
+        self.key_lights.all_off(&mut self.midi_output);
+        self.key_lights.rebuild(&self.song, self.transport.position_secs());
+    }
+
+    // Sostituisce il brano in riproduzione. Dispositivo GPU, audio e porte
+    // MIDI restano quelli aperti: si ricostruisce solo ciò che dipende dalle note.
+    pub fn load_song(&mut self, path: &Path) -> Result<(), String> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("Impossibile leggere '{}': {}", path.display(), e))?;
+        let song =
+            midi_loader::parse_midi(&bytes).map_err(|e| format!("'{}': {}", path.display(), e))?;
+        println!(
+            "[INFO] Caricato '{}' ({} note)",
+            path.display(),
+            song.notes.len()
+        );
+        self.set_song(song, Some(path.to_path_buf()), Some(bytes));
+        self.session_path = path
+            .with_extension(session::SESSION_EXTENSION)
+            .display()
+            .to_string();
+        Ok(())
+    }
+
+    fn set_song(&mut self, song: Song, path: Option<PathBuf>, bytes: Option<Vec<u8>>) {
+        self.key_lights.all_off(&mut self.midi_output);
+        self.song = song;
+        self.song_path = path;
+        self.song_bytes = bytes;
+        self.sequencer = Sequencer::new(&self.song);
+        self.playback_filter.muted_tracks.clear();
+        self.transport.loop_region = None;
+        self.lesson = Lesson::default();
+        self.key_lights.rebuild(&self.song, 0.0);
+        self.seek(0.0);
+    }
+
+    // Apre una sessione: il brano e poi aspetto, mani, loop e lezione
+    pub fn load_session(&mut self, path: &Path) -> Result<(), String> {
+        let session = Session::load(path)?;
+        let session_song = session.song(path)?;
+        println!(
+            "[INFO] Sessione '{}' caricata ({} note)",
+            path.display(),
+            session_song.song.notes.len()
+        );
+        self.set_song(session_song.song, session_song.path, session_song.bytes);
+        self.set_hand_assignment(session.hands);
+        match (&session.view, &session.preset) {
+            (Some(view), _) => {
+                self.apply_view(view);
+                self.preset_name = session.preset.clone();
+            }
+            (None, Some(preset)) => self.select_preset(preset),
+            (None, None) => {}
+        }
+        self.transport.loop_region = session.loop_region;
+        self.lesson = session.lesson;
+        self.session_path = path.display().to_string();
+        self.session_status = None;
+        Ok(())
+    }
+
+    // Scrive la sessione nel file indicato in `session_path`
+    pub fn save_session(&mut self) {
+        let mut path = PathBuf::from(self.session_path.trim());
+        if path.extension().is_none() {
+            path.set_extension(session::SESSION_EXTENSION);
+        }
+        let mut session = Session::new();
+        session.midi_path = self.song_path.clone();
+        // Il brano aperto dalla riga di comando non ha i byte in memoria
+        if self.session_embed_midi {
+            session.midi_data = self.song_bytes.clone().or_else(|| {
+                self.song_path
+                    .as_ref()
+                    .and_then(|path| std::fs::read(path).ok())
+            });
+        }
+        session.preset = self.preset_name.clone();
+        session.view = Some(self.view_settings());
+        session.loop_region = self.transport.loop_region;
+        session.hands = self.song.hands.clone();
+        session.lesson = self.lesson.clone();
+        self.session_status = Some(match session.save(&path) {
+            Ok(()) => format!("Salvato {}", path.display()),
+            Err(e) => format!("Errore: {}", e),
+        });
+    }
+
+    // Cambia la divisione fra le mani e aggiorna ciò che ne dipende
+    pub fn set_hand_assignment(&mut self, hands: HandAssignment) {
+        self.song.assign_hands(hands);
+        self.wait_mode
+            .resync(self.transport.position_secs(), &self.song.notes);
+        self.rebuild_key_lights();
+        self.all_notes_off();
+    }
+
+    // Apre un file scelto dal menu o trascinato nella finestra: le SoundFont
+    // e le registrazioni si agganciano al brano, il resto lo sostituisce
+    pub fn open_file(&mut self, path: &Path) {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "sf2" => {
+                self.soundfont_path = path.display().to_string();
+                self.load_soundfont();
+            }
+            "wav" | "mp3" | "flac" | "ogg" => {
+                self.recording_path = path.display().to_string();
+                self.load_recording();
+            }
+            _ if playlist::is_playlist_file(path) => {
+                self.playlist_file = path.display().to_string();
+                self.load_playlist();
+                if self.playlist_status.is_none() {
+                    self.start_playlist();
+                }
+            }
+            // Un brano aperto a parte interrompe la scaletta
+            _ => {
+                self.song_error = self.open_song(path).err();
+                if self.song_error.is_none() {
+                    self.playlist.current = None;
+                }
+            }
+        }
+    }
+
+    // Brano o sessione, secondo l'estensione; finisce fra i file recenti
+    fn open_song(&mut self, path: &Path) -> Result<(), String> {
+        if session::is_session_file(path) {
+            self.load_session(path)?;
+        } else {
+            self.load_song(path)?;
+        }
+        self.add_recent(path);
+        Ok(())
+    }
+
+    fn add_recent(&mut self, path: &Path) {
+        self.recent_error = self.recent.add(path).err();
+        if let Some(e) = &self.recent_error {
+            eprintln!("[ATTENZIONE] {}", e);
+        }
+    }
+
+    pub fn clear_recent(&mut self) {
+        self.recent_error = self.recent.clear().err();
+    }
+
+    // --- SCALETTA ---
+
+    // Sostituisce la scaletta con quella del file in `playlist_file`
+    pub fn load_playlist(&mut self) {
+        let path = PathBuf::from(self.playlist_file.trim());
+        match playlist::read_m3u(&path) {
+            Ok(entries) => {
+                println!(
+                    "[INFO] Scaletta '{}' caricata ({} brani)",
+                    path.display(),
+                    entries.len()
+                );
+                self.playlist.set_entries(entries);
+                self.playlist_status = None;
+                self.add_recent(&path);
+            }
+            Err(e) => self.playlist_status = Some(format!("Errore: {}", e)),
+        }
+    }
+
+    pub fn save_playlist(&mut self) {
+        let mut path = PathBuf::from(self.playlist_file.trim());
+        if path.extension().is_none() {
+            path.set_extension(playlist::PLAYLIST_EXTENSIONS[0]);
+        }
+        self.playlist_status = Some(match self.playlist.save(&path) {
+            Ok(()) => format!("Salvato {}", path.display()),
+            Err(e) => format!("Errore: {}", e),
+        });
+    }
+
+    // Aggiunge il file in `playlist_file`, o tutti i brani se è una cartella
+    pub fn add_to_playlist(&mut self) {
+        let path = PathBuf::from(self.playlist_file.trim());
+        if path.is_dir() {
+            let mut songs: Vec<PathBuf> = std::fs::read_dir(&path)
+                .map(|read| {
+                    read.flatten()
+                        .map(|entry| entry.path())
+                        .filter(|p| file_browser::is_midi_file(p))
+                        .collect()
+                })
+                .unwrap_or_default();
+            songs.sort();
+            self.playlist_status = Some(format!("Aggiunti {} brani", songs.len()));
+            for song in songs {
+                self.playlist.add(song);
+            }
+        } else if path.is_file() {
+            self.playlist.add(path);
+            self.playlist_status = None;
+        } else {
+            self.playlist_status = Some(format!("Errore: '{}' non trovato", path.display()));
+        }
+    }
+
+    // Suona la scaletta dall'inizio (o da un brano a caso)
+    pub fn start_playlist(&mut self) {
+        self.playlist.rewind();
+        self.advance_playlist(false);
+    }
+
+    // Passa al brano successivo; quelli che non si aprono vengono saltati
+    pub fn advance_playlist(&mut self, automatic: bool) {
+        for _ in 0..self.playlist.entries.len() {
+            match self.playlist.next(automatic) {
+                // Ripetizione del brano: basta tornare all'inizio
+                Some(index) if Some(index) == self.playlist.current => {
+                    self.seek(0.0);
+                    return;
+                }
+                Some(index) => {
+                    if self.play_playlist_entry(index) {
+                        return;
+                    }
+                }
+                None => break,
+            }
+        }
+        // Scaletta finita: ci si ferma sull'ultimo brano
+        self.playlist.current = None;
+        if self.transport.is_playing() {
+            self.toggle_play();
+        }
+    }
+
+    pub fn previous_playlist_entry(&mut self) {
+        if let Some(index) = self.playlist.previous() {
+            self.play_playlist_entry(index);
+        }
+    }
+
+    // Apre un brano della scaletta e lo fa partire; false se non si apre
+    pub fn play_playlist_entry(&mut self, index: usize) -> bool {
+        self.playlist.select(index);
+        let path = self.playlist.entries[index].path.clone();
+        match self.open_song(&path) {
+            Ok(()) => {
+                self.song_error = None;
+                if !self.transport.is_playing() {
+                    self.transport.toggle_play();
+                }
+                true
+            }
+            Err(e) => {
+                eprintln!("[ATTENZIONE] Scaletta: {}", e);
+                self.song_error = Some(e);
+                false
+            }
+        }
+    }
+
+    // Esegue un comando arrivato da OSC o HTTP
+    pub fn apply_remote(&mut self, command: RemoteCommand) -> Result<(), String> {
+        // NaN e infiniti passerebbero indenni da clamp
+        let finite = |value: f32| {
+            if value.is_finite() {
+                Ok(value)
+            } else {
+                Err(format!("Valore non valido: {}", value))
+            }
+        };
+        match command {
+            RemoteCommand::Play => {
+                if !self.transport.is_playing() {
+                    self.toggle_play();
+                }
+            }
+            RemoteCommand::Pause => {
+                if self.transport.is_playing() {
+                    self.toggle_play();
+                }
+            }
+            RemoteCommand::TogglePlay => self.toggle_play(),
+            RemoteCommand::Seek(position_secs) => {
+                let position_secs = finite(position_secs)?.clamp(0.0, self.song_end_secs());
+                self.seek(position_secs);
+            }
+            RemoteCommand::Load(path) => self.load_song(&path)?,
+            // Stessi limiti dello slider della UI
+            RemoteCommand::Speed(speed) => self.transport.speed = finite(speed)?.clamp(0.25, 2.0),
+            RemoteCommand::FallDuration(secs) => {
+                self.fall_duration_secs = finite(secs)?.clamp(0.5, 10.0)
+            }
+            RemoteCommand::Color { hand, rgb: [r, g, b] } => {
+                let color = Color32::from_rgb(r, g, b);
+                match hand {
+                    Hand::Left => self.color_left_hand = color,
+                    Hand::Right => self.color_right_hand = color,
+                }
+            }
+            RemoteCommand::SynthVolume(volume) => {
+                self.set_synth_volume(finite(volume)?.clamp(0.0, 1.0))
+            }
+            RemoteCommand::Metronome(enabled) => {
+                self.metronome.enabled = enabled;
+                self.metronome.seek(self.transport.position_secs());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn start_http(&mut self) {
+        self.http = None;
+        match HttpApi::start(self.http_port) {
+            Ok(api) => {
+                self.http = Some(api);
+                self.http_error = None;
+            }
+            Err(e) => self.http_error = Some(e),
+        }
+    }
+
+    // Stato corrente per l'API HTTP
+    fn snapshot(&self, time_secs: f32) -> StateSnapshot {
+        let rgb = |c: Color32| [c.r(), c.g(), c.b()];
+        StateSnapshot {
+            time_secs,
+            playing: self.transport.is_playing(),
+            song_path: self.song_path.clone(),
+            song_end_secs: self.song_end_secs(),
+            notes: self
+                .song
+                .sounding_at(time_secs)
+                .map(|n| ActiveNote {
+                    pitch: n.pitch,
+                    velocity: n.velocity,
+                    hand: n.hand,
+                })
+                .collect(),
+            settings: ApiSettings {
+                speed: self.transport.speed,
+                fall_duration_secs: self.fall_duration_secs,
+                color_left: rgb(self.color_left_hand),
+                color_right: rgb(self.color_right_hand),
+                synth_volume: self.synth_volume,
+                metronome: self.metronome.enabled,
+            },
+        }
+    }
+
+    pub fn start_osc(&mut self) {
+        self.osc = None;
+        self.osc_error = None;
+        let target = if self.osc_target.trim().is_empty() {
+            None
+        } else {
+            match self.osc_target.trim().parse() {
+                Ok(address) => Some(address),
+                Err(_) => {
+                    self.osc_error = Some(format!(
+                        "Destinazione OSC '{}' non valida (es. 127.0.0.1:9001)",
+                        self.osc_target
+                    ));
+                    return;
+                }
+            }
+        };
+        match OscServer::start(self.osc_port, target) {
+            Ok(server) => self.osc = Some(server),
+            Err(e) => self.osc_error = Some(e),
+        }
+    }
+
+    // Decodifica la registrazione indicata in `recording_path` e la aggancia
+    // al trasporto (percorso vuoto = nessuna registrazione)
+    pub fn load_recording(&mut self) {
+        self.recording_error = None;
+        self.audio.detach_recording();
+        let path = self.recording_path.trim();
+        if path.is_empty() {
+            return;
+        }
+        match recording::decode_audio_file(std::path::Path::new(path)) {
+            Ok(decoded) => {
+                println!(
+                    "[INFO] Registrazione caricata: {} ({:.1} s)",
+                    path,
+                    decoded.samples.len() as f32 / 2.0 / decoded.sample_rate as f32
+                );
+                self.audio
+                    .attach_recording(Arc::new(decoded), self.recording_offset_secs);
+                self.audio.seek_recording(self.transport.position_secs());
+                // Forza il reinvio dello stato del trasporto al prossimo frame
+                self.sent_playback = None;
+            }
+            Err(e) => self.recording_error = Some(e),
+        }
+    }
+
+    pub fn set_recording_offset(&mut self, offset_secs: f32) {
+        self.recording_offset_secs = offset_secs;
+        self.audio.set_recording_offset(offset_secs);
+    }
+
+    // Imposta l'inizio o la fine del loop A-B sulla posizione attuale
+    pub fn set_loop_start(&mut self) {
+        let position = self.transport.position_secs();
+        let end = self
+            .transport
+            .loop_region
+            .map_or(self.song_end_secs(), |(_, end)| end);
+        self.transport.loop_region = Some((position, end.max(position)));
+    }
+
+    pub fn set_loop_end(&mut self) {
+        let position = self.transport.position_secs();
+        let start = self.transport.loop_region.map_or(0.0, |(start, _)| start);
+        self.transport.loop_region = Some((start.min(position), position));
+    }
+
+    // Carica la SoundFont indicata in `soundfont_path` e la mette a suonare
+    // al posto dello strumento attuale (percorso vuoto = synth integrato)
+    pub fn load_soundfont(&mut self) {
+        self.soundfont_error = None;
+        self.soundfont = None;
+        let path = self.soundfont_path.trim();
+        if !path.is_empty() {
+            match soundfont::load_soundfont(std::path::Path::new(path)) {
+                Ok(soundfont) => {
+                    println!("[INFO] SoundFont caricata: {}", path);
+                    self.soundfont = Some(soundfont);
+                }
+                Err(e) => self.soundfont_error = Some(e),
+            }
+        }
+
+        let instrument = self.make_instrument(self.audio.sample_rate());
+        self.audio.set_instrument(instrument);
+        self.audio.send(SynthEvent::SetVolume(self.synth_volume));
+        // Il nuovo strumento non conosce i program change già passati
+        self.sequencer.seek(self.transport.position_secs());
+    }
+
+    // Lo strumento da usare: la SoundFont se caricata, altrimenti il synth integrato
+    fn make_instrument(&self, sample_rate: u32) -> Box<dyn Instrument> {
+        if let Some(soundfont) = &self.soundfont {
+            match SoundFontSynth::new(soundfont, sample_rate) {
+                Ok(synth) => return Box::new(synth),
+                Err(e) => eprintln!("[ATTENZIONE] SoundFont non utilizzabile: {}", e),
+            }
+        }
+        Box::new(AdditiveSynth::new(sample_rate))
+    }
+
+    pub fn toggle_play(&mut self) {
+        self.transport.toggle_play();
+        if !self.transport.is_playing() {
+            self.all_notes_off();
+            return;
+        }
+        // Battute di conteggio prima del punto di ripresa
+        let position_secs = self.transport.position_secs();
+        let start_secs = self
+            .metronome
+            .count_in_start(position_secs, &self.song.tempo_map);
+        if start_secs < position_secs {
+            self.seek(start_secs);
+        }
+    }
+
+    // Torna all'inizio del brano, con il conteggio se il metronomo è attivo
+    pub fn restart(&mut self) {
+        let start_secs = self.metronome.count_in_start(0.0, &self.song.tempo_map);
+        self.seek(start_secs);
+    }
+
+    pub fn set_synth_enabled(&mut self, enabled: bool) {
+        self.synth_enabled = enabled;
+        if !enabled {
+            self.audio.send(SynthEvent::AllNotesOff);
+        }
+    }
+
+    pub fn set_synth_volume(&mut self, volume: f32) {
+        self.synth_volume = volume;
+        self.audio.send(SynthEvent::SetVolume(volume));
+    }
+
+    // Renderizza il brano con il synth in un WAV accanto al file MIDI
+    pub fn export_wav(&mut self) {
+        let wav_path = self
+            .song_path
+            .as_ref()
+            .map(|p| p.with_extension("wav"))
+            .unwrap_or_else(|| PathBuf::from("export.wav"));
+        let mut instrument = self.make_instrument(audio_output::WAV_SAMPLE_RATE);
+        instrument.handle_event(SynthEvent::SetVolume(self.synth_volume));
+        self.export_status = Some(
+            match audio_output::render_song_to_wav(&self.song, &wav_path, instrument.as_mut()) {
+                Ok(()) => format!("Salvato {}", wav_path.display()),
+                Err(e) => format!("Errore: {}", e),
+            },
+        );
+    }
+
+    // Fine dell'ultima nota del brano
+    pub fn song_end_secs(&self) -> f32 {
+        self.song.end_secs()
+    }
+
+    // Sposta il trasporto e riallinea la modalità attesa alla nuova posizione
+    pub fn seek(&mut self, position_secs: f32) {
+        self.transport.seek(position_secs);
+        self.on_jump(position_secs);
+        self.audio.seek_recording(position_secs);
+    }
+
+    // Tutto ciò che segue la posizione va riallineato dopo un salto
+    // (seek esplicito o ritorno all'inizio del loop)
+    fn on_jump(&mut self, position_secs: f32) {
+        self.wait_mode.resync(position_secs, &self.song.notes);
+        self.scorer.reset(position_secs, &self.song.notes);
+        self.last_grade = None;
+        self.sequencer.seek(position_secs);
+        self.metronome.seek(position_secs);
+        self.all_notes_off();
+    }
+
+    pub fn connect_midi_sync(&mut self, port_name: &str) {
+        self.midi_sync_error = self.midi_sync.connect(port_name).err();
+    }
+
+    pub fn connect_midi_input(&mut self, port_name: &str) {
+        self.midi_input_error = self.midi_input.connect(port_name).err();
+    }
+
+    // Nota da tastiera del computer o mouse: entra come un evento MIDI
+    // (attesa, valutazione) e, non essendoci uno strumento, suona nel synth
+    pub fn play_virtual_note(&mut self, event: NoteEvent) {
+        self.midi_input.inject(event);
+        self.audio.send(match event {
+            NoteEvent::On { pitch, velocity } => SynthEvent::NoteOn {
+                channel: 0,
+                pitch,
+                velocity,
+            },
+            NoteEvent::Off { pitch } => SynthEvent::NoteOff { channel: 0, pitch },
+        });
+    }
+
+    pub fn set_computer_keyboard_enabled(&mut self, enabled: bool) {
+        self.computer_keyboard.enabled = enabled;
+        if !enabled {
+            for event in self.computer_keyboard.release_all() {
+                self.play_virtual_note(event);
+            }
         }
     }
 
@@ -252,124 +978,194 @@ impl State {
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
 
-            let uniforms = StateUniforms {
-                screen_size: [new_size.width as f32, new_size.height as f32],
-            };
-            self.queue.write_buffer(
-                &self.uniform_buffer,
-                0,
-                bytemuck::bytes_of(&uniforms),
-            );
+            self.scene_renderer
+                .resize(&self.queue, new_size.width, new_size.height);
         }
     }
 
     // --- FUNZIONE UPDATE (MODIFICATA) ---
     pub fn update(&mut self) {
-        let current_time_secs = self.start_time.elapsed().as_secs_f32();
-        let screen_height = self.size.height as f32;
-        let screen_width = self.size.width as f32;
-        
-        let pixels_per_second = screen_height / self.fall_duration_secs;
-
-        // --- PRENDIAMO I COLORI DALLO STATO ---
-        // Convertiamo da Color32 (0-255) a [f32; 3] (0.0-1.0) per lo shader
-let color_lh_f32: [f32; 3] = [
-        self.color_left_hand.r() as f32 / 255.0,
-        self.color_left_hand.g() as f32 / 255.0,
-        self.color_left_hand.b() as f32 / 255.0,
-    ];
-    let color_rh_f32: [f32; 3] = [
-        self.color_right_hand.r() as f32 / 255.0,
-        self.color_right_hand.g() as f32 / 255.0,
-        self.color_right_hand.b() as f32 / 255.0,
-    ];
-        const MIDDLE_C_PITCH: u8 = 60; // Do centrale
+        // Comandi dal controllo remoto, prima di far avanzare il trasporto
+        let commands = self.osc.as_mut().map(|osc| osc.poll()).unwrap_or_default();
+        for command in commands {
+            if let Err(e) = self.apply_remote(command) {
+                eprintln!("[ATTENZIONE] Comando OSC fallito: {}", e);
+                self.osc_error = Some(e);
+            }
+        }
+        if let Some(http) = self.http.take() {
+            // L'errore torna al client HTTP che ha inviato il comando
+            http.process(|command| self.apply_remote(command));
+            self.http = Some(http);
+        }
 
-        let mut vertices = Vec::new();
+        let mut current_time_secs = self.transport.tick();
+        let mut jumped = self.transport.looped();
 
-        for note in &self.midi_notes {
-            let present_line_y = 0.0;
-            let y_hit_position =
-                present_line_y + (note.start_time_secs - current_time_secs) * pixels_per_second;
-            let note_height_pixels = note.duration_secs * pixels_per_second;
-            let y_top_position = y_hit_position + note_height_pixels;
-
-            if y_top_position < 0.0 || y_hit_position > screen_height {
-                continue;
-            }
-
-            let x_pos = (note.pitch as f32 - 48.0) * NOTE_WIDTH + (screen_width / 4.0);
-            let w = NOTE_WIDTH;
-            
-            // --- LOGICA COLORE MODIFICATA ---
-            // Rimuoviamo la costante
-            // let c = NOTE_COLOR; // <-- RIMOSSO
-            
-            // Scegliamo il colore in base all'altezza (pitch) della nota
-            let c = if note.pitch < MIDDLE_C_PITCH {
-                color_lh_f32
+        // Con una sorgente esterna (clock MIDI o MTC) comanda la DAW: avvio,
+        // stop e locate arrivano da lì
+        if let Some(sync) = self.midi_sync.poll(&self.song.tempo_map) {
+            if sync.running != self.transport.is_playing() {
+                self.transport.toggle_play();
+                if !sync.running {
+                    self.all_notes_off();
+                }
+            }
+            let sync_jumped = if sync.running {
+                self.transport.follow(sync.position_secs)
             } else {
-                color_rh_f32
+                // Locate a trasporto fermo: si salta subito
+                let moved = (self.transport.position_secs() - sync.position_secs).abs() > 0.001;
+                if moved {
+                    self.transport.seek(sync.position_secs);
+                }
+                moved
             };
-            // --- FINE MODIFICA ---
-
-            let y_top = screen_height - y_top_position;
-            let y_hit = screen_height - y_hit_position;
-
-            vertices.extend_from_slice(&[
-                Vertex {
-                    position: [x_pos, y_hit],
-                    color: c,
-                },
-                Vertex {
-                    position: [x_pos + w, y_hit],
-                    color: c,
-                },
-                Vertex {
-                    position: [x_pos, y_top],
-                    color: c,
-                },
-                Vertex {
-                    position: [x_pos + w, y_hit],
-                    color: c,
-                },
-                Vertex {
-                    position: [x_pos + w, y_top],
-                    color: c,
-                },
-                Vertex {
-                    position: [x_pos, y_top],
-                    color: c,
-                },
-            ]);
-        }
-
-        // ... (Gestione buffer invariata) ...
-        if !vertices.is_empty() {
-            let required_size = (vertices.len() * std::mem::size_of::<Vertex>()) as u64;
-            let current_size = self.vertex_buffer.size();
-
-            if required_size > current_size {
-                let new_size = required_size * 2;
-                println!(
-                    "[INFO] Riallocazione buffer GPU: {} -> {} bytes",
-                    current_size, new_size
-                );
+            current_time_secs = self.transport.position_secs();
+            if sync_jumped {
+                self.audio.seek_recording(current_time_secs);
+            }
+            jumped |= sync_jumped;
+        }
+        // Con una registrazione agganciata comanda l'orologio della scheda audio:
+        // la registrazione fa da sola seek e loop sul campione esatto
+        else if self.transport.is_playing()
+            && !self.wait_mode.is_waiting()
+            && let Some(recording_secs) = self.audio.recording_position()
+        {
+            jumped |= self.transport.follow(recording_secs);
+            current_time_secs = self.transport.position_secs();
+        }
+        if jumped {
+            self.on_jump(current_time_secs);
+        }
 
-                self.vertex_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-                    label: Some("Vertex Buffer (Dynamic)"),
-                    size: new_size,
-                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                    mapped_at_creation: false,
-                });
+        // Scaletta: finiti il brano e la pausa che lo segue, si passa al
+        // successivo (non in loop A-B e non quando comanda una DAW)
+        if self.playlist.auto_advance
+            && self.playlist.current.is_some()
+            && self.transport.is_playing()
+            && self.transport.loop_region.is_none()
+            && self.midi_sync.connected_port.is_none()
+            && current_time_secs >= self.song_end_secs() + self.playlist.gap_secs
+        {
+            self.advance_playlist(true);
+            current_time_secs = self.transport.position_secs();
+        }
+
+        // Eventi dallo strumento dello studente
+        for event in self.midi_input.poll() {
+            self.wait_mode.on_note_event(event, current_time_secs);
+            match event {
+                NoteEvent::On { pitch, velocity } => {
+                    self.last_grade = Some(self.scorer.note_on(
+                        pitch,
+                        velocity,
+                        current_time_secs,
+                        &self.song.notes,
+                    ));
+                }
+                NoteEvent::Off { pitch } => self.scorer.note_off(pitch, current_time_secs),
             }
+        }
+        let was_waiting = self.wait_mode.is_waiting();
+        if let Some(hold_secs) = self.wait_mode.update(current_time_secs, &self.song.notes) {
+            self.transport.seek(hold_secs);
+            current_time_secs = hold_secs;
+            if !was_waiting {
+                self.audio.seek_recording(hold_secs);
+            }
+        }
+        self.scorer.update(current_time_secs, &self.song.notes);
+        if self.scorer.enabled && current_time_secs > self.song_end_secs() + 1.0 {
+            self.scorer.finish(&self.song.notes);
+        }
 
-            self.queue
-                .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
-            self.num_vertices = vertices.len() as u32;
-        } else {
-            self.num_vertices = 0;
+        // Il thread audio rispecchia il trasporto (ferma anche durante l'attesa)
+        let playback = PlaybackState {
+            playing: self.transport.is_playing() && !self.wait_mode.is_waiting(),
+            speed: self.transport.speed,
+            loop_region: self.transport.loop_region,
+        };
+        if self.sent_playback != Some(playback) {
+            self.audio.set_playback(playback);
+            self.sent_playback = Some(playback);
+        }
+
+        // Synth e uscita MIDI suonano le note che hanno appena raggiunto la linea del presente
+        let synth_enabled = self.synth_enabled;
+        let audio = &self.audio;
+        let midi_output = &mut self.midi_output;
+        let filter = &self.playback_filter;
+        let notes = &self.song.notes;
+        self.sequencer.advance(current_time_secs, |e| {
+            if let Some(index) = e.note
+                && !filter.allows(&notes[index])
+            {
+                return;
+            }
+            if synth_enabled {
+                audio.send(e.event);
+            }
+            midi_output.send_event(e.event);
+        });
+        if self.key_lights.enabled {
+            self.key_lights.advance(current_time_secs, &self.song, &mut self.midi_output);
+        }
+        // Il click suona sempre dall'audio interno, anche con il synth spento
+        let audio = &self.audio;
+        self.metronome
+            .advance(current_time_secs, &self.song.tempo_map, |e| audio.send(e));
+
+        if let Some(osc) = &mut self.osc {
+            let sounding: Vec<u8> = self
+                .song
+                .sounding_at(current_time_secs)
+                .map(|n| n.pitch)
+                .collect();
+            osc.publish(current_time_secs, self.transport.is_playing(), &sounding);
+        }
+        if let Some(http) = &self.http {
+            http.publish(self.snapshot(current_time_secs));
         }
+
+        self.autosave_settings();
+
+        self.frame_time_secs = current_time_secs;
+        let vertices = self.scene_vertices(
+            current_time_secs,
+            self.size.width as f32,
+            self.size.height as f32,
+        );
+        self.scene_renderer
+            .upload(&self.device, &self.queue, &vertices);
+    }
+
+    // Geometria della scena all'istante dato, per una superficie di qualsiasi dimensione
+    fn scene_vertices(&self, time_secs: f32, width: f32, height: f32) -> Vec<Vertex> {
+        let scene = Scene {
+            song: &self.song,
+            time_secs,
+            width,
+            height,
+            fall_duration_secs: self.fall_duration_secs,
+            // Convertiamo da Color32 (0-255) a [f32; 3] (0.0-1.0) per lo shader
+            color_left: scene::color_to_f32(self.color_left_hand),
+            color_right: scene::color_to_f32(self.color_right_hand),
+            key_range: self.key_range,
+        };
+        let mut vertices = Vec::new();
+        scene.push_notes(&mut vertices, |note_index, note| {
+            // Se la valutazione è attiva, le note già giudicate cambiano colore
+            match self.scorer.grade_of(note_index).filter(|_| self.scorer.enabled) {
+                Some(Grade::Hit) => FEEDBACK_HIT_COLOR,
+                Some(Grade::Early | Grade::Late) => FEEDBACK_EARLY_LATE_COLOR,
+                Some(Grade::Missed) => FEEDBACK_MISSED_COLOR,
+                Some(Grade::Wrong) | None => scene.hand_color(note.hand),
+            }
+        });
+        scene.push_keyboard(&mut vertices, |pitch| self.computer_keyboard.is_held(pitch));
+        vertices
     }
 
     // --- FUNZIONE RENDER (invariata) ---
@@ -417,29 +1213,10 @@ let color_lh_f32: [f32; 3] = [
         );
         
         {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.05,
-                            g: 0.05,
-                            b: 0.1,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
+            let mut render_pass = renderer::begin_clear_pass(&mut encoder, &view);
 
             // Disegna la tua visualizzazione
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.draw(0..self.num_vertices, 0..1);
+            self.scene_renderer.draw(&mut render_pass);
 
             // Disegna egui
             self.egui_renderer
@@ -452,5 +1229,438 @@ let color_lh_f32: [f32; 3] = [
 
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            let overlay = self
+                .screenshot_with_ui
+                .then_some((egui_primitives, window.scale_factor() as f32));
+            self.save_screenshot(overlay);
+        }
+    }
+
+    // L'aspetto attuale, quello che finisce nei preset
+    pub fn view_settings(&self) -> settings::ViewSettings {
+        settings::ViewSettings {
+            fall_duration_secs: self.fall_duration_secs,
+            color_left_hand: self.color_left_hand,
+            color_right_hand: self.color_right_hand,
+            key_range: self.key_range,
+        }
+    }
+
+    fn apply_view(&mut self, view: &settings::ViewSettings) {
+        self.fall_duration_secs = view.fall_duration_secs;
+        self.color_left_hand = view.color_left_hand;
+        self.color_right_hand = view.color_right_hand;
+        self.key_range = view.key_range;
+        self.key_range_text = view.key_range.map(|r| r.to_string()).unwrap_or_default();
+        self.key_range_error = None;
+    }
+
+    // Applica il testo della casella dei tasti (vuoto = attorno al Do3)
+    pub fn set_key_range_text(&mut self) {
+        let text = self.key_range_text.trim();
+        if text.is_empty() {
+            self.key_range = None;
+            self.key_range_error = None;
+            return;
+        }
+        match KeyRange::parse(text) {
+            Ok(range) => {
+                self.key_range = Some(range);
+                self.key_range_error = None;
+            }
+            Err(e) => self.key_range_error = Some(e),
+        }
+    }
+
+    pub fn select_preset(&mut self, name: &str) {
+        if let Some(preset) = self.presets.find(name) {
+            let (name, view) = (preset.name.clone(), preset.view.clone());
+            self.apply_view(&view);
+            self.preset_name = Some(name);
+            self.preset_status = None;
+        }
+    }
+
+    // true se l'aspetto è stato ritoccato dopo aver scelto il preset
+    pub fn preset_modified(&self) -> bool {
+        let current = self.view_settings();
+        self.preset_name
+            .as_deref()
+            .and_then(|name| self.presets.find(name))
+            .is_some_and(|preset| preset.view != current)
+    }
+
+    // Salva l'aspetto attuale come preset dell'utente, con il nome scritto
+    pub fn save_preset(&mut self) {
+        let name = self.preset_new_name.trim().to_string();
+        self.preset_status = Some(match self.presets.save(&name, self.view_settings()) {
+            Ok(()) => {
+                self.preset_name = Some(name.clone());
+                self.preset_new_name.clear();
+                format!("Preset '{}' salvato", name)
+            }
+            Err(e) => format!("Errore: {}", e),
+        });
+    }
+
+    pub fn delete_preset(&mut self) {
+        let Some(name) = self.preset_name.clone() else {
+            return;
+        };
+        self.preset_status = Some(match self.presets.delete(&name) {
+            Ok(()) => {
+                self.preset_name = None;
+                format!("Preset '{}' eliminato", name)
+            }
+            Err(e) => format!("Errore: {}", e),
+        });
+    }
+
+    // Aggiunge il preset del file indicato in `preset_file` e lo applica
+    pub fn import_preset(&mut self) {
+        let path = PathBuf::from(self.preset_file.trim());
+        match self.presets.import(&path) {
+            Ok(name) => {
+                self.select_preset(&name);
+                self.preset_status = Some(format!("Preset '{}' importato", name));
+            }
+            Err(e) => self.preset_status = Some(format!("Errore: {}", e)),
+        }
+    }
+
+    // Scrive l'aspetto attuale in `preset_file`, per condividerlo
+    pub fn export_preset(&mut self) {
+        let path = PathBuf::from(self.preset_file.trim());
+        let name = match &self.preset_name {
+            Some(name) if !self.preset_modified() => name.clone(),
+            _ => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "Personalizzato".to_string()),
+        };
+        let preset = Preset::new(&name, self.view_settings());
+        self.preset_status = Some(match preset.save(&path) {
+            Ok(()) => format!("Salvato {}", path.display()),
+            Err(e) => format!("Errore: {}", e),
+        });
+    }
+
+    // Le impostazioni dell'utente come sono adesso
+    pub fn settings(&self) -> Settings {
+        Settings {
+            version: settings::SETTINGS_VERSION,
+            preset: self.preset_name.clone(),
+            view: self.view_settings(),
+            playback: settings::PlaybackSettings {
+                speed: self.transport.speed,
+                metronome: self.metronome.enabled,
+                count_in_bars: self.metronome.count_in_bars,
+                mute_left_hand: self.playback_filter.mute_left_hand,
+                mute_right_hand: self.playback_filter.mute_right_hand,
+            },
+            audio: settings::AudioSettings {
+                synth_enabled: self.synth_enabled,
+                synth_volume: self.synth_volume,
+                soundfont_path: self.soundfont_path.trim().to_string(),
+            },
+            input: settings::InputSettings {
+                midi_port: self.midi_input.connected_port.clone(),
+                sync_port: self.midi_sync.connected_port.clone(),
+                mtc_offset_secs: self.midi_sync.mtc_offset_secs,
+                computer_keyboard: self.computer_keyboard.enabled,
+                computer_keyboard_velocity: self.computer_keyboard.velocity,
+                computer_keyboard_base_pitch: self.computer_keyboard.base_pitch,
+            },
+            output: settings::OutputSettings {
+                midi_port: self.midi_output.connected_port.clone(),
+                key_lights: self.key_lights.enabled,
+                key_lights_look_ahead_secs: self.key_lights.look_ahead_secs,
+                key_lights_left: self.key_lights.left,
+                key_lights_right: self.key_lights.right,
+            },
+            practice: settings::PracticeSettings {
+                wait_mode: self.wait_mode.enabled,
+                wait_hands: self.wait_mode.hands,
+                chord_tolerance_secs: self.wait_mode.chord_tolerance_secs,
+                scoring: self.scorer.enabled,
+                timing_windows: self.scorer.windows,
+            },
+            playlist: settings::PlaylistSettings {
+                auto_advance: self.playlist.auto_advance,
+                shuffle: self.playlist.shuffle,
+                repeat: self.playlist.repeat,
+                gap_secs: self.playlist.gap_secs,
+            },
+            remote: settings::RemoteSettings {
+                osc_port: self.osc_port,
+                osc_target: self.osc_target.clone(),
+                http_port: self.http_port,
+            },
+            screenshot: settings::ScreenshotSettings {
+                scale: self.screenshot_scale,
+                with_ui: self.screenshot_with_ui,
+            },
+            shortcuts: self.shortcuts.to_map(),
+        }
+    }
+
+    // Applica delle impostazioni (all'avvio o con "Ripristina predefiniti"),
+    // passando dagli stessi metodi dell'interfaccia
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        let position = self.transport.position_secs();
+
+        self.apply_view(&settings.view);
+        self.preset_name = settings.preset.clone();
+
+        let playback = &settings.playback;
+        self.transport.speed = playback.speed;
+        self.metronome.enabled = playback.metronome;
+        self.metronome.count_in_bars = playback.count_in_bars;
+        self.metronome.seek(position);
+        self.playback_filter.mute_left_hand = playback.mute_left_hand;
+        self.playback_filter.mute_right_hand = playback.mute_right_hand;
+
+        let audio = &settings.audio;
+        self.set_synth_enabled(audio.synth_enabled);
+        self.set_synth_volume(audio.synth_volume);
+        if audio.soundfont_path != self.soundfont_path.trim() {
+            self.soundfont_path = audio.soundfont_path.clone();
+            self.load_soundfont();
+        }
+
+        let input = &settings.input;
+        match &input.midi_port {
+            Some(port) if self.midi_input.connected_port.as_ref() != Some(port) => {
+                self.connect_midi_input(port)
+            }
+            Some(_) => {}
+            None => self.midi_input.disconnect(),
+        }
+        match &input.sync_port {
+            Some(port) if self.midi_sync.connected_port.as_ref() != Some(port) => {
+                self.connect_midi_sync(port)
+            }
+            Some(_) => {}
+            None => self.midi_sync.disconnect(),
+        }
+        self.midi_sync.mtc_offset_secs = input.mtc_offset_secs;
+        self.set_computer_keyboard_enabled(input.computer_keyboard);
+        self.computer_keyboard.velocity = input.computer_keyboard_velocity;
+        self.computer_keyboard.base_pitch = input.computer_keyboard_base_pitch;
+
+        let output = &settings.output;
+        match &output.midi_port {
+            Some(port) if self.midi_output.connected_port.as_ref() != Some(port) => {
+                self.connect_midi_output(port)
+            }
+            Some(_) => {}
+            None => self.midi_output.disconnect(),
+        }
+        self.key_lights.look_ahead_secs = output.key_lights_look_ahead_secs;
+        self.key_lights.left = output.key_lights_left;
+        self.key_lights.right = output.key_lights_right;
+        self.rebuild_key_lights();
+        self.set_key_lights_enabled(output.key_lights);
+
+        let practice = &settings.practice;
+        self.wait_mode.enabled = practice.wait_mode;
+        self.wait_mode.hands = practice.wait_hands;
+        self.wait_mode.chord_tolerance_secs = practice.chord_tolerance_secs;
+        self.wait_mode.resync(position, &self.song.notes);
+        if self.scorer.enabled != practice.scoring {
+            self.scorer.enabled = practice.scoring;
+            self.scorer.reset(position, &self.song.notes);
+        }
+        self.scorer.windows = practice.timing_windows;
+
+        let playlist = &settings.playlist;
+        self.playlist.auto_advance = playlist.auto_advance;
+        self.playlist.shuffle = playlist.shuffle;
+        self.playlist.repeat = playlist.repeat;
+        self.playlist.gap_secs = playlist.gap_secs;
+
+        self.osc_port = settings.remote.osc_port;
+        self.osc_target = settings.remote.osc_target.clone();
+        self.http_port = settings.remote.http_port;
+
+        self.screenshot_scale = settings.screenshot.scale;
+        self.screenshot_with_ui = settings.screenshot.with_ui;
+
+        self.shortcuts = Shortcuts::from_map(&settings.shortcuts);
+
+        self.all_notes_off();
+    }
+
+    // Salva le impostazioni se sono cambiate dall'ultima volta
+    pub fn save_settings(&mut self) {
+        self.settings_changed_at = None;
+        let settings = self.settings();
+        if settings == self.saved_settings {
+            return;
+        }
+        self.settings_error = settings.save().err();
+        if let Some(e) = &self.settings_error {
+            eprintln!("[ATTENZIONE] {}", e);
+        }
+        self.saved_settings = settings;
+    }
+
+    // Salvataggio automatico, un attimo dopo l'ultimo cambiamento
+    fn autosave_settings(&mut self) {
+        // Raccogliere tutte le impostazioni costa: basta guardarle ogni tanto
+        if self.settings_checked_at.elapsed() < settings::CHECK_INTERVAL {
+            return;
+        }
+        self.settings_checked_at = Instant::now();
+        if self.settings() == self.saved_settings {
+            self.settings_changed_at = None;
+            return;
+        }
+        match self.settings_changed_at {
+            None => self.settings_changed_at = Some(Instant::now()),
+            Some(changed_at) if changed_at.elapsed() >= settings::SAVE_DELAY => {
+                self.save_settings()
+            }
+            Some(_) => {}
+        }
+    }
+
+    // --- SCORCIATOIE ---
+
+    // Esegue l'azione di una scorciatoia da tastiera
+    pub fn run_action(&mut self, action: Action) {
+        let position = self.transport.position_secs();
+        // Passi di velocità sempre "tondi", anche dopo lo slider
+        let step_speed = |speed: f32, step: f32| ((speed + step) * 100.0).round() / 100.0;
+        match action {
+            Action::PlayPause => self.toggle_play(),
+            Action::Restart => self.restart(),
+            Action::SeekBackward => self.seek((position - shortcuts::SEEK_STEP_SECS).max(0.0)),
+            Action::SeekForward => {
+                self.seek((position + shortcuts::SEEK_STEP_SECS).min(self.song_end_secs()))
+            }
+            // Stessi limiti dello slider della UI
+            Action::SpeedDown => {
+                self.transport.speed =
+                    step_speed(self.transport.speed, -shortcuts::SPEED_STEP).max(0.25)
+            }
+            Action::SpeedUp => {
+                self.transport.speed =
+                    step_speed(self.transport.speed, shortcuts::SPEED_STEP).min(2.0)
+            }
+            Action::LoopStart => self.set_loop_start(),
+            Action::LoopEnd => self.set_loop_end(),
+            Action::LoopClear => self.transport.loop_region = None,
+            Action::OpenFile => {
+                self.show_ui = true;
+                let directory = self.song_path.as_deref().and_then(|p| p.parent());
+                self.file_browser.open_in(directory);
+            }
+            Action::PreviousSong => self.previous_playlist_entry(),
+            Action::NextSong => {
+                if !self.playlist.entries.is_empty() {
+                    self.advance_playlist(false);
+                }
+            }
+            Action::ToggleUi => self.show_ui = !self.show_ui,
+            Action::Fullscreen => self.fullscreen_requested = true,
+            Action::Screenshot => self.request_screenshot(),
+        }
+    }
+
+    // Nuovo tasto scelto dall'interfaccia per un'azione
+    pub fn bind_shortcut(&mut self, action: Action, binding: KeyBinding) {
+        self.shortcuts_status = self.shortcuts.bind(action, binding).map(|previous| {
+            format!(
+                "{} non è più il tasto di \"{}\"",
+                binding,
+                previous.label()
+            )
+        });
+    }
+
+    // true una volta per ogni richiesta di (uscire dallo) schermo intero
+    pub fn take_fullscreen_request(&mut self) -> bool {
+        std::mem::take(&mut self.fullscreen_requested)
+    }
+
+    // Scatta al prossimo fotogramma disegnato
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    fn save_screenshot(&mut self, overlay: Option<(&[egui::ClippedPrimitive], f32)>) {
+        // Non oltre la texture più grande che il dispositivo sa gestire
+        let max_side = self.device.limits().max_texture_dimension_2d;
+        let scale = self
+            .screenshot_scale
+            .min(max_side / self.size.width.max(self.size.height).max(1))
+            .max(1);
+        let (width, height) = (self.size.width * scale, self.size.height * scale);
+        let path = screenshot::screenshot_path(self.song_path.as_deref());
+        let saved = self
+            .capture_frame(width, height, overlay)
+            .and_then(|rgba| png_writer::write_png(&path, width, height, &rgba));
+        self.screenshot_status = Some(match saved {
+            Ok(()) => {
+                println!("[INFO] Screenshot salvato in '{}'", path.display());
+                format!("Salvato {} ({}x{})", path.display(), width, height)
+            }
+            Err(e) => format!("Errore: {}", e),
+        });
+    }
+
+    // Disegna il fotogramma attuale fuori schermo alla risoluzione data e lo
+    // restituisce come RGBA. `overlay` aggiunge l'interfaccia egui (primitive
+    // del fotogramma e punti per pixel della finestra), ingrandita in proporzione.
+    pub fn capture_frame(
+        &mut self,
+        width: u32,
+        height: u32,
+        overlay: Option<(&[egui::ClippedPrimitive], f32)>,
+    ) -> Result<Vec<u8>, String> {
+        let target = OffscreenTarget::new(&self.device, width, height, self.config.format)?;
+        let vertices = self.scene_vertices(self.frame_time_secs, width as f32, height as f32);
+        self.scene_renderer.resize(&self.queue, width, height);
+        self.scene_renderer
+            .upload(&self.device, &self.queue, &vertices);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Screenshot Encoder"),
+            });
+        let screen_descriptor = overlay.map(|(primitives, pixels_per_point)| {
+            let screen_descriptor = egui_wgpu::renderer::ScreenDescriptor {
+                size_in_pixels: [width, height],
+                pixels_per_point: pixels_per_point * width as f32 / self.size.width as f32,
+            };
+            self.egui_renderer.update_buffers(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                primitives,
+                &screen_descriptor,
+            );
+            (primitives, screen_descriptor)
+        });
+        {
+            let mut render_pass = renderer::begin_clear_pass(&mut encoder, target.view());
+            self.scene_renderer.draw(&mut render_pass);
+            if let Some((primitives, screen_descriptor)) = &screen_descriptor {
+                self.egui_renderer
+                    .render(&mut render_pass, primitives, screen_descriptor);
+            }
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+        let rgba = target.read_rgba(&self.device, &self.queue);
+
+        // La finestra torna alle sue dimensioni; i vertici li rifà update()
+        self.scene_renderer
+            .resize(&self.queue, self.size.width, self.size.height);
+        rgba
     }
 }
\ No newline at end of file
@@ -0,0 +1,261 @@
+// src/synth.rs
+use std::f32::consts::TAU;
+
+// Numero massimo di voci contemporanee: oltre, si ruba la voce più vecchia
+const MAX_VOICES: usize = 64;
+// Durata dell'attacco e del rilascio dell'inviluppo
+const ATTACK_SECS: f32 = 0.005;
+const RELEASE_SECS: f32 = 0.12;
+// Parziali della sintesi additiva
+const HARMONICS: usize = 6;
+// Frequenza del click del metronomo (il primo battito è un'ottava sopra)
+const CLICK_HZ: f32 = 1_000.0;
+const CLICK_SECS: f32 = 0.03;
+
+// Un comando per il sintetizzatore, dal trasporto o dal rendering offline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SynthEvent {
+    NoteOn {
+        channel: u8,
+        pitch: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        pitch: u8,
+    },
+    Sustain {
+        channel: u8,
+        down: bool,
+    },
+    ProgramChange {
+        channel: u8,
+        bank: u8,
+        program: u8,
+    },
+    AllNotesOff,
+    SetVolume(f32),
+    // Battito del metronomo; `accent` per il primo della battuta
+    Click {
+        accent: bool,
+    },
+}
+
+// Qualsiasi generatore di suono pilotabile con `SynthEvent`: il synth additivo
+// integrato o una SoundFont. Vive nel thread audio, quindi deve essere `Send`.
+pub trait Instrument: Send {
+    fn handle_event(&mut self, event: SynthEvent);
+    // Sovrascrive i due canali con i prossimi campioni (stessa lunghezza)
+    fn render(&mut self, left: &mut [f32], right: &mut [f32]);
+}
+
+struct Voice {
+    pitch: u8,
+    // Incremento di fase per campione della fondamentale
+    phase_step: f32,
+    phase: f32,
+    // Ampiezza corrente di ogni parziale, già scalata per la velocity
+    harmonic_gains: [f32; HARMONICS],
+    // Smorzamento naturale per campione: le parziali alte muoiono prima
+    harmonic_decays: [f32; HARMONICS],
+    age_samples: u32,
+    attack_samples: u32,
+    key_down: bool,
+    // Tasto rilasciato ma tenuto dal pedale
+    sustained: bool,
+    released: bool,
+    release_gain: f32,
+    release_decay: f32,
+}
+
+impl Voice {
+    fn new(pitch: u8, velocity: u8, sample_rate: f32) -> Self {
+        let frequency = 440.0 * 2f32.powf((pitch as f32 - 69.0) / 12.0);
+        let velocity = velocity as f32 / 127.0;
+        let loudness = velocity.powf(1.5) * 0.25;
+        // Più forte si suona, più ricco è il timbro
+        let brightness = 2.4 - velocity;
+        // Le note gravi risuonano più a lungo di quelle acute
+        let base_decay = 0.4 + pitch as f32 / 40.0;
+
+        let mut harmonic_gains = [0.0; HARMONICS];
+        let mut harmonic_decays = [0.0; HARMONICS];
+        for h in 0..HARMONICS {
+            let n = (h + 1) as f32;
+            // Niente parziali vicino a Nyquist
+            if frequency * n < sample_rate * 0.4 {
+                harmonic_gains[h] = loudness / n.powf(brightness);
+            }
+            harmonic_decays[h] = (-base_decay * (1.0 + 0.6 * h as f32) / sample_rate).exp();
+        }
+
+        Self {
+            pitch,
+            phase_step: TAU * frequency / sample_rate,
+            phase: 0.0,
+            harmonic_gains,
+            harmonic_decays,
+            age_samples: 0,
+            attack_samples: (ATTACK_SECS * sample_rate) as u32,
+            key_down: true,
+            sustained: false,
+            released: false,
+            release_gain: 1.0,
+            release_decay: (-5.0 / (RELEASE_SECS * sample_rate)).exp(),
+        }
+    }
+
+    fn release(&mut self) {
+        self.released = true;
+    }
+
+    fn is_finished(&self) -> bool {
+        self.release_gain < 1e-4 || self.harmonic_gains.iter().all(|&g| g < 1e-5)
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        let mut value = 0.0;
+        for h in 0..HARMONICS {
+            value += self.harmonic_gains[h] * (self.phase * (h + 1) as f32).sin();
+            self.harmonic_gains[h] *= self.harmonic_decays[h];
+        }
+
+        let attack = if self.age_samples < self.attack_samples {
+            self.age_samples as f32 / self.attack_samples as f32
+        } else {
+            1.0
+        };
+        if self.released {
+            self.release_gain *= self.release_decay;
+        }
+
+        self.phase = (self.phase + self.phase_step) % TAU;
+        self.age_samples = self.age_samples.saturating_add(1);
+        value * attack * self.release_gain
+    }
+}
+
+// Un colpo di metronomo: sinusoide acuta che si spegne in pochi millisecondi
+struct ClickVoice {
+    phase_step: f32,
+    phase: f32,
+    gain: f32,
+    decay: f32,
+}
+
+impl ClickVoice {
+    fn new(accent: bool, sample_rate: f32) -> Self {
+        let frequency = if accent { CLICK_HZ * 2.0 } else { CLICK_HZ };
+        Self {
+            phase_step: TAU * frequency / sample_rate,
+            phase: 0.0,
+            gain: if accent { 0.5 } else { 0.35 },
+            decay: (-5.0 / (CLICK_SECS * sample_rate)).exp(),
+        }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        let value = self.gain * self.phase.sin();
+        self.phase = (self.phase + self.phase_step) % TAU;
+        self.gain *= self.decay;
+        value
+    }
+}
+
+// Sintetizzatore polifonico additivo con un timbro vagamente pianistico.
+// Non sa nulla di dispositivi audio: riempie buffer di campioni, così si usa
+// allo stesso modo in tempo reale e nel rendering su WAV. Suona tutti i
+// canali con lo stesso timbro e ignora i cambi di programma.
+pub struct AdditiveSynth {
+    sample_rate: f32,
+    voices: Vec<Voice>,
+    clicks: Vec<ClickVoice>,
+    sustain: bool,
+    volume: f32,
+}
+
+impl AdditiveSynth {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate: sample_rate as f32,
+            voices: Vec::with_capacity(MAX_VOICES),
+            clicks: Vec::new(),
+            sustain: false,
+            volume: 0.8,
+        }
+    }
+}
+
+impl Instrument for AdditiveSynth {
+    fn handle_event(&mut self, event: SynthEvent) {
+        match event {
+            SynthEvent::NoteOn {
+                pitch, velocity, ..
+            } => {
+                // Ribattere un tasto spegne la voce precedente sullo stesso tasto
+                for voice in self.voices.iter_mut().filter(|v| v.pitch == pitch) {
+                    voice.release();
+                }
+                if self.voices.len() >= MAX_VOICES {
+                    self.voices.remove(0);
+                }
+                self.voices
+                    .push(Voice::new(pitch, velocity, self.sample_rate));
+            }
+            SynthEvent::NoteOff { pitch, .. } => {
+                for voice in self
+                    .voices
+                    .iter_mut()
+                    .filter(|v| v.pitch == pitch && v.key_down)
+                {
+                    voice.key_down = false;
+                    if self.sustain {
+                        voice.sustained = true;
+                    } else {
+                        voice.release();
+                    }
+                }
+            }
+            SynthEvent::Sustain { down, .. } => {
+                self.sustain = down;
+                if !down {
+                    for voice in self.voices.iter_mut().filter(|v| v.sustained) {
+                        voice.sustained = false;
+                        voice.release();
+                    }
+                }
+            }
+            SynthEvent::AllNotesOff => {
+                self.sustain = false;
+                for voice in &mut self.voices {
+                    voice.key_down = false;
+                    voice.sustained = false;
+                    voice.release();
+                }
+            }
+            SynthEvent::ProgramChange { .. } => {}
+            SynthEvent::SetVolume(volume) => self.volume = volume,
+            SynthEvent::Click { accent } => {
+                self.clicks.push(ClickVoice::new(accent, self.sample_rate))
+            }
+        }
+    }
+
+    fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let mut mix = 0.0;
+            for voice in &mut self.voices {
+                mix += voice.next_sample();
+            }
+            for click in &mut self.clicks {
+                mix += click.next_sample();
+            }
+            // Soft clip per non distorcere con accordi pieni
+            let value = (mix * self.volume).tanh();
+            *l = value;
+            *r = value;
+        }
+        self.voices.retain(|v| !v.is_finished());
+        self.clicks.retain(|c| c.gain > 1e-4);
+    }
+}
@@ -0,0 +1,240 @@
+// src/tempo_map.rs
+use midly::{Smf, TrackEventKind};
+
+// Da un cambio di tempo in poi: dove cade (tick e secondi) e quanto dura un beat
+#[derive(Debug, Clone)]
+struct TempoSegment {
+    tick: u32,
+    secs: f64,
+    us_per_beat: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TimeSignature {
+    pub tick: u32,
+    pub numerator: u8,
+    // Valore della nota che fa da unità (4 = semiminima, 8 = croma...)
+    pub denominator: u8,
+}
+
+// Un battito del metronomo
+#[derive(Debug, Clone, Copy)]
+pub struct Beat {
+    pub time_secs: f32,
+    // Primo battito della battuta
+    pub downbeat: bool,
+}
+
+// Conversione fra tick MIDI e secondi che tiene conto di tutti i cambi di
+// tempo, più le indicazioni di tempo (4/4, 6/8...) per il metronomo.
+// Prima dell'inizio del brano valgono il primo tempo e la prima indicazione.
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    ticks_per_beat: u16,
+    segments: Vec<TempoSegment>,
+    time_signatures: Vec<TimeSignature>,
+}
+
+impl Default for TempoMap {
+    // 120 BPM in 4/4, il default dello standard MIDI
+    fn default() -> Self {
+        Self::new(480, Vec::new(), Vec::new())
+    }
+}
+
+impl TempoMap {
+    // `tempo_changes` sono coppie (tick assoluto, microsecondi per beat)
+    fn new(
+        ticks_per_beat: u16,
+        mut tempo_changes: Vec<(u32, u32)>,
+        mut time_signatures: Vec<TimeSignature>,
+    ) -> Self {
+        tempo_changes.sort_by_key(|&(tick, _)| tick);
+        if tempo_changes.first().is_none_or(|&(tick, _)| tick > 0) {
+            tempo_changes.insert(0, (0, 500_000)); // 120 BPM = 500,000 µs per beat
+        }
+
+        let mut segments: Vec<TempoSegment> = Vec::with_capacity(tempo_changes.len());
+        for (tick, us_per_beat) in tempo_changes {
+            let secs = match segments.last() {
+                Some(previous) => {
+                    previous.secs + Self::segment_secs(previous, tick, ticks_per_beat)
+                }
+                None => 0.0,
+            };
+            segments.push(TempoSegment {
+                tick,
+                secs,
+                us_per_beat,
+            });
+        }
+
+        time_signatures.sort_by_key(|s| s.tick);
+        if time_signatures.first().is_none_or(|s| s.tick > 0) {
+            time_signatures.insert(
+                0,
+                TimeSignature {
+                    tick: 0,
+                    numerator: 4,
+                    denominator: 4,
+                },
+            );
+        }
+
+        Self {
+            ticks_per_beat,
+            segments,
+            time_signatures,
+        }
+    }
+
+    // Legge tempo e indicazioni di tempo da tutte le tracce del file
+    pub fn from_smf(smf: &Smf) -> Self {
+        let ticks_per_beat = match smf.header.timing {
+            midly::Timing::Metrical(tpq) => tpq.as_int(),
+            _ => 480, // Fallback comune
+        };
+
+        let mut tempo_changes = Vec::new();
+        let mut time_signatures = Vec::new();
+        for track in &smf.tracks {
+            let mut tick: u32 = 0;
+            for event in track {
+                tick += event.delta.as_int();
+                match event.kind {
+                    TrackEventKind::Meta(midly::MetaMessage::Tempo(us_per_beat)) => {
+                        tempo_changes.push((tick, us_per_beat.as_int()));
+                    }
+                    TrackEventKind::Meta(midly::MetaMessage::TimeSignature(
+                        numerator,
+                        denominator_pow2,
+                        _,
+                        _,
+                    )) if numerator > 0 => {
+                        time_signatures.push(TimeSignature {
+                            tick,
+                            numerator,
+                            denominator: 1u8.checked_shl(denominator_pow2 as u32).unwrap_or(4),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Self::new(ticks_per_beat, tempo_changes, time_signatures)
+    }
+
+    fn segment_secs(segment: &TempoSegment, tick: impl Into<f64>, ticks_per_beat: u16) -> f64 {
+        (tick.into() - segment.tick as f64) * segment.us_per_beat as f64
+            / 1_000_000.0
+            / ticks_per_beat as f64
+    }
+
+    // Tempo più lento e più veloce del brano, in BPM
+    pub fn bpm_range(&self) -> (f64, f64) {
+        self.segments
+            .iter()
+            .map(|s| 60_000_000.0 / s.us_per_beat as f64)
+            .fold((f64::MAX, 0.0), |(slowest, fastest), bpm| {
+                (slowest.min(bpm), fastest.max(bpm))
+            })
+    }
+
+    pub fn time_signatures(&self) -> &[TimeSignature] {
+        &self.time_signatures
+    }
+
+    pub fn ticks_to_secs(&self, tick: u32) -> f32 {
+        self.fractional_ticks_to_secs(tick as f64) as f32
+    }
+
+    // Posizione espressa in semiminime dall'inizio (es. dal clock MIDI)
+    pub fn quarter_notes_to_secs(&self, quarter_notes: f64) -> f32 {
+        self.fractional_ticks_to_secs(quarter_notes * self.ticks_per_beat as f64) as f32
+    }
+
+    fn fractional_ticks_to_secs(&self, tick: f64) -> f64 {
+        let index = self
+            .segments
+            .partition_point(|s| (s.tick as f64) <= tick)
+            .saturating_sub(1);
+        let segment = &self.segments[index];
+        segment.secs + Self::segment_secs(segment, tick, self.ticks_per_beat)
+    }
+
+    fn secs_to_ticks(&self, secs: f64) -> f64 {
+        let index = self
+            .segments
+            .partition_point(|s| s.secs <= secs)
+            .saturating_sub(1);
+        let segment = &self.segments[index];
+        segment.tick as f64
+            + (secs - segment.secs) * 1_000_000.0 * self.ticks_per_beat as f64
+                / segment.us_per_beat as f64
+    }
+
+    fn time_signature_at(&self, tick: f64) -> (usize, &TimeSignature) {
+        let index = self
+            .time_signatures
+            .partition_point(|s| (s.tick as f64) <= tick)
+            .saturating_sub(1);
+        (index, &self.time_signatures[index])
+    }
+
+    // Durata in tick di un battito (l'unità indicata dal denominatore)
+    fn beat_ticks(&self, signature: &TimeSignature) -> f64 {
+        self.ticks_per_beat as f64 * 4.0 / signature.denominator as f64
+    }
+
+    // Durata in secondi della battuta in corso a `secs` (per il count-in)
+    pub fn bar_secs_at(&self, secs: f32) -> f32 {
+        let tick = self.secs_to_ticks(secs as f64);
+        let (_, signature) = self.time_signature_at(tick);
+        let bar_ticks = self.beat_ticks(signature) * signature.numerator as f64;
+        (self.fractional_ticks_to_secs(tick + bar_ticks) - self.fractional_ticks_to_secs(tick))
+            as f32
+    }
+
+    // Tutti i battiti con tempo in [from_secs, to_secs)
+    pub fn beats_between(&self, from_secs: f32, to_secs: f32) -> Vec<Beat> {
+        let from_tick = self.secs_to_ticks(from_secs as f64);
+        let to_tick = self.secs_to_ticks(to_secs as f64);
+
+        let mut beats = Vec::new();
+        let (mut index, _) = self.time_signature_at(from_tick);
+        while index < self.time_signatures.len() {
+            let signature = &self.time_signatures[index];
+            // La prima indicazione vale anche prima dell'inizio (count-in)
+            let segment_start = if index == 0 {
+                f64::NEG_INFINITY
+            } else {
+                signature.tick as f64
+            };
+            let segment_end = self
+                .time_signatures
+                .get(index + 1)
+                .map_or(f64::INFINITY, |next| next.tick as f64);
+            if segment_start >= to_tick {
+                break;
+            }
+
+            let beat_ticks = self.beat_ticks(signature);
+            let start = from_tick.max(segment_start);
+            let mut beat_index = ((start - signature.tick as f64) / beat_ticks).ceil() as i64;
+            loop {
+                let tick = signature.tick as f64 + beat_index as f64 * beat_ticks;
+                if tick >= to_tick || tick >= segment_end {
+                    break;
+                }
+                beats.push(Beat {
+                    time_secs: self.fractional_ticks_to_secs(tick) as f32,
+                    downbeat: beat_index.rem_euclid(signature.numerator as i64) == 0,
+                });
+                beat_index += 1;
+            }
+            index += 1;
+        }
+        beats
+    }
+}
@@ -0,0 +1,140 @@
+// src/thumbnail.rs
+// Anteprime dei file MIDI: una panoramica del piano roll in cui ogni pixel
+// mostra quanto è fitta la musica in quel punto, nel colore della mano.
+// Le immagini restano in cache su disco, con il nome preso dall'hash del
+// file: rinominare o spostare un brano non le invalida, modificarlo sì.
+use crate::config::{COLOR_LEFT_HAND, COLOR_RIGHT_HAND};
+use crate::midi_loader::{self, Hand, Song};
+use crate::paths;
+use crate::piano_roll;
+use crate::png_writer::write_png;
+use crate::renderer::CLEAR_COLOR;
+use crate::scene;
+use std::path::{Path, PathBuf};
+
+pub const THUMBNAIL_WIDTH: u32 = 256;
+pub const THUMBNAIL_HEIGHT: u32 = 64;
+// Da cambiare quando cambia il disegno, per scartare le anteprime vecchie
+const THUMBNAIL_VERSION: u32 = 1;
+
+// FNV-1a a 64 bit: stabile fra versioni e piattaforme, basta per una cache
+fn file_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// Disegna l'anteprima in RGBA: il tempo (tutto il brano) va da sinistra a
+// destra, le altezze dal basso verso l'alto
+pub fn render_thumbnail(song: &Song, width: u32, height: u32) -> Vec<u8> {
+    let background = [
+        CLEAR_COLOR.r as f32,
+        CLEAR_COLOR.g as f32,
+        CLEAR_COLOR.b as f32,
+    ];
+    let colors = [
+        scene::color_to_f32(COLOR_LEFT_HAND),
+        scene::color_to_f32(COLOR_RIGHT_HAND),
+    ];
+    let end_secs = song.end_secs();
+    let (w, h) = (width as usize, height as usize);
+
+    // Secondi di nota che cadono in ogni cella, separati per mano
+    let mut coverage = vec![[0.0f32; 2]; w * h];
+    if let Some(pitches) = piano_roll::pitch_range(song, 0.0, end_secs) {
+        let low = *pitches.start() as f32;
+        let pitch_count = (*pitches.end() - *pitches.start() + 1) as f32;
+        let secs_per_column = end_secs / width as f32;
+        for note in &song.notes {
+            let row = (((pitch_count - 1.0 - (note.pitch as f32 - low)) / pitch_count)
+                * height as f32) as usize;
+            let hand = match note.hand {
+                Hand::Left => 0,
+                Hand::Right => 1,
+            };
+            let (start, end) = (
+                note.start_time_secs,
+                note.start_time_secs + note.duration_secs,
+            );
+            let first = (start / secs_per_column) as usize;
+            let last = ((end / secs_per_column) as usize).min(w - 1);
+            for column in first.min(w - 1)..=last {
+                let column_start = column as f32 * secs_per_column;
+                let overlap = end.min(column_start + secs_per_column) - start.max(column_start);
+                coverage[row.min(h - 1) * w + column][hand] += overlap.max(0.0);
+            }
+        }
+        // Quota della cella occupata: 1 = una nota tenuta su ogni tasto della riga
+        let cell_capacity = secs_per_column * (pitch_count / height as f32).max(1.0);
+        for cell in &mut coverage {
+            cell[0] /= cell_capacity;
+            cell[1] /= cell_capacity;
+        }
+    }
+
+    let mut rgba = Vec::with_capacity(w * h * 4);
+    for [left, right] in coverage {
+        let total = left + right;
+        // Anche poche note devono vedersi: la densità satura presto
+        let intensity = (total * 2.0).min(1.0);
+        let mut pixel = background;
+        if total > 0.0 {
+            for (channel, value) in pixel.iter_mut().enumerate() {
+                let hand_mix = (colors[0][channel] * left + colors[1][channel] * right) / total;
+                *value += (hand_mix - *value) * intensity;
+            }
+        }
+        rgba.extend(pixel.map(scene::linear_to_srgb8));
+        rgba.push(255);
+    }
+    rgba
+}
+
+// Percorso dell'anteprima del file MIDI, creata al primo uso
+pub fn thumbnail_path(midi_path: &Path, width: u32, height: u32) -> Result<PathBuf, String> {
+    let bytes = std::fs::read(midi_path)
+        .map_err(|e| format!("Impossibile leggere '{}': {}", midi_path.display(), e))?;
+    let directory = paths::cache_dir().join("thumbnails");
+    let cached = directory.join(format!(
+        "{:016x}_{}x{}_v{}.png",
+        file_hash(&bytes),
+        width,
+        height,
+        THUMBNAIL_VERSION
+    ));
+    if cached.exists() {
+        return Ok(cached);
+    }
+
+    let song =
+        midi_loader::parse_midi(&bytes).map_err(|e| format!("'{}': {}", midi_path.display(), e))?;
+    let rgba = render_thumbnail(&song, width, height);
+    std::fs::create_dir_all(&directory)
+        .map_err(|e| format!("Impossibile creare '{}': {}", directory.display(), e))?;
+    // Prima in un file temporaneo: un'interruzione non lascia anteprime rotte
+    let partial = cached.with_extension("part");
+    write_png(&partial, width, height, &rgba)?;
+    std::fs::rename(&partial, &cached)
+        .map_err(|e| format!("Impossibile salvare '{}': {}", cached.display(), e))?;
+    Ok(cached)
+}
+
+// L'anteprima in RGBA, letta dalla cache (e creata se manca)
+pub fn load_thumbnail(midi_path: &Path, width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let path = thumbnail_path(midi_path, width, height)?;
+    let file = std::fs::File::open(&path)
+        .map_err(|e| format!("Impossibile leggere '{}': {}", path.display(), e))?;
+    let mut reader = png::Decoder::new(std::io::BufReader::new(file))
+        .read_info()
+        .map_err(|e| format!("Anteprima '{}' non valida: {}", path.display(), e))?;
+    let mut rgba = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut rgba)
+        .map_err(|e| format!("Anteprima '{}' non valida: {}", path.display(), e))?;
+    // Le anteprime le scrive solo write_png, sempre in RGBA a 8 bit
+    if info.color_type != png::ColorType::Rgba || (info.width, info.height) != (width, height) {
+        return Err(format!("Anteprima '{}' non valida", path.display()));
+    }
+    rgba.truncate(info.buffer_size());
+    Ok(rgba)
+}
@@ -0,0 +1,86 @@
+// src/transport.rs
+use std::time::Instant;
+
+// Oltre questa distanza da un orologio esterno si salta, invece di correggere piano
+const FOLLOW_SNAP_SECS: f32 = 0.1;
+// Frazione dello scarto recuperata ad ogni frame
+const FOLLOW_GAIN: f32 = 0.1;
+
+// L'orologio della riproduzione: posizione nel brano, pausa, velocità e loop.
+// Sostituisce il vecchio `start_time.elapsed()`, che non si poteva fermare.
+pub struct Transport {
+    position_secs: f32,
+    playing: bool,
+    pub speed: f32,
+    // Regione A-B ripetuta all'infinito
+    pub loop_region: Option<(f32, f32)>,
+    looped: bool,
+    last_tick: Instant,
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Self {
+            position_secs: 0.0,
+            playing: true,
+            speed: 1.0,
+            loop_region: None,
+            looped: false,
+            last_tick: Instant::now(),
+        }
+    }
+
+    // Avanza la posizione in base al tempo reale trascorso dall'ultima chiamata
+    // (da chiamare una volta per frame) e restituisce la posizione aggiornata
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+        self.looped = false;
+        if self.playing {
+            self.position_secs += now.duration_since(self.last_tick).as_secs_f32() * self.speed;
+
+            if let Some((start, end)) = self.loop_region
+                && end > start
+                && self.position_secs >= end
+            {
+                self.position_secs = start + (self.position_secs - end) % (end - start);
+                self.looped = true;
+            }
+        }
+        self.last_tick = now;
+        self.position_secs
+    }
+
+    // true se l'ultimo `tick` è tornato all'inizio del loop
+    pub fn looped(&self) -> bool {
+        self.looped
+    }
+
+    // Corregge la posizione verso un orologio esterno più affidabile (es. la
+    // scheda audio), smussandone il jitter. Restituisce true se ha dovuto saltare.
+    pub fn follow(&mut self, reference_secs: f32) -> bool {
+        let drift = reference_secs - self.position_secs;
+        if drift.abs() > FOLLOW_SNAP_SECS {
+            self.position_secs = reference_secs;
+            true
+        } else {
+            self.position_secs += drift * FOLLOW_GAIN;
+            false
+        }
+    }
+
+    pub fn position_secs(&self) -> f32 {
+        self.position_secs
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn toggle_play(&mut self) {
+        self.playing = !self.playing;
+    }
+
+    pub fn seek(&mut self, position_secs: f32) {
+        self.position_secs = position_secs;
+    }
+}
@@ -0,0 +1,1143 @@
+// src/ui.rs
+use crate::config::KEYBOARD_HEIGHT;
+use crate::layout::{self, Layout};
+use crate::midi_loader::Hand;
+use crate::midi_sync::SyncSource;
+use crate::playlist::Repeat;
+use crate::scoring::Grade;
+use crate::screenshot::SCREENSHOT_SCALES;
+use crate::settings::Settings;
+use crate::shortcuts::{Action, Shortcuts};
+use crate::state::State;
+use crate::wait_mode::WaitHands;
+
+// Costruisce la finestra "Impostazioni" di egui
+pub fn draw(ctx: &egui::Context, state: &mut State) {
+    // Prima il tasto da riassegnare, poi le scorciatoie: i tasti usati non
+    // arrivano alla tastiera come strumento né ai widget
+    if let Some((action, binding)) = state.shortcuts.capture(ctx) {
+        state.bind_shortcut(action, binding);
+    }
+    for action in state.shortcuts.pressed(ctx) {
+        state.run_action(action);
+    }
+    handle_computer_keyboard(ctx, state);
+    draw_keyboard(ctx, state);
+    draw_annotations(ctx, state);
+    if !state.show_ui {
+        return;
+    }
+
+    draw_menu_bar(ctx, state);
+    if let Some(path) = state.file_browser.show(ctx) {
+        state.open_file(&path);
+    }
+    if state.show_lesson {
+        draw_lesson(ctx, state);
+    }
+    if state.show_playlist {
+        draw_playlist(ctx, state);
+    }
+
+    egui::Window::new("Impostazioni").show(ctx, |ui| {
+        ui.label("Trasporto");
+        ui.horizontal(|ui| {
+            let play_label = if state.transport.is_playing() {
+                "Pausa"
+            } else {
+                "Play"
+            };
+            if ui.button(play_label).clicked() {
+                state.toggle_play();
+            }
+            if ui.button("Ricomincia").clicked() {
+                state.restart();
+            }
+            ui.label(format!("{:.1} s", state.transport.position_secs()));
+        });
+        ui.add(
+            egui::Slider::new(&mut state.transport.speed, 0.25..=2.0).text("Velocità Riproduzione"),
+        );
+        ui.horizontal(|ui| {
+            ui.label("Loop:");
+            if ui.button("Imposta A").clicked() {
+                state.set_loop_start();
+            }
+            if ui.button("Imposta B").clicked() {
+                state.set_loop_end();
+            }
+            if let Some((start, end)) = state.transport.loop_region {
+                ui.label(format!("{:.1} - {:.1} s", start, end));
+                if ui.button("Disattiva").clicked() {
+                    state.transport.loop_region = None;
+                }
+            }
+        });
+
+        ui.separator();
+
+        ui.label("Metronomo");
+        ui.horizontal(|ui| {
+            let mut enabled = state.metronome.enabled;
+            if ui.checkbox(&mut enabled, "Attivo").changed() {
+                state.metronome.enabled = enabled;
+                state.metronome.seek(state.transport.position_secs());
+            }
+            // Indicatore del battito: più chiaro sul primo della battuta
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+            let color = match state.metronome.flash(state.transport.position_secs()) {
+                Some(true) => egui::Color32::from_rgb(255, 210, 60),
+                Some(false) => egui::Color32::from_rgb(120, 200, 255),
+                None => egui::Color32::DARK_GRAY,
+            };
+            ui.painter().circle_filled(rect.center(), 7.0, color);
+        });
+        ui.add(
+            egui::DragValue::new(&mut state.metronome.count_in_bars)
+                .clamp_range(0..=4)
+                .prefix("Battute di conteggio: "),
+        );
+
+        ui.separator();
+
+        ui.label("Preset Aspetto");
+        ui.horizontal(|ui| {
+            let selected = match &state.preset_name {
+                Some(name) if state.preset_modified() => format!("{} *", name),
+                Some(name) => name.clone(),
+                None => "Personalizzato".to_string(),
+            };
+            let mut chosen_preset = None;
+            egui::ComboBox::from_id_source("preset")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for preset in state.presets.presets() {
+                        let current = state.preset_name.as_deref() == Some(preset.name.as_str());
+                        if ui.selectable_label(current, &preset.name).clicked() {
+                            chosen_preset = Some(preset.name.clone());
+                        }
+                    }
+                });
+            if let Some(name) = chosen_preset {
+                state.select_preset(&name);
+            }
+            // I preset predefiniti non si eliminano
+            let deletable = state
+                .preset_name
+                .as_deref()
+                .is_some_and(|name| !state.presets.is_builtin(name));
+            if ui.add_enabled(deletable, egui::Button::new("Elimina")).clicked() {
+                state.delete_preset();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut state.preset_new_name).hint_text("Nome del preset"),
+            );
+            if ui.button("Salva preset").clicked() {
+                state.save_preset();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("File:");
+            ui.add(egui::TextEdit::singleline(&mut state.preset_file).hint_text("preset.json"));
+            if ui.button("Importa").clicked() {
+                state.import_preset();
+            }
+            if ui.button("Esporta").clicked() {
+                state.export_preset();
+            }
+        });
+        if let Some(status) = &state.preset_status {
+            ui.label(status);
+        }
+
+        ui.separator();
+
+        ui.label("Velocità Animazione");
+        ui.add(
+            egui::Slider::new(&mut state.fall_duration_secs, 0.5..=10.0)
+                .text("Durata Caduta (sec)"),
+        );
+        ui.label("(Valori più bassi = più veloce)");
+
+        ui.separator(); // Un separatore visivo
+
+        ui.label("Colori Note");
+        ui.horizontal(|ui| {
+            ui.label("Mano Sinistra:");
+            // Usiamo 'srgba' che accetta &mut Color32
+            egui::color_picker::color_edit_button_srgba(
+                ui,
+                &mut state.color_left_hand,
+                egui::color_picker::Alpha::Opaque,
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Mano Destra:");
+            egui::color_picker::color_edit_button_srgba(
+                ui,
+                &mut state.color_right_hand,
+                egui::color_picker::Alpha::Opaque,
+            );
+        });
+        let split_pitch = state.song.hands.split_pitch;
+        ui.label(format!(
+            "(Split su {} - Tasto {})",
+            layout::note_name(split_pitch),
+            split_pitch
+        ));
+        ui.horizontal(|ui| {
+            ui.label("Tasti:");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut state.key_range_text)
+                    .hint_text("es. Do2-Do7 (vuoto = attorno al Do3)"),
+            );
+            if response.lost_focus() {
+                state.set_key_range_text();
+            }
+        });
+        if let Some(error) = &state.key_range_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.separator();
+
+        ui.label("Audio");
+        let mut synth_enabled = state.synth_enabled;
+        if ui.checkbox(&mut synth_enabled, "Synth integrato").changed() {
+            state.set_synth_enabled(synth_enabled);
+        }
+        let mut volume = state.synth_volume;
+        if ui
+            .add(egui::Slider::new(&mut volume, 0.0..=1.0).text("Volume"))
+            .changed()
+        {
+            state.set_synth_volume(volume);
+        }
+        if let Some(error) = &state.audio.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        ui.horizontal(|ui| {
+            ui.label("SoundFont:");
+            ui.text_edit_singleline(&mut state.soundfont_path);
+            if ui.button("Carica").clicked() {
+                state.load_soundfont();
+            }
+        });
+        if let Some(error) = &state.soundfont_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        ui.horizontal(|ui| {
+            ui.label("Registrazione:");
+            ui.text_edit_singleline(&mut state.recording_path);
+            if ui.button("Carica").clicked() {
+                state.load_recording();
+            }
+        });
+        let mut offset = state.recording_offset_secs;
+        if ui
+            .add(egui::Slider::new(&mut offset, -10.0..=10.0).text("Offset Registrazione (sec)"))
+            .changed()
+        {
+            state.set_recording_offset(offset);
+        }
+        if let Some(error) = &state.recording_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        if ui.button("Esporta WAV").clicked() {
+            state.export_wav();
+        }
+        if let Some(status) = &state.export_status {
+            ui.label(status);
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Screenshot (F12)").clicked() {
+                state.request_screenshot();
+            }
+            egui::ComboBox::from_id_source("screenshot_scale")
+                .selected_text(format!("{}x", state.screenshot_scale))
+                .show_ui(ui, |ui| {
+                    for scale in SCREENSHOT_SCALES {
+                        ui.selectable_value(&mut state.screenshot_scale, scale, format!("{}x", scale));
+                    }
+                });
+            ui.checkbox(&mut state.screenshot_with_ui, "Con interfaccia");
+        });
+        if let Some(status) = &state.screenshot_status {
+            ui.label(status);
+        }
+
+        ui.separator();
+
+        ui.label("Ingresso MIDI");
+        ui.horizontal(|ui| {
+            let selected = state
+                .midi_input
+                .connected_port
+                .clone()
+                .unwrap_or_else(|| "Nessuno".to_string());
+            // Some(None) = "Nessuno", Some(Some(porta)) = connetti a quella porta
+            let mut chosen_port = None;
+            egui::ComboBox::from_id_source("midi_input_port")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    if ui.selectable_label(false, "Nessuno").clicked() {
+                        chosen_port = Some(None);
+                    }
+                    for port in &state.midi_input_ports {
+                        if ui.selectable_label(false, port).clicked() {
+                            chosen_port = Some(Some(port.clone()));
+                        }
+                    }
+                });
+            match chosen_port {
+                Some(Some(port)) => state.connect_midi_input(&port),
+                Some(None) => state.midi_input.disconnect(),
+                None => {}
+            }
+            if ui.button("Aggiorna").clicked() {
+                state.midi_input_ports = crate::midi_input::MidiInput::port_names();
+            }
+        });
+        if let Some(error) = &state.midi_input_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        let mut computer_keyboard = state.computer_keyboard.enabled;
+        if ui
+            .checkbox(&mut computer_keyboard, "Tastiera del computer e mouse")
+            .changed()
+        {
+            state.set_computer_keyboard_enabled(computer_keyboard);
+        }
+        if state.computer_keyboard.enabled {
+            ui.add(
+                egui::Slider::new(&mut state.computer_keyboard.velocity, 1..=127).text("Velocity"),
+            );
+            ui.label(format!(
+                "Z = {} (- / + per cambiare ottava)",
+                layout::note_name(state.computer_keyboard.base_pitch)
+            ));
+        }
+
+        ui.separator();
+
+        ui.label("Sincronizzazione Esterna (Clock MIDI / MTC)");
+        ui.horizontal(|ui| {
+            let selected = state
+                .midi_sync
+                .connected_port
+                .clone()
+                .unwrap_or_else(|| "Nessuna".to_string());
+            let mut chosen_port = None;
+            egui::ComboBox::from_id_source("midi_sync_port")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    if ui.selectable_label(false, "Nessuna").clicked() {
+                        chosen_port = Some(None);
+                    }
+                    for port in &state.midi_input_ports {
+                        if ui.selectable_label(false, port).clicked() {
+                            chosen_port = Some(Some(port.clone()));
+                        }
+                    }
+                });
+            match chosen_port {
+                Some(Some(port)) => state.connect_midi_sync(&port),
+                Some(None) => state.midi_sync.disconnect(),
+                None => {}
+            }
+        });
+        ui.add(
+            egui::DragValue::new(&mut state.midi_sync.mtc_offset_secs)
+                .speed(0.1)
+                .prefix("Offset MTC (sec): "),
+        );
+        if state.midi_sync.connected_port.is_some() {
+            let follower = &state.midi_sync.follower;
+            ui.label(match (follower.source, follower.mtc_rate) {
+                (Some(SyncSource::Clock), _) => {
+                    format!("Clock MIDI a {:.1} BPM", follower.clock_bpm())
+                }
+                (Some(SyncSource::Mtc), Some(fps)) => format!("MTC a {} fps", fps),
+                _ => "In attesa della sorgente...".to_string(),
+            });
+        }
+        if let Some(error) = &state.midi_sync_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.separator();
+
+        ui.label("Controllo OSC");
+        ui.horizontal(|ui| {
+            ui.add_enabled(
+                state.osc.is_none(),
+                egui::DragValue::new(&mut state.osc_port)
+                    .clamp_range(1024..=65535)
+                    .prefix("Porta: "),
+            );
+            if state.osc.is_some() {
+                if ui.button("Ferma").clicked() {
+                    state.osc = None;
+                }
+            } else if ui.button("Avvia").clicked() {
+                state.start_osc();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Invia a:");
+            ui.add_enabled(
+                state.osc.is_none(),
+                egui::TextEdit::singleline(&mut state.osc_target).hint_text("127.0.0.1:9001"),
+            );
+        });
+        if let Some(osc) = &state.osc {
+            ui.label(format!("In ascolto su 127.0.0.1:{}", osc.port));
+        }
+        if let Some(error) = &state.osc_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.separator();
+
+        ui.label("API HTTP / WebSocket");
+        ui.horizontal(|ui| {
+            ui.add_enabled(
+                state.http.is_none(),
+                egui::DragValue::new(&mut state.http_port)
+                    .clamp_range(1024..=65535)
+                    .prefix("Porta: "),
+            );
+            if state.http.is_some() {
+                if ui.button("Ferma").clicked() {
+                    state.http = None;
+                }
+            } else if ui.button("Avvia").clicked() {
+                state.start_http();
+            }
+        });
+        if let Some(http) = &state.http {
+            ui.label(format!("http://{}/api/state", http.address));
+        }
+        if let Some(error) = &state.http_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.separator();
+
+        ui.label("Uscita MIDI");
+        ui.horizontal(|ui| {
+            let selected = state
+                .midi_output
+                .connected_port
+                .clone()
+                .unwrap_or_else(|| "Nessuno".to_string());
+            let mut chosen_port = None;
+            egui::ComboBox::from_id_source("midi_output_port")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    if ui.selectable_label(false, "Nessuno").clicked() {
+                        chosen_port = Some(None);
+                    }
+                    for port in &state.midi_output_ports {
+                        if ui.selectable_label(false, port).clicked() {
+                            chosen_port = Some(Some(port.clone()));
+                        }
+                    }
+                });
+            match chosen_port {
+                Some(Some(port)) => state.connect_midi_output(&port),
+                Some(None) => state.midi_output.disconnect(),
+                None => {}
+            }
+            if ui.button("Aggiorna").clicked() {
+                state.midi_output_ports = crate::midi_output::MidiOutput::port_names();
+            }
+        });
+        if let Some(error) = &state.midi_output_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        // Cosa suonano synth e uscita MIDI: il resto lo suona lo studente
+        let mut filter_changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Muto:");
+            filter_changed |= ui
+                .checkbox(&mut state.playback_filter.mute_left_hand, "Sinistra")
+                .changed();
+            filter_changed |= ui
+                .checkbox(&mut state.playback_filter.mute_right_hand, "Destra")
+                .changed();
+        });
+        let track_count = state.song.track_names.len();
+        if track_count > 1 {
+            ui.collapsing("Tracce", |ui| {
+                for track in 0..track_count {
+                    if !state.song.notes.iter().any(|n| n.track == track) {
+                        continue;
+                    }
+                    let name = match state.song.track_names[track].as_str() {
+                        "" => format!("Traccia {}", track + 1),
+                        name => name.to_string(),
+                    };
+                    let mut audible = !state.playback_filter.muted_tracks.contains(&track);
+                    if ui.checkbox(&mut audible, name).changed() {
+                        if audible {
+                            state.playback_filter.muted_tracks.remove(&track);
+                        } else {
+                            state.playback_filter.muted_tracks.insert(track);
+                        }
+                        filter_changed = true;
+                    }
+                }
+            });
+        }
+        if filter_changed {
+            state.all_notes_off();
+        }
+
+        let mut lights_enabled = state.key_lights.enabled;
+        if ui
+            .checkbox(&mut lights_enabled, "Guida luminosa sui tasti")
+            .changed()
+        {
+            state.set_key_lights_enabled(lights_enabled);
+        }
+        if state.key_lights.enabled {
+            let mut lights_changed = ui
+                .add(
+                    egui::Slider::new(&mut state.key_lights.look_ahead_secs, 0.0..=2.0)
+                        .text("Anticipo Luci (sec)"),
+                )
+                .changed();
+            for (label, light) in [
+                ("Sinistra", &mut state.key_lights.left),
+                ("Destra", &mut state.key_lights.right),
+            ] {
+                ui.horizontal(|ui| {
+                    lights_changed |= ui.checkbox(&mut light.enabled, label).changed();
+                    // Canali mostrati 1-16 come sugli strumenti
+                    let mut channel = light.channel + 1;
+                    ui.label("Canale");
+                    if ui
+                        .add(egui::DragValue::new(&mut channel).clamp_range(1..=16))
+                        .changed()
+                    {
+                        light.channel = channel - 1;
+                        lights_changed = true;
+                    }
+                    ui.label("Colore (velocity)");
+                    lights_changed |= ui
+                        .add(egui::DragValue::new(&mut light.color_velocity).clamp_range(1..=127))
+                        .changed();
+                });
+            }
+            if lights_changed {
+                state.rebuild_key_lights();
+            }
+        }
+
+        ui.separator();
+
+        ui.label("Modalità Attesa");
+        if ui
+            .checkbox(&mut state.wait_mode.enabled, "Aspetta i tasti giusti")
+            .changed()
+        {
+            let position = state.transport.position_secs();
+            state.wait_mode.resync(position, &state.song.notes);
+        }
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut state.wait_mode.hands, WaitHands::Both, "Entrambe");
+            ui.radio_value(&mut state.wait_mode.hands, WaitHands::Left, "Sinistra");
+            ui.radio_value(&mut state.wait_mode.hands, WaitHands::Right, "Destra");
+        });
+        ui.add(
+            egui::Slider::new(&mut state.wait_mode.chord_tolerance_secs, 0.0..=0.5)
+                .text("Tolleranza Accordi (sec)"),
+        );
+        if state.wait_mode.is_waiting() {
+            ui.label("In attesa dei tasti...");
+        }
+
+        ui.separator();
+
+        ui.label("Valutazione");
+        if ui
+            .checkbox(&mut state.scorer.enabled, "Valuta l'esecuzione")
+            .changed()
+        {
+            state.scorer.reset(state.transport.position_secs(), &state.song.notes);
+        }
+        let windows = &mut state.scorer.windows;
+        ui.add(egui::Slider::new(&mut windows.hit_secs, 0.01..=0.2).text("Finestra Giusta (sec)"));
+        ui.add(egui::Slider::new(&mut windows.early_secs, 0.05..=0.5).text("Anticipo Max (sec)"));
+        ui.add(egui::Slider::new(&mut windows.late_secs, 0.05..=0.5).text("Ritardo Max (sec)"));
+        if state.scorer.enabled {
+            let summary = state.scorer.summary();
+            ui.label(format!("Precisione: {:.1}%", summary.accuracy_percent()));
+            if let Some(grade) = state.last_grade {
+                ui.label(format!("Ultima nota: {}", grade_label(grade)));
+            }
+        }
+
+        ui.separator();
+
+        ui.collapsing("Scorciatoie da tastiera", |ui| {
+            draw_shortcuts(ui, state);
+        });
+
+        ui.separator();
+
+        // Le impostazioni si salvano da sole a ogni cambiamento
+        if ui.button("Ripristina predefiniti").clicked() {
+            state.apply_settings(&Settings::default());
+        }
+        if let Some(error) = &state.settings_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    });
+
+    // Riepilogo a fine brano, calcolato da State::update sull'esecuzione registrata
+    if state.scorer.enabled
+        && let Some(summary) = state.scorer.final_summary()
+    {
+        egui::Window::new("Riepilogo").show(ctx, |ui| {
+            ui.heading(format!("Precisione: {:.1}%", summary.accuracy_percent()));
+            ui.label(format!("Giuste: {}", summary.hit));
+            ui.label(format!("In anticipo: {}", summary.early));
+            ui.label(format!("In ritardo: {}", summary.late));
+            ui.label(format!("Sbagliate: {}", summary.wrong));
+            ui.label(format!("Mancate: {}", summary.missed));
+            if ui.button("Ricomincia").clicked() {
+                state.seek(0.0);
+            }
+        });
+    }
+}
+
+// Un pulsante per azione: cliccandolo, il prossimo tasto premuto diventa
+// la sua scorciatoia (Esc annulla)
+fn draw_shortcuts(ui: &mut egui::Ui, state: &mut State) {
+    egui::Grid::new("scorciatoie").striped(true).show(ui, |ui| {
+        for action in Action::ALL {
+            ui.label(action.label());
+            let binding = state.shortcuts.binding(action);
+            let text = if state.shortcuts.capturing == Some(action) {
+                "Premi un tasto…".to_string()
+            } else {
+                binding.map_or("—".to_string(), |b| b.to_string())
+            };
+            if ui
+                .button(text)
+                .on_hover_text("Clic e poi il nuovo tasto (Esc annulla)")
+                .clicked()
+            {
+                state.shortcuts.capturing = Some(action);
+                state.shortcuts_status = None;
+            }
+            if ui
+                .add_enabled(binding.is_some(), egui::Button::new("✖").small())
+                .on_hover_text("Nessun tasto")
+                .clicked()
+            {
+                state.shortcuts.unbind(action);
+            }
+            ui.end_row();
+        }
+    });
+    if ui.button("Tasti predefiniti").clicked() {
+        state.shortcuts = Shortcuts::new();
+        state.shortcuts_status = None;
+    }
+    if let Some(status) = &state.shortcuts_status {
+        ui.label(status);
+    }
+}
+
+// Tasti del computer come note, se la modalità è attiva e nessun campo di testo ha il focus
+fn handle_computer_keyboard(ctx: &egui::Context, state: &mut State) {
+    if !state.computer_keyboard.enabled || ctx.wants_keyboard_input() {
+        return;
+    }
+    // Con Ctrl, Alt o Cmd i tasti sono scorciatoie, non note: quelle tenute
+    // si rilasciano appena il modificatore scende
+    let is_chord =
+        |modifiers: egui::Modifiers| modifiers.command || modifiers.ctrl || modifiers.alt;
+    if is_chord(ctx.input(|i| i.modifiers)) {
+        for note in state.computer_keyboard.release_keys() {
+            state.play_virtual_note(note);
+        }
+    }
+    let events = ctx.input(|i| i.events.clone());
+    for event in events {
+        match event {
+            egui::Event::Key {
+                key,
+                pressed,
+                repeat,
+                modifiers,
+            } => {
+                if pressed && is_chord(modifiers) {
+                    continue;
+                }
+                if let Some(note) = state.computer_keyboard.on_key(key, pressed, repeat) {
+                    state.play_virtual_note(note);
+                }
+            }
+            // Senza focus i rilasci non arrivano più: meglio non lasciare note appese
+            egui::Event::WindowFocused(false) => {
+                for note in state.computer_keyboard.release_all() {
+                    state.play_virtual_note(note);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Barra in alto con il menu "File" e il brano aperto
+fn draw_menu_bar(ctx: &egui::Context, state: &mut State) {
+    egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+        egui::menu::bar(ui, |ui| {
+            ui.menu_button("File", |ui| {
+                if ui.button("Apri…").clicked() {
+                    let directory = state.song_path.as_deref().and_then(|p| p.parent());
+                    state.file_browser.open_in(directory);
+                    ui.close_menu();
+                }
+                ui.menu_button("Recenti", |ui| {
+                    if state.recent.paths().is_empty() {
+                        ui.label("Nessun file aperto di recente");
+                    }
+                    let mut chosen = None;
+                    for path in state.recent.paths() {
+                        let name = path.file_name().unwrap_or_default().to_string_lossy();
+                        if ui.button(name).on_hover_text(path.display().to_string()).clicked() {
+                            chosen = Some(path.clone());
+                        }
+                    }
+                    if let Some(path) = chosen {
+                        state.open_file(&path);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("Svuota elenco").clicked() {
+                        state.clear_recent();
+                        ui.close_menu();
+                    }
+                    if let Some(error) = &state.recent_error {
+                        ui.colored_label(egui::Color32::RED, error);
+                    }
+                });
+                let reload = ui.add_enabled(state.song_path.is_some(), egui::Button::new("Ricarica"));
+                if reload.clicked()
+                    && let Some(path) = state.song_path.clone()
+                {
+                    state.open_file(&path);
+                    ui.close_menu();
+                }
+                ui.separator();
+                ui.label("Sessione (.pvs)");
+                ui.text_edit_singleline(&mut state.session_path);
+                ui.checkbox(&mut state.session_embed_midi, "Includi il file MIDI");
+                if ui.button("Salva sessione").clicked() {
+                    state.save_session();
+                }
+                if let Some(status) = &state.session_status {
+                    ui.label(status);
+                }
+            });
+            ui.toggle_value(&mut state.show_lesson, "Lezione");
+            ui.toggle_value(&mut state.show_playlist, "Scaletta");
+            match &state.song_path {
+                Some(path) => ui.label(path.file_name().unwrap_or_default().to_string_lossy()),
+                None => ui.label("Brano dimostrativo"),
+            };
+            if let Some(error) = &state.song_error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+    });
+}
+
+// Finestra "Lezione": divisione fra le mani, loop salvati, segnaposti e
+// note per lo studente, tutto salvato nelle sessioni
+fn draw_lesson(ctx: &egui::Context, state: &mut State) {
+    let mut open = true;
+    egui::Window::new("Lezione")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            let position = state.transport.position_secs();
+
+            ui.label("Mani");
+            let mut hands = state.song.hands.clone();
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut hands.split_pitch).clamp_range(0..=127).prefix("Split: "));
+                ui.label(layout::note_name(hands.split_pitch));
+            });
+            let track_count = state.song.track_names.len();
+            if track_count > 1 {
+                ui.collapsing("Tracce", |ui| {
+                    for track in 0..track_count {
+                        if !state.song.notes.iter().any(|n| n.track == track) {
+                            continue;
+                        }
+                        let name = match state.song.track_names[track].as_str() {
+                            "" => format!("Traccia {}", track + 1),
+                            name => name.to_string(),
+                        };
+                        let mut hand = hands.tracks.get(&track).copied();
+                        egui::ComboBox::from_label(name)
+                            .selected_text(hand_label(hand))
+                            .show_ui(ui, |ui| {
+                                for choice in [None, Some(Hand::Left), Some(Hand::Right)] {
+                                    ui.selectable_value(&mut hand, choice, hand_label(choice));
+                                }
+                            });
+                        match hand {
+                            Some(hand) => hands.tracks.insert(track, hand),
+                            None => hands.tracks.remove(&track),
+                        };
+                    }
+                });
+            }
+            if hands != state.song.hands {
+                state.set_hand_assignment(hands);
+            }
+
+            ui.separator();
+
+            ui.label("Loop salvati");
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut state.lesson_loop_name).hint_text("Nome"));
+                let current_loop = state.transport.loop_region;
+                if ui
+                    .add_enabled(current_loop.is_some(), egui::Button::new("Salva loop A-B"))
+                    .clicked()
+                    && let Some(region) = current_loop
+                {
+                    state.lesson.add_loop(region, &state.lesson_loop_name);
+                    state.lesson_loop_name.clear();
+                }
+            });
+            let mut removed = None;
+            for (index, saved) in state.lesson.loops.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui.button("Usa").clicked() {
+                        state.transport.loop_region = Some((saved.start_secs, saved.end_secs));
+                    }
+                    ui.label(format!(
+                        "{} ({:.1} - {:.1} s)",
+                        saved.name, saved.start_secs, saved.end_secs
+                    ));
+                    if ui.small_button("✖").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                state.lesson.loops.remove(index);
+            }
+
+            ui.separator();
+
+            ui.label("Segnaposti");
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut state.lesson_marker_name).hint_text("Nome"));
+                if ui.button(format!("Aggiungi a {:.1} s", position)).clicked() {
+                    state.lesson.add_marker(position, &state.lesson_marker_name);
+                    state.lesson_marker_name.clear();
+                }
+            });
+            let mut jump = None;
+            let mut removed = None;
+            for (index, marker) in state.lesson.markers.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(format!("▶ {} ({:.1} s)", marker.name, marker.time_secs))
+                        .clicked()
+                    {
+                        jump = Some(marker.time_secs);
+                    }
+                    if ui.small_button("✖").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(time_secs) = jump {
+                state.seek(time_secs);
+            }
+            if let Some(index) = removed {
+                state.lesson.markers.remove(index);
+            }
+
+            ui.separator();
+
+            ui.label("Note per lo studente");
+            ui.add(
+                egui::TextEdit::multiline(&mut state.lesson_note_text)
+                    .desired_rows(2)
+                    .hint_text("Mostrata sopra le note da questo punto"),
+            );
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut state.lesson_note_secs)
+                        .clamp_range(0.5..=60.0)
+                        .speed(0.1)
+                        .prefix("Durata (sec): "),
+                );
+                let has_text = !state.lesson_note_text.trim().is_empty();
+                if ui
+                    .add_enabled(has_text, egui::Button::new(format!("Aggiungi a {:.1} s", position)))
+                    .clicked()
+                {
+                    state
+                        .lesson
+                        .add_annotation(position, state.lesson_note_secs, &state.lesson_note_text);
+                    state.lesson_note_text.clear();
+                }
+            });
+            let mut removed = None;
+            for (index, annotation) in state.lesson.annotations.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{:.1} - {:.1} s: {}",
+                        annotation.start_secs, annotation.end_secs, annotation.text
+                    ));
+                    if ui.small_button("✖").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                state.lesson.annotations.remove(index);
+            }
+        });
+    state.show_lesson &= open;
+}
+
+// Finestra "Scaletta": brani in coda, ordine, avanzamento automatico
+fn draw_playlist(ctx: &egui::Context, state: &mut State) {
+    let mut open = true;
+    egui::Window::new("Scaletta")
+        .open(&mut open)
+        .default_width(360.0)
+        .show(ctx, |ui| {
+            let has_entries = !state.playlist.entries.is_empty();
+            ui.horizontal(|ui| {
+                let has_previous = state.playlist.previous().is_some();
+                if ui.add_enabled(has_previous, egui::Button::new("⏮")).clicked() {
+                    state.previous_playlist_entry();
+                }
+                if ui
+                    .add_enabled(has_entries, egui::Button::new("▶ Dall'inizio"))
+                    .clicked()
+                {
+                    state.start_playlist();
+                }
+                if ui.add_enabled(has_entries, egui::Button::new("⏭")).clicked() {
+                    state.advance_playlist(false);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut state.playlist.auto_advance, "Avanti da solo");
+                ui.checkbox(&mut state.playlist.shuffle, "Casuale");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Ripeti:");
+                ui.radio_value(&mut state.playlist.repeat, Repeat::Off, "No");
+                ui.radio_value(&mut state.playlist.repeat, Repeat::All, "Scaletta");
+                ui.radio_value(&mut state.playlist.repeat, Repeat::One, "Brano");
+            });
+            ui.add(
+                egui::Slider::new(&mut state.playlist.gap_secs, 0.0..=30.0)
+                    .text("Pausa fra i brani (sec)"),
+            );
+
+            ui.separator();
+
+            // Spostamenti e rimozioni si applicano dopo aver disegnato la lista
+            let mut play = None;
+            let mut moved = None;
+            let mut removed = None;
+            let count = state.playlist.entries.len();
+            egui::ScrollArea::vertical()
+                .max_height(240.0)
+                .auto_shrink([false, true])
+                .show(ui, |ui| {
+                    if !has_entries {
+                        ui.label("Scaletta vuota");
+                    }
+                    for (index, entry) in state.playlist.entries.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(index > 0, egui::Button::new("⬆").small()).clicked() {
+                                moved = Some((index, index - 1));
+                            }
+                            if ui
+                                .add_enabled(index + 1 < count, egui::Button::new("⬇").small())
+                                .clicked()
+                            {
+                                moved = Some((index, index + 1));
+                            }
+                            if ui.small_button("✖").clicked() {
+                                removed = Some(index);
+                            }
+                            let current = state.playlist.current == Some(index);
+                            let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+                            let label = format!("{}. {}", index + 1, name);
+                            if ui
+                                .selectable_label(current, label)
+                                .on_hover_text(entry.path.display().to_string())
+                                .double_clicked()
+                            {
+                                play = Some(index);
+                            }
+                        });
+                    }
+                });
+            if let Some((from, to)) = moved {
+                state.playlist.move_entry(from, to);
+            }
+            if let Some(index) = removed {
+                state.playlist.remove(index);
+            }
+            if let Some(index) = play {
+                state.play_playlist_entry(index);
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                let add_current = ui.add_enabled(
+                    state.song_path.is_some(),
+                    egui::Button::new("Aggiungi brano aperto"),
+                );
+                if add_current.clicked()
+                    && let Some(path) = state.song_path.clone()
+                {
+                    state.playlist.add(path);
+                }
+                if ui.add_enabled(has_entries, egui::Button::new("Svuota")).clicked() {
+                    state.playlist.clear();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut state.playlist_file)
+                        .hint_text("brano, cartella o scaletta .m3u"),
+                );
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Aggiungi").clicked() {
+                    state.add_to_playlist();
+                }
+                if ui.button("Carica").clicked() {
+                    state.load_playlist();
+                }
+                if ui.button("Salva").clicked() {
+                    state.save_playlist();
+                }
+            });
+            if let Some(status) = &state.playlist_status {
+                ui.label(status);
+            }
+        });
+    state.show_playlist &= open;
+}
+
+fn hand_label(hand: Option<Hand>) -> &'static str {
+    match hand {
+        None => "Split",
+        Some(Hand::Left) => "Sinistra",
+        Some(Hand::Right) => "Destra",
+    }
+}
+
+// Le note per lo studente attive in questo punto del brano, in alto al centro
+fn draw_annotations(ctx: &egui::Context, state: &State) {
+    let position = state.transport.position_secs();
+    let mut annotations = state.lesson.annotations_at(position).peekable();
+    if annotations.peek().is_none() {
+        return;
+    }
+    egui::Area::new("note_studente")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                for annotation in annotations {
+                    ui.label(egui::RichText::new(&annotation.text).size(18.0));
+                }
+            });
+        });
+}
+
+// Tastiera in fondo allo schermo: i tasti li disegna la scena, qui ci sono
+// solo il mouse e le etichette dei tasti del computer
+fn draw_keyboard(ctx: &egui::Context, state: &mut State) {
+    let pixels_per_point = ctx.pixels_per_point();
+    let layout = Layout::new(state.size.width as f32, state.key_range);
+    let screen_rect = ctx.screen_rect();
+    let height = KEYBOARD_HEIGHT / pixels_per_point;
+    let keyboard_rect = egui::Rect::from_min_max(
+        egui::pos2(screen_rect.left(), screen_rect.bottom() - height),
+        screen_rect.right_bottom(),
+    );
+    if !state.computer_keyboard.enabled {
+        return;
+    }
+
+    egui::Area::new("tastiera")
+        .order(egui::Order::Background)
+        .fixed_pos(keyboard_rect.min)
+        .show(ctx, |ui| {
+            let response = ui.allocate_rect(keyboard_rect, egui::Sense::click_and_drag());
+
+            // Mouse: il tasto sotto il puntatore suona finché il pulsante resta premuto
+            let pressed_pitch = response
+                .is_pointer_button_down_on()
+                .then(|| response.interact_pointer_pos())
+                .flatten()
+                .and_then(|pos| layout.pitch_at_x(pos.x * pixels_per_point));
+            for note in state.computer_keyboard.on_mouse(pressed_pitch) {
+                state.play_virtual_note(note);
+            }
+
+            let painter = ui.painter();
+            for pitch in layout.visible_pitches() {
+                let Some(label) = state.computer_keyboard.key_label(pitch) else {
+                    continue;
+                };
+                let x = layout.note_x(pitch) / pixels_per_point;
+                let key_rect = egui::Rect::from_min_size(
+                    egui::pos2(x, keyboard_rect.top()),
+                    egui::vec2(layout.key_width() / pixels_per_point, height),
+                );
+                let text_color = if layout::is_black_key(pitch) {
+                    egui::Color32::WHITE
+                } else {
+                    egui::Color32::BLACK
+                };
+                painter.text(
+                    key_rect.center_bottom() - egui::vec2(0.0, 4.0),
+                    egui::Align2::CENTER_BOTTOM,
+                    label,
+                    egui::FontId::monospace(10.0),
+                    text_color,
+                );
+            }
+        });
+}
+
+fn grade_label(grade: Grade) -> &'static str {
+    match grade {
+        Grade::Hit => "Giusta",
+        Grade::Early => "In anticipo",
+        Grade::Late => "In ritardo",
+        Grade::Wrong => "Sbagliata",
+        Grade::Missed => "Mancata",
+    }
+}
@@ -0,0 +1,267 @@
+// src/video_export.rs
+use crate::audio_output::{self, TAIL_SECS, WAV_SAMPLE_RATE};
+use crate::config::{COLOR_LEFT_HAND, COLOR_RIGHT_HAND, FALL_DURATION_SECS};
+use crate::layout::KeyRange;
+use crate::midi_loader::Song;
+use crate::png_writer::write_png;
+use crate::renderer::{self, OffscreenTarget, SceneRenderer};
+use crate::scene::{self, Scene};
+use crate::soundfont::{self, SoundFontSynth};
+use crate::synth::{AdditiveSynth, Instrument, SynthEvent};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+pub struct VideoSettings {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub fall_duration_secs: f32,
+    pub color_left: [f32; 3],
+    pub color_right: [f32; 3],
+    pub key_range: Option<KeyRange>,
+    // SoundFont per l'audio (None = synth integrato)
+    pub soundfont: Option<PathBuf>,
+    pub software_rendering: bool,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            fps: 60,
+            fall_duration_secs: FALL_DURATION_SECS,
+            color_left: scene::color_to_f32(COLOR_LEFT_HAND),
+            color_right: scene::color_to_f32(COLOR_RIGHT_HAND),
+            key_range: None,
+            soundfont: None,
+            software_rendering: false,
+        }
+    }
+}
+
+// Quanto si aspetta che ffmpeg esca da solo prima di fermarlo
+const FFMPEG_EXIT_WAIT: Duration = Duration::from_secs(1);
+
+// File cancellato quando esce di scena
+struct TemporaryFile(PathBuf);
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// Dove finiscono i fotogrammi
+enum FrameSink {
+    // Video compresso da ffmpeg, che riceve i fotogrammi grezzi su stdin
+    Ffmpeg(Child),
+    // Un PNG per fotogramma: cartella e prefisso del nome
+    Png { directory: PathBuf, stem: String },
+}
+
+impl FrameSink {
+    fn write_frame(
+        &mut self,
+        index: u32,
+        settings: &VideoSettings,
+        rgba: &[u8],
+    ) -> Result<(), String> {
+        match self {
+            FrameSink::Ffmpeg(child) => {
+                let written = child
+                    .stdin
+                    .as_mut()
+                    .ok_or("ffmpeg non accetta dati")?
+                    .write_all(rgba);
+                written.map_err(|e| format!("ffmpeg si è interrotto ({}): {}", stop(child), e))
+            }
+            FrameSink::Png { directory, stem } => write_png(
+                &directory.join(format!("{}_{:06}.png", stem, index)),
+                settings.width,
+                settings.height,
+                rgba,
+            ),
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            FrameSink::Ffmpeg(mut child) => {
+                // Chiudere stdin dice a ffmpeg che i fotogrammi sono finiti
+                drop(child.stdin.take());
+                let status = child.wait().map_err(|e| e.to_string())?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(format!("ffmpeg è terminato con errore ({})", status))
+                }
+            }
+            FrameSink::Png { .. } => Ok(()),
+        }
+    }
+}
+
+// Ferma ffmpeg (di solito è già uscito da solo, con il motivo scritto sul
+// terminale) e ne raccoglie lo stato, senza lasciare il processo appeso
+fn stop(child: &mut Child) -> String {
+    drop(child.stdin.take());
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) if started.elapsed() < FFMPEG_EXIT_WAIT => {
+                std::thread::sleep(Duration::from_millis(10))
+            }
+            _ => {
+                let _ = child.kill();
+                break child.wait();
+            }
+        }
+    };
+    match status {
+        Ok(status) => status.to_string(),
+        Err(e) => format!("stato sconosciuto: {}", e),
+    }
+}
+
+fn spawn_ffmpeg(output: &Path, audio: &Path, settings: &VideoSettings) -> Result<Child, String> {
+    Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+        .args(["-s", &format!("{}x{}", settings.width, settings.height)])
+        .args(["-r", &settings.fps.to_string()])
+        .args(["-i", "-"])
+        .arg("-i")
+        .arg(audio)
+        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        .args(["-c:a", "aac", "-shortest"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            format!(
+                "Impossibile avviare ffmpeg ({}). Installarlo oppure esportare una sequenza PNG (uscita .png)",
+                e
+            )
+        })
+}
+
+fn make_instrument(settings: &VideoSettings) -> Result<Box<dyn Instrument>, String> {
+    let mut instrument: Box<dyn Instrument> = match &settings.soundfont {
+        Some(path) => {
+            let soundfont = soundfont::load_soundfont(path)?;
+            Box::new(SoundFontSynth::new(&soundfont, WAV_SAMPLE_RATE)?)
+        }
+        None => Box::new(AdditiveSynth::new(WAV_SAMPLE_RATE)),
+    };
+    instrument.handle_event(SynthEvent::SetVolume(0.8));
+    Ok(instrument)
+}
+
+// Renderizza l'animazione senza finestra con un orologio deterministico
+// (fotogramma / fps): il risultato non dipende dalla velocità della macchina.
+// Se `output` finisce in .png si scrive una sequenza di immagini con il WAV
+// accanto, altrimenti ffmpeg produce il video con l'audio già unito.
+pub fn export_video(song: &Song, output: &Path, settings: &VideoSettings) -> Result<(), String> {
+    let (device, queue) =
+        pollster::block_on(renderer::headless_device(settings.software_rendering))?;
+    let (width, height) = (settings.width, settings.height);
+
+    let target = OffscreenTarget::new(&device, width, height, renderer::OFFSCREEN_FORMAT)?;
+    let mut scene_renderer = SceneRenderer::new(&device, renderer::OFFSCREEN_FORMAT, width, height);
+
+    // L'audio si renderizza prima: ffmpeg lo legge mentre arrivano i fotogrammi
+    let is_png = output
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    let wav_path = if is_png {
+        output.with_extension("wav")
+    } else {
+        std::env::temp_dir().join(format!("piano_visualizer_{}.wav", std::process::id()))
+    };
+    // Il WAV temporaneo sparisce comunque finisca l'esportazione
+    let _temporary_wav = (!is_png).then(|| TemporaryFile(wav_path.clone()));
+    let mut instrument = make_instrument(settings)?;
+    audio_output::render_song_to_wav(song, &wav_path, instrument.as_mut())?;
+
+    let mut sink = if is_png {
+        FrameSink::Png {
+            directory: output
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+            stem: output
+                .file_stem()
+                .map_or("frame".into(), |s| s.to_string_lossy().into_owned()),
+        }
+    } else {
+        FrameSink::Ffmpeg(spawn_ffmpeg(output, &wav_path, settings)?)
+    };
+
+    let duration_secs = song.end_secs() + TAIL_SECS;
+    let total_frames = (duration_secs * settings.fps as f32).ceil() as u32;
+    println!(
+        "[INFO] Esportazione video: {} fotogrammi {}x{} a {} fps",
+        total_frames, width, height, settings.fps
+    );
+    let started = Instant::now();
+
+    let result = (0..total_frames).try_for_each(|index| {
+        let scene = Scene {
+            song,
+            time_secs: index as f32 / settings.fps as f32,
+            width: width as f32,
+            height: height as f32,
+            fall_duration_secs: settings.fall_duration_secs,
+            color_left: settings.color_left,
+            color_right: settings.color_right,
+            key_range: settings.key_range,
+        };
+        let frame = scene_renderer.render_rgba(&device, &queue, &target, &scene.vertices())?;
+        sink.write_frame(index, settings, &frame)?;
+        if index % (settings.fps * 10) == 0 {
+            println!("[INFO] {:.0}%", index as f32 * 100.0 / total_frames as f32);
+        }
+        Ok(())
+    });
+    result.and_then(|()| sink.finish())?;
+
+    let elapsed = started.elapsed().as_secs_f32();
+    println!(
+        "[INFO] Video salvato in '{}' ({:.1} s, {:.1}x il tempo reale)",
+        output.display(),
+        elapsed,
+        duration_secs / elapsed.max(1e-3)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoder_that_exits_early_is_reported_with_its_status() {
+        // Al posto di ffmpeg, un processo che esce subito senza leggere
+        let child = Command::new("sh")
+            .args(["-c", "exit 3"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut sink = FrameSink::Ffmpeg(child);
+        let settings = VideoSettings::default();
+        let frame = vec![0u8; 1 << 20];
+        let error = (0..16)
+            .find_map(|index| sink.write_frame(index, &settings, &frame).err())
+            .expect("scrittura riuscita su un processo terminato");
+        assert!(error.contains("exit status: 3"), "{}", error);
+        let FrameSink::Ffmpeg(mut child) = sink else {
+            unreachable!()
+        };
+        // Già raccolto: non resta nessun processo da aspettare
+        assert!(child.try_wait().unwrap().is_some());
+    }
+}
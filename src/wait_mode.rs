@@ -0,0 +1,117 @@
+// src/wait_mode.rs
+use crate::midi_input::NoteEvent;
+use crate::midi_loader::{Hand, MidiNote};
+use std::collections::HashMap;
+
+// Quali mani deve suonare lo studente perché la riproduzione prosegua
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WaitHands {
+    Both,
+    Left,
+    Right,
+}
+
+impl WaitHands {
+    pub fn requires(self, hand: Hand) -> bool {
+        match self {
+            WaitHands::Both => true,
+            WaitHands::Left => hand == Hand::Left,
+            WaitHands::Right => hand == Hand::Right,
+        }
+    }
+}
+
+// Modalità di apprendimento "alla Synthesia": quando una nota arriva alla linea
+// del presente il trasporto si ferma finché lo studente non preme i tasti giusti.
+pub struct WaitMode {
+    pub enabled: bool,
+    pub hands: WaitHands,
+    // Note che iniziano entro questa finestra vengono trattate come un unico accordo;
+    // la stessa finestra vale per i tasti premuti leggermente in anticipo.
+    pub chord_tolerance_secs: f32,
+
+    // Indice (in `song.notes`, ordinate per inizio) della prima nota non ancora risolta
+    next_note: usize,
+    // Ultima pressione di ogni tasto, espressa come posizione nel brano
+    presses: HashMap<u8, f32>,
+    waiting: bool,
+}
+
+impl WaitMode {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            hands: WaitHands::Both,
+            chord_tolerance_secs: 0.15,
+            next_note: 0,
+            presses: HashMap::new(),
+            waiting: false,
+        }
+    }
+
+    // true se il trasporto è fermo in attesa dello studente
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    pub fn on_note_event(&mut self, event: NoteEvent, position_secs: f32) {
+        if let NoteEvent::On { pitch, .. } = event {
+            self.presses.insert(pitch, position_secs);
+        }
+    }
+
+    // Da chiamare dopo ogni salto del trasporto (seek, riavvio, attivazione):
+    // le note prima della nuova posizione non vengono più richieste.
+    pub fn resync(&mut self, position_secs: f32, notes: &[MidiNote]) {
+        self.next_note = notes.partition_point(|n| n.start_time_secs < position_secs);
+        self.presses.clear();
+        self.waiting = false;
+    }
+
+    // Restituisce `Some(t)` se il trasporto deve restare fermo alla posizione `t`
+    pub fn update(&mut self, position_secs: f32, notes: &[MidiNote]) -> Option<f32> {
+        self.waiting = false;
+        if !self.enabled {
+            return None;
+        }
+
+        loop {
+            // Le note della mano non richiesta scorrono liberamente
+            while self.next_note < notes.len() && !self.hands.requires(notes[self.next_note].hand)
+            {
+                self.next_note += 1;
+            }
+            let group_start = notes.get(self.next_note)?.start_time_secs;
+            if position_secs < group_start {
+                return None;
+            }
+
+            let tolerance = self.chord_tolerance_secs;
+            let group_len = notes[self.next_note..]
+                .iter()
+                .take_while(|n| n.start_time_secs <= group_start + tolerance)
+                .count();
+            let group = &notes[self.next_note..self.next_note + group_len];
+
+            let satisfied = group
+                .iter()
+                .filter(|n| self.hands.requires(n.hand))
+                .all(|n| {
+                    self.presses
+                        .get(&n.pitch)
+                        .is_some_and(|&pressed_at| pressed_at >= group_start - tolerance)
+                });
+            if !satisfied {
+                self.waiting = true;
+                return Some(group_start);
+            }
+
+            // Accordo risolto: le pressioni usate non valgono per il prossimo
+            for note in group {
+                self.presses.remove(&note.pitch);
+            }
+            self.next_note += group_len;
+        }
+    }
+}